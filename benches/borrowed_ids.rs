@@ -0,0 +1,45 @@
+//! Compares fusing lists with long string IDs by cloning each ID into the
+//! fused list, with `fuse_scored`, against borrowing them from the input,
+//! with `fuse_scored_ref`.
+//!
+//! Run with `cargo bench --bench borrowed_ids`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use vindicator::fuser::{comb_sum, fuse_scored, fuse_scored_ref};
+use vindicator::{score, EntryInfo};
+
+const LIST_LEN: u32 = 100_000;
+const NUM_LISTS: u32 = 4;
+const ITERATIONS: u32 = 20;
+
+fn time<F: FnMut() -> usize>(mut f: F) -> Duration {
+    let start = Instant::now();
+    let mut total = 0;
+    for _ in 0..ITERATIONS {
+        total += black_box(f());
+    }
+    assert!(total > 0);
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    // URL-like IDs, partly shared between lists
+    let entries: Vec<EntryInfo<String>> = (0..NUM_LISTS)
+        .flat_map(|l| {
+            (0..LIST_LEN).map(move |i| EntryInfo {
+                id: format!("https://example.com/collection/documents/{:08}", i * (l + 1)),
+                score: score(1. / (i + 1) as f32),
+            })
+        })
+        .collect();
+
+    let cloned = time(|| fuse_scored(&entries, comb_sum).len());
+    let borrowed = time(|| fuse_scored_ref(&entries, comb_sum).len());
+
+    println!("fuse_scored:     {:>10.3?} per iteration", cloned);
+    println!("fuse_scored_ref: {:>10.3?} per iteration", borrowed);
+    println!(
+        "speedup:         {:>10.2}x",
+        cloned.as_secs_f64() / borrowed.as_secs_f64()
+    );
+}
@@ -0,0 +1,52 @@
+//! Compares reloading a large run from a binary cache against parsing its
+//! text again.
+//!
+//! Run with `cargo bench --bench cache`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use vindicator::cache::{read_cache, write_cache};
+use vindicator::rng::Rng;
+use vindicator::trec::{parse_from_trec, TrecEntry};
+
+const NUM_QUERIES: u32 = 250;
+const LIST_LEN: u32 = 1000;
+const ITERATIONS: u32 = 10;
+
+fn time<F: FnMut() -> usize>(mut f: F) -> Duration {
+    let start = Instant::now();
+    let mut total = 0;
+    for _ in 0..ITERATIONS {
+        total += black_box(f());
+    }
+    assert!(total > 0);
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let mut rng = Rng::new(0);
+    let mut text = String::new();
+    for q in 0..NUM_QUERIES {
+        for rank in 0..LIST_LEN {
+            let docno = format!("clueweb09-en{:04}-{:02}-{:05}", rng.below(10000), rng.below(100), rng.below(100000));
+            let score = 20. - rank as f32 / 50. + rng.next_f32() / 100.;
+            text.push_str(&format!("{} Q0 {} {} {} bm25\n", 301 + q, docno, rank, score));
+        }
+    }
+    let entries: Vec<_> = parse_from_trec(&text).unwrap().iter().map(TrecEntry::to_owned).collect();
+    let mut cache = Vec::new();
+    write_cache(&mut cache, &entries).unwrap();
+
+    let parsed = time(|| {
+        let entries: Vec<_> = parse_from_trec(&text).unwrap().iter().map(TrecEntry::to_owned).collect();
+        entries.len()
+    });
+    let loaded = time(|| read_cache(&cache[..]).unwrap().len());
+
+    println!("{} entries, {} bytes of text, {} bytes of cache", entries.len(), text.len(), cache.len());
+    println!("parse_from_trec: {:>10.3?} per iteration", parsed);
+    println!("read_cache:      {:>10.3?} per iteration", loaded);
+    println!(
+        "speedup:         {:>10.2}x",
+        parsed.as_secs_f64() / loaded.as_secs_f64()
+    );
+}
@@ -0,0 +1,49 @@
+//! Compares the accuracy and speed of naive and compensated summation
+//! (`comb_sum` and `comb_sum_kahan`) on inputs where naive summation
+//! loses precision.
+//!
+//! Run with `cargo bench`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use vindicator::fuser::{comb_sum, comb_sum_kahan};
+use vindicator::{score, Score};
+
+const ITERATIONS: u32 = 1_000;
+
+fn time<F: Fn(&[Score]) -> Score>(f: F, scores: &[Score]) -> Duration {
+    let start = Instant::now();
+    let mut total = 0.;
+    for _ in 0..ITERATIONS {
+        total += f(black_box(scores)).raw();
+    }
+    assert!(total.is_finite());
+    start.elapsed() / ITERATIONS
+}
+
+fn report(name: &str, scores: &[Score]) {
+    let exact: f64 = scores.iter().map(|s| f64::from(s.raw())).sum();
+    println!("{} (exact sum {}):", name, exact);
+    for (fuser_name, fuser) in &[
+        ("comb_sum", comb_sum as fn(&[Score]) -> Score),
+        ("comb_sum_kahan", comb_sum_kahan),
+    ] {
+        let sum = f64::from(fuser(scores).raw());
+        println!(
+            "  {:<15} relative error {:>10.3e}, {:>10.3?} per sum",
+            fuser_name,
+            ((sum - exact) / exact).abs(),
+            time(fuser, scores),
+        );
+    }
+}
+
+fn main() {
+    // a few large scores followed by many tiny ones
+    let mut scores = vec![score(1e6); 4];
+    scores.extend(vec![score(1e-2); 100_000]);
+    report("large then tiny", &scores);
+
+    // reciprocal ranks of long lists, as in RRF
+    let scores: Vec<_> = (0..100_000).map(|r| score(1. / (r as f32 + 61.))).collect();
+    report("reciprocal ranks", &scores);
+}
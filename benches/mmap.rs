@@ -0,0 +1,56 @@
+//! Compares the throughput of parsing a large run file from a memory
+//! mapping against reading it into a string first.
+//!
+//! Run with `cargo bench --features mmap --bench mmap`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use vindicator::mmap::{parse_from_trec_mmap, MappedFile};
+use vindicator::parse_from_trec;
+
+const NUM_QUERIES: u32 = 50;
+const LIST_LEN: u32 = 2_000;
+const ITERATIONS: u32 = 20;
+
+fn time<F: FnMut() -> usize>(mut f: F) -> Duration {
+    let start = Instant::now();
+    let mut total = 0;
+    for _ in 0..ITERATIONS {
+        total += black_box(f());
+    }
+    assert!(total > 0);
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let mut data = String::new();
+    for q in 0..NUM_QUERIES {
+        for i in 0..LIST_LEN {
+            data.push_str(&format!("{} Q0 doc{:08} {} {} bench\n", 301 + q, i * 7, i, 1. / (i + 1) as f32));
+        }
+    }
+    let path = std::env::temp_dir().join(format!("vindicator-bench-mmap-{}.txt", std::process::id()));
+    std::fs::write(&path, &data).unwrap();
+
+    let read = time(|| {
+        let data = std::fs::read_to_string(&path).unwrap();
+        parse_from_trec(&data).unwrap().len()
+    });
+    let mapped = time(|| {
+        let file = unsafe { MappedFile::open(&path) }.unwrap();
+        parse_from_trec_mmap(&file).unwrap().len()
+    });
+    std::fs::remove_file(&path).unwrap();
+
+    let mb = data.len() as f64 / 1e6;
+    println!("file size:      {:>10.1} MB", mb);
+    println!(
+        "read_to_string: {:>10.3?} per iteration ({:.0} MB/s)",
+        read,
+        mb / read.as_secs_f64()
+    );
+    println!(
+        "mmap:           {:>10.3?} per iteration ({:.0} MB/s)",
+        mapped,
+        mb / mapped.as_secs_f64()
+    );
+}
@@ -0,0 +1,46 @@
+//! Compares sorting a long fused list by descending score, as done by the
+//! fusion functions, against partitioning it with `top_k_by_score` when
+//! only the first results are needed.
+//!
+//! Run with `cargo bench --bench sort`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use vindicator::postprocess::top_k_by_score;
+use vindicator::rng::Rng;
+use vindicator::{score, EntryInfo};
+
+const LIST_LEN: u32 = 1_000_000;
+const TOP_K: usize = 1000;
+const ITERATIONS: u32 = 10;
+
+fn time<F: FnMut(Vec<EntryInfo<u32>>) -> Vec<EntryInfo<u32>>>(list: &[EntryInfo<u32>], mut f: F) -> Duration {
+    let mut elapsed = Duration::default();
+    for _ in 0..ITERATIONS {
+        let list = list.to_vec();
+        let start = Instant::now();
+        let out = black_box(f(list));
+        elapsed += start.elapsed();
+        assert!(!out.is_empty());
+    }
+    elapsed / ITERATIONS
+}
+
+fn main() {
+    let mut rng = Rng::new(0);
+    let list: Vec<_> = (0..LIST_LEN)
+        .map(|id| EntryInfo {
+            id,
+            score: score(rng.below(1 << 24) as f32 / (1 << 20) as f32 - 4.),
+        })
+        .collect();
+
+    let sorted = time(&list, |mut l| {
+        l.sort_unstable_by_key(|e| -e.score);
+        l.truncate(TOP_K);
+        l
+    });
+    let top_k = time(&list, |l| top_k_by_score(l, TOP_K));
+
+    println!("sort and truncate:    {:>10.3?} per iteration", sorted);
+    println!("top_k_by_score:       {:>10.3?} per iteration", top_k);
+}
@@ -0,0 +1,45 @@
+//! Compares the sorted merge fast path for fusing two lists
+//! against the general, hash-based fusion procedure.
+//!
+//! Run with `cargo bench`.
+use std::time::{Duration, Instant};
+use vindicator::fuser::{comb_sum, fuse_scored_lists, fuse_two_sorted};
+use vindicator::{score, EntryInfo};
+
+const LIST_LEN: u32 = 10_000;
+const ITERATIONS: u32 = 200;
+
+fn make_list(offset: u32, step: u32) -> Vec<EntryInfo<String>> {
+    (0..LIST_LEN)
+        .map(|i| EntryInfo {
+            id: format!("doc{:08}", offset + i * step),
+            score: score(1. / (i + 1) as f32),
+        })
+        .collect()
+}
+
+fn time<F: FnMut() -> usize>(mut f: F) -> Duration {
+    let start = Instant::now();
+    let mut total = 0;
+    for _ in 0..ITERATIONS {
+        total += f();
+    }
+    assert!(total > 0);
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    // IDs are zero padded, so both lists are sorted by ID
+    let list1 = make_list(0, 2);
+    let list2 = make_list(LIST_LEN / 2, 3);
+
+    let general = time(|| fuse_scored_lists(&list1, &list2, comb_sum).len());
+    let sorted = time(|| fuse_two_sorted(&list1, &list2, comb_sum).len());
+
+    println!("fuse_scored_lists: {:>10.3?} per iteration", general);
+    println!("fuse_two_sorted:   {:>10.3?} per iteration", sorted);
+    println!(
+        "speedup:           {:>10.2}x",
+        general.as_secs_f64() / sorted.as_secs_f64()
+    );
+}
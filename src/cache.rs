@@ -0,0 +1,158 @@
+//! A compact binary cache of parsed run files, for reloading large runs
+//! faster than parsing their text again.
+//!
+//! A cache starts with the magic bytes `VNDC` and a format version, so that
+//! caches written in another format are rejected instead of misread. The
+//! entries follow, each with its query ID, document ID, rank, score and run
+//! ID, where strings are prefixed by their length in bytes and all numbers
+//! are little endian.
+use crate::trec::TrecEntryOwned;
+use crate::Score;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"VNDC";
+
+/// The version of the cache format, to be increased on every change to it.
+pub const CACHE_VERSION: u32 = 1;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_str<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    let len = u32::try_from(s.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("string of {} bytes is too long for a run cache", s.len()),
+        )
+    })?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(s.as_bytes())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = read_u32(reader)? as u64;
+    // read up to the length instead of allocating it up front, as it is not
+    // trusted either
+    let mut bytes = Vec::new();
+    if reader.by_ref().take(len).read_to_end(&mut bytes)? as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|e| invalid_data(e.to_string()))
+}
+
+/// Writes run entries to a cache.
+pub fn write_cache<W: Write>(mut writer: W, entries: &[TrecEntryOwned]) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&CACHE_VERSION.to_le_bytes())?;
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for e in entries {
+        write_str(&mut writer, &e.qid)?;
+        write_str(&mut writer, &e.docno)?;
+        writer.write_all(&e.rank.to_le_bytes())?;
+        writer.write_all(&e.score.raw().to_le_bytes())?;
+        write_str(&mut writer, &e.runid)?;
+    }
+    writer.flush()
+}
+
+/// Reads run entries from a cache written by [`write_cache`].
+///
+/// # Errors
+///
+/// Besides I/O errors, returns an error of kind `InvalidData` if the data
+/// is not a cache, or a cache of another format version, or if an entry
+/// has an invalid string or a `NaN` score. A truncated cache fails with an
+/// error of kind `UnexpectedEof`.
+///
+/// [`write_cache`]: ./fn.write_cache.html
+pub fn read_cache<R: Read>(mut reader: R) -> io::Result<Vec<TrecEntryOwned>> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a run cache".to_string()));
+    }
+    let version = read_u32(&mut reader)?;
+    if version != CACHE_VERSION {
+        return Err(invalid_data(format!(
+            "run cache has format version {}, expected {}",
+            version, CACHE_VERSION
+        )));
+    }
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len) as usize;
+
+    // the length is not trusted for allocating everything up front
+    let mut entries = Vec::with_capacity(len.min(1 << 16));
+    for _ in 0..len {
+        let qid = read_string(&mut reader)?;
+        let docno = read_string(&mut reader)?;
+        let rank = read_u32(&mut reader)?;
+        let score = f32::from_bits(read_u32(&mut reader)?);
+        let score = Score::try_new(score).ok_or_else(|| invalid_data("score is not a number".to_string()))?;
+        let runid = read_string(&mut reader)?;
+        entries.push(TrecEntryOwned {
+            qid,
+            docno,
+            rank,
+            score,
+            runid,
+        });
+    }
+    Ok(entries)
+}
+
+/// Saves run entries to a cache file, replacing its contents.
+pub fn save_cache<P: AsRef<Path>>(entries: &[TrecEntryOwned], path: P) -> io::Result<()> {
+    write_cache(BufWriter::new(File::create(path)?), entries)
+}
+
+/// Loads run entries from a cache file saved with [`save_cache`].
+///
+/// [`save_cache`]: ./fn.save_cache.html
+pub fn load_cache<P: AsRef<Path>>(path: P) -> io::Result<Vec<TrecEntryOwned>> {
+    read_cache(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trec::parse_from_trec;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let data = "301 0 d1 0 12.5 bm25\n301 0 dé2 1 -0.25 bm25\n302 0 d3 0 1e-30 dense\n";
+        let entries: Vec<_> = parse_from_trec(data).unwrap().iter().map(|e| e.to_owned()).collect();
+
+        let path = std::env::temp_dir().join(format!("vindicator-test-cache-{}.bin", std::process::id()));
+        save_cache(&entries, &path).unwrap();
+        let loaded = load_cache(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, entries);
+
+        let mut cache = Vec::new();
+        write_cache(&mut cache, &entries).unwrap();
+        assert_eq!(read_cache(&cache[..cache.len() - 1]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_cache(data.as_bytes()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        cache[4] += 1;
+        let err = read_cache(&cache[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("version 2"), "{}", err);
+
+        // a length prefix far beyond the end of the data
+        let mut bogus = Vec::new();
+        write_cache(&mut bogus, &entries[..1]).unwrap();
+        bogus[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_cache(&bogus[..]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
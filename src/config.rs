@@ -0,0 +1,367 @@
+//! Named fusion configurations, which can be saved to a file and loaded
+//! back to reproduce the same fusion.
+//!
+//! Configurations are stored in a small subset of TOML, with one
+//! `key = value` pair per line:
+//!
+//! ```toml
+//! # tuned on the 2019 topics
+//! name = "tuned"
+//! normalization = "minmax"
+//! weights = [1, 0.5]
+//! fuser = "combSUM"
+//! top_k = 100
+//! min_score = 0.25
+//! ```
+//!
+//! Only `fuser` is required.
+use crate::fuser::{self, ScoreFuserFn};
+use crate::normalize::{MinMax, Normalizer, Percentile, Sum, ZScore};
+use crate::postprocess;
+use crate::{EntryInfo, SearchEntry};
+use std::fmt::{self, Write as _};
+use std::hash::Hash;
+use std::io;
+use std::path::Path;
+
+/// A fusion setup: how each system's results are normalized and weighted,
+/// how they are fused, and which cutoffs are applied to the fused list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FusionConfig {
+    /// a name for the configuration, for telling saved setups apart
+    pub name: Option<String>,
+    /// the name of the normalization applied to each list before fusion:
+    /// `minmax`, `zscore`, `sum` or `percentile` (none by default)
+    pub normalization: Option<String>,
+    /// the weight of each system, by list position, applied after
+    /// normalization (1 for lists without a weight)
+    pub weights: Vec<f32>,
+    /// the name of a built-in score-based fusion algorithm, as accepted
+    /// by [`score_fuser`]
+    ///
+    /// [`score_fuser`]: ../fuser/fn.score_fuser.html
+    pub fuser: String,
+    /// the maximum number of documents in the fused list
+    pub top_k: Option<usize>,
+    /// the minimum fused score of a document to be kept
+    pub min_score: Option<f32>,
+}
+
+/// An error loading or applying a fusion configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// the configuration file could not be read
+    Io(io::Error),
+    /// a line of the configuration could not be parsed (with 1-based line
+    /// numbers)
+    Parse { line: usize, message: String },
+    /// the required `fuser` key is missing
+    MissingFuser,
+    /// there is no built-in fusion algorithm with this name
+    UnknownFuser(String),
+    /// there is no normalization with this name
+    UnknownNormalization(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read configuration: {}", e),
+            ConfigError::Parse { line, message } => {
+                write!(f, "failed to parse configuration at line {}: {}", line, message)
+            }
+            ConfigError::MissingFuser => f.write_str("configuration has no fuser"),
+            ConfigError::UnknownFuser(name) => write!(f, "unknown fusion algorithm `{}`", name),
+            ConfigError::UnknownNormalization(name) => write!(f, "unknown normalization `{}`", name),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl FusionConfig {
+    /// Creates a configuration which only fuses with the given algorithm.
+    pub fn new(fuser: &str) -> Self {
+        FusionConfig {
+            name: None,
+            normalization: None,
+            weights: Vec::new(),
+            fuser: fuser.to_string(),
+            top_k: None,
+            min_score: None,
+        }
+    }
+
+    /// Retrieves the fusion algorithm of this configuration.
+    pub fn score_fuser(&self) -> Result<ScoreFuserFn, ConfigError> {
+        fuser::score_fuser(&self.fuser).ok_or_else(|| ConfigError::UnknownFuser(self.fuser.clone()))
+    }
+
+    fn normalizer<I>(&self) -> Result<Option<Box<dyn Normalizer<I>>>, ConfigError> {
+        let name = match &self.normalization {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let normalizer: Box<dyn Normalizer<I>> = match &name[..] {
+            "minmax" => Box::new(MinMax),
+            "zscore" => Box::new(ZScore),
+            "sum" => Box::new(Sum),
+            "percentile" => Box::new(Percentile),
+            _ => return Err(ConfigError::UnknownNormalization(name.clone())),
+        };
+        Ok(Some(normalizer))
+    }
+
+    /// Fuses the results of multiple systems, one list per system, with
+    /// this configuration. Fails if the fuser or the normalization is
+    /// unknown.
+    pub fn apply<I, LL, L, R>(&self, lists: LL) -> Result<Vec<EntryInfo<I>>, ConfigError>
+    where
+        I: Eq + Clone + Hash,
+        LL: IntoIterator<Item = L>,
+        L: IntoIterator<Item = R>,
+        R: SearchEntry<Id = I>,
+    {
+        let fuser = self.score_fuser()?;
+        let normalizer = self.normalizer()?;
+        let lists: Vec<Vec<EntryInfo<I>>> = lists
+            .into_iter()
+            .map(|list| {
+                let mut list: Vec<_> = list.into_iter().map(|r| r.to_entry()).collect();
+                if let Some(normalizer) = &normalizer {
+                    normalizer.normalize(&mut list);
+                }
+                list
+            })
+            .collect();
+        let fused = fuser::fuse_weighted(&lists, &self.weights, fuser);
+        Ok(postprocess::finalize(fused, self.top_k, self.min_score))
+    }
+
+    /// Serializes this configuration in the format described in the
+    /// [module documentation](index.html). Keys which are not set are left
+    /// out.
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        if let Some(name) = &self.name {
+            out.push_str("name = ");
+            quote(&mut out, name);
+            out.push('\n');
+        }
+        if let Some(normalization) = &self.normalization {
+            out.push_str("normalization = ");
+            quote(&mut out, normalization);
+            out.push('\n');
+        }
+        if !self.weights.is_empty() {
+            let weights: Vec<_> = self.weights.iter().map(f32::to_string).collect();
+            writeln!(out, "weights = [{}]", weights.join(", ")).unwrap();
+        }
+        out.push_str("fuser = ");
+        quote(&mut out, &self.fuser);
+        out.push('\n');
+        if let Some(top_k) = self.top_k {
+            writeln!(out, "top_k = {}", top_k).unwrap();
+        }
+        if let Some(min_score) = self.min_score {
+            writeln!(out, "min_score = {}", min_score).unwrap();
+        }
+        out
+    }
+
+    /// Parses a configuration in the format described in the
+    /// [module documentation](index.html). Blank lines and comments
+    /// starting with `#` are ignored. Fails if a key is repeated, or if the
+    /// fuser or the normalization is unknown.
+    pub fn from_toml(data: &str) -> Result<Self, ConfigError> {
+        let mut config = FusionConfig::new("");
+        let mut keys: Vec<&str> = Vec::new();
+        for (i, l) in data.lines().enumerate() {
+            let l = l.trim();
+            if l.is_empty() || l.starts_with('#') {
+                continue;
+            }
+            let err = |message: &str| ConfigError::Parse {
+                line: i + 1,
+                message: message.to_string(),
+            };
+            let (key, value) = match l.find('=') {
+                Some(pos) => (l[..pos].trim(), l[pos + 1..].trim()),
+                None => return Err(err("expected `key = value`")),
+            };
+            if keys.contains(&key) {
+                return Err(err(&format!("duplicate key `{}`", key)));
+            }
+            keys.push(key);
+            let string = || unquote(value).ok_or_else(|| err("expected a string with valid escapes"));
+            let number = |value: &str| value.trim().parse::<f32>().map_err(|_| err("expected a number"));
+            match key {
+                "name" => config.name = Some(string()?),
+                "normalization" => config.normalization = Some(string()?),
+                "fuser" => config.fuser = string()?,
+                "weights" => {
+                    if !value.starts_with('[') || !value.ends_with(']') {
+                        return Err(err("expected an array of numbers"));
+                    }
+                    let items = value[1..value.len() - 1].trim();
+                    config.weights = if items.is_empty() {
+                        Vec::new()
+                    } else {
+                        items.split(',').map(number).collect::<Result<_, _>>()?
+                    };
+                }
+                "top_k" => {
+                    config.top_k = Some(value.parse().map_err(|_| err("expected a non-negative integer"))?)
+                }
+                "min_score" => config.min_score = Some(number(value)?),
+                _ => return Err(err(&format!("unknown key `{}`", key))),
+            }
+        }
+        if !keys.contains(&"fuser") {
+            return Err(ConfigError::MissingFuser);
+        }
+        config.score_fuser()?;
+        config.normalizer::<&str>()?;
+        Ok(config)
+    }
+}
+
+/// Writes a string as a double-quoted literal, escaping quotes, backslashes
+/// and control characters as in TOML, so that the literal stays on one line.
+fn quote(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => write!(out, "\\u{:04X}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Parses a double-quoted string literal written by `quote`. Fails on
+/// unknown escape sequences.
+fn unquote(value: &str) -> Option<String> {
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return None;
+    }
+    let mut out = String::new();
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push(match chars.next()? {
+                '"' => '"',
+                '\\' => '\\',
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return None;
+                    }
+                    std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                _ => return None,
+            }),
+            '"' => return None,
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+/// Saves a fusion configuration to a file, replacing its contents.
+pub fn save_config<P: AsRef<Path>>(config: &FusionConfig, path: P) -> io::Result<()> {
+    std::fs::write(path, config.to_toml())
+}
+
+/// Loads a fusion configuration from a file, checking that its fuser is
+/// known.
+pub fn load_config<P: AsRef<Path>>(path: P) -> Result<FusionConfig, ConfigError> {
+    FusionConfig::from_toml(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score;
+
+    #[test]
+    fn test_config_round_trip() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let lists = vec![
+            vec![e("a", 10.), e("b", 6.), e("c", 2.)],
+            vec![e("b", 0.75), e("d", 0.5), e("a", 0.25)],
+            vec![e("c", 3.), e("a", 1.)],
+        ];
+        let config = FusionConfig {
+            name: Some("tuned \"v2\"\n\tC:\\runs\u{7}".to_string()),
+            normalization: Some("minmax".to_string()),
+            weights: vec![1., 0.6],
+            fuser: "combMNZ".to_string(),
+            top_k: Some(3),
+            min_score: Some(0.1),
+        };
+
+        let path = std::env::temp_dir().join(format!("vindicator-test-config-{}.toml", std::process::id()));
+        save_config(&config, &path).unwrap();
+        let loaded = load_config(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, config);
+        let fused = config.apply(&lists).unwrap();
+        assert_eq!(loaded.apply(&lists).unwrap(), fused);
+        assert_eq!(fused.iter().map(|e| e.id).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let config = FusionConfig::from_toml("# defaults\n\nfuser = \"combsum\"\n").unwrap();
+        assert_eq!(config, FusionConfig::new("combsum"));
+
+        let parse_line = |data| match FusionConfig::from_toml(data) {
+            Err(ConfigError::Parse { line, .. }) => line,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(parse_line("fuser = \"combSUM\"\ntop_k = -1\n"), 2);
+        assert_eq!(parse_line("fuser = combSUM\n"), 1);
+        assert_eq!(parse_line("fuser = \"combSUM\"\nweights = [1, x]\n"), 2);
+        assert_eq!(parse_line("fuser = \"combSUM\"\ndepth = 3\n"), 2);
+        assert_eq!(parse_line("fuser = \"combSUM\"\nname = \"a\\qb\"\n"), 2);
+        assert_eq!(parse_line("fuser = \"combSUM\"\nname = \"a\\u12\"\n"), 2);
+        assert_eq!(parse_line("fuser = \"combSUM\"\ntop_k = 3\ntop_k = 4\n"), 3);
+
+        assert!(matches!(FusionConfig::from_toml("top_k = 3"), Err(ConfigError::MissingFuser)));
+        assert!(matches!(
+            FusionConfig::from_toml("fuser = \"combFOO\""),
+            Err(ConfigError::UnknownFuser(_))
+        ));
+        assert!(matches!(
+            FusionConfig::from_toml("fuser = \"combSUM\"\nnormalization = \"log\""),
+            Err(ConfigError::UnknownNormalization(_))
+        ));
+        let mut config = FusionConfig::new("combSUM");
+        config.normalization = Some("log".to_string());
+        let lists: Vec<Vec<EntryInfo<&str>>> = vec![];
+        assert!(matches!(config.apply(&lists), Err(ConfigError::UnknownNormalization(_))));
+    }
+}
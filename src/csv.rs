@@ -0,0 +1,132 @@
+//! CSV result list parsing module
+use crate::trec::{self, ParseError, TrecEntry};
+use crate::{parsed_score, Score};
+use std::io::{self, Write};
+
+/// Parses search results in comma-separated values.
+///
+/// Expected format:
+///
+/// `qid,docno,rank,score,runid`
+///
+/// A header line beginning with `qid` is skipped if present. Fields are
+/// trimmed of surrounding whitespace, and quoting is not supported.
+pub fn parse_from_csv<'a>(file_data: &'a str) -> Result<Vec<TrecEntry<'a>>, ParseError> {
+    let mut lines = file_data.lines().peekable();
+    if let Some(header) = lines.peek() {
+        if header.split(',').next().map(str::trim) == Some("qid") {
+            lines.next();
+        }
+    }
+
+    lines
+        .map(|l| {
+            let mut fields = l.split(',').map(str::trim);
+            let qid = fields.next().ok_or(ParseError::Eol("qid"))?;
+            let docno = fields.next().ok_or(ParseError::Eol("docno"))?;
+            let rank = fields.next().ok_or(ParseError::Eol("rank"))?;
+            let rank: u32 = rank
+                .parse()
+                .map_err(|_| ParseError::InvalidRank(rank.to_string()))?;
+            let score = fields.next().ok_or(ParseError::Eol("score"))?;
+            let score: Score = score
+                .parse()
+                .map_err(|_| ())
+                .and_then(|s| parsed_score(s).ok_or(()))
+                .map_err(|_| ParseError::InvalidScore(score.to_string()))?;
+            let runid = fields.next().ok_or(ParseError::Eol("runid"))?;
+            Ok(TrecEntry {
+                qid,
+                docno,
+                rank,
+                score,
+                runid,
+            })
+        })
+        .collect()
+}
+
+/// Writes search results in comma-separated values, in the format read by
+/// [`parse_from_csv`], starting with a header line.
+///
+/// # Errors
+///
+/// Besides I/O errors, returns an error of kind `InvalidData` if a field
+/// contains a comma or a line break, or starts or ends with whitespace, as
+/// it would not be read back the same without quoting.
+///
+/// [`parse_from_csv`]: ./fn.parse_from_csv.html
+pub fn write_all<'a, I, W>(mut writer: W, list: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = TrecEntry<'a>>,
+    W: Write,
+{
+    fn check_field(name: &str, value: &str) -> io::Result<()> {
+        if value.contains([',', '\n', '\r']) || value.trim() != value {
+            return Err(trec::invalid_field(name, value, "CSV"));
+        }
+        Ok(())
+    }
+    writeln!(writer, "qid,docno,rank,score,runid")?;
+    for e in list {
+        check_field("query ID", e.qid)?;
+        check_field("document ID", e.docno)?;
+        check_field("run ID", e.runid)?;
+        writeln!(writer, "{},{},{},{},{}", e.qid, e.docno, e.rank, e.score, e.runid)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score;
+
+    #[test]
+    fn test_parse_from_csv() {
+        let data = "qid,docno,rank,score,runid\n301, d1, 0, 2.5, a\n301,d2,1,1,a\n";
+        let list = parse_from_csv(data).unwrap();
+        assert_eq!(
+            list,
+            vec![
+                TrecEntry {
+                    qid: "301",
+                    docno: "d1",
+                    rank: 0,
+                    score: score(2.5),
+                    runid: "a",
+                },
+                TrecEntry {
+                    qid: "301",
+                    docno: "d2",
+                    rank: 1,
+                    score: score(1.),
+                    runid: "a",
+                },
+            ]
+        );
+
+        match parse_from_csv("301,d1,0,NaN,a") {
+            Err(ParseError::InvalidScore(s)) => assert_eq!(s, "NaN"),
+            e => panic!("unexpected result {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_write_all() {
+        let data = "qid,docno,rank,score,runid\n301,d1,0,2.5,a\n301,d2,1,0.1,a\n";
+        let mut out = Vec::new();
+        write_all(&mut out, parse_from_csv(data).unwrap()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), data);
+
+        let entry = TrecEntry {
+            qid: "301",
+            docno: "d1,d2",
+            rank: 0,
+            score: score(1.),
+            runid: "a",
+        };
+        let err = write_all(Vec::new(), vec![entry]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,564 @@
+//! Evaluation of ranked result lists against relevance judgments.
+//!
+//! The judgments of a query map document IDs to a relevance level, where
+//! levels above zero are relevant. Documents without a judgment are
+//! considered non-relevant. All metrics take the order of the given list as
+//! its ranking, so fused lists should be sorted by score beforehand, as they
+//! are when returned by the fusion functions.
+use crate::fuser::{comb_sum, fuse_weighted};
+use crate::rng::Rng;
+use crate::trec::TrecEntryOwned;
+use crate::{EntryInfo, Rank, SearchEntry};
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash};
+
+fn is_relevant<I, S>(id: &I, qrels: &HashMap<I, i32, S>) -> bool
+where
+    I: Eq + Hash,
+    S: BuildHasher,
+{
+    matches!(qrels.get(id), Some(&r) if r > 0)
+}
+
+/// Calculates the average precision of a ranked list: the mean of the
+/// precision at the rank of each relevant document, where relevant
+/// documents which were not retrieved contribute a precision of 0.
+///
+/// Returns 0 if there are no relevant documents.
+pub fn average_precision<I, S>(ranked: &[EntryInfo<I>], qrels: &HashMap<I, i32, S>) -> f32
+where
+    I: Eq + Hash,
+    S: BuildHasher,
+{
+    let num_relevant = qrels.values().filter(|&&r| r > 0).count();
+    if num_relevant == 0 {
+        return 0.;
+    }
+    let mut hits = 0;
+    let mut sum = 0.;
+    for (i, e) in ranked.iter().enumerate() {
+        if is_relevant(&e.id, qrels) {
+            hits += 1;
+            sum += hits as f32 / (i + 1) as f32;
+        }
+    }
+    sum / num_relevant as f32
+}
+
+/// Calculates the precision at `k` of a ranked list: the fraction of the
+/// first `k` positions taken by relevant documents. Missing positions, if
+/// the list is shorter than `k`, count as non-relevant.
+///
+/// Returns 0 if `k` is 0.
+pub fn precision_at_k<I, S>(ranked: &[EntryInfo<I>], qrels: &HashMap<I, i32, S>, k: usize) -> f32
+where
+    I: Eq + Hash,
+    S: BuildHasher,
+{
+    if k == 0 {
+        return 0.;
+    }
+    let hits = ranked
+        .iter()
+        .take(k)
+        .filter(|e| is_relevant(&e.id, qrels))
+        .count();
+    hits as f32 / k as f32
+}
+
+/// The gain of a judged document in the discounted cumulative gain, as a
+/// function of its relevance level `rel`. Both give no gain to
+/// non-relevant documents, and the same gain of 1 to documents of level 1,
+/// so they only differ on graded judgments, where the exponential gain
+/// rewards the most relevant documents much more.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum GainKind {
+    /// a gain of `rel`, as in the original formulation of nDCG and in
+    /// trec_eval's `ndcg_cut`
+    Linear,
+    /// a gain of `2^rel - 1`, the most common choice in recent work (the
+    /// default)
+    #[default]
+    Exponential,
+}
+
+impl GainKind {
+    fn gain(self, rel: i32) -> f32 {
+        if rel <= 0 {
+            return 0.;
+        }
+        match self {
+            GainKind::Linear => rel as f32,
+            GainKind::Exponential => 2f32.powi(rel) - 1.,
+        }
+    }
+}
+
+/// Calculates the normalized discounted cumulative gain at `k` of a ranked
+/// list, with a gain of `2^rel - 1` for a document of relevance level `rel`
+/// and a discount of `log2(rank + 2)` for ranks starting at 0. The ideal
+/// ranking, used for normalization, sorts all judged documents by relevance.
+/// See [`ndcg_at_k_with_gain`] for a linear gain.
+///
+/// Returns 0 if there are no relevant documents.
+///
+/// [`ndcg_at_k_with_gain`]: ./fn.ndcg_at_k_with_gain.html
+pub fn ndcg_at_k<I, S>(ranked: &[EntryInfo<I>], qrels: &HashMap<I, i32, S>, k: usize) -> f32
+where
+    I: Eq + Hash,
+    S: BuildHasher,
+{
+    ndcg_at_k_with_gain(ranked, qrels, k, GainKind::Exponential)
+}
+
+/// Calculates the normalized discounted cumulative gain at `k` of a ranked
+/// list, like [`ndcg_at_k`], with the given gain function. When comparing
+/// with published results on graded judgments, the gain must match theirs,
+/// as the two can rank systems differently.
+///
+/// Returns 0 if there are no relevant documents.
+///
+/// [`ndcg_at_k`]: ./fn.ndcg_at_k.html
+pub fn ndcg_at_k_with_gain<I, S>(ranked: &[EntryInfo<I>], qrels: &HashMap<I, i32, S>, k: usize, gain: GainKind) -> f32
+where
+    I: Eq + Hash,
+    S: BuildHasher,
+{
+    fn discount(i: usize) -> f32 {
+        (i as f32 + 2.).log2()
+    }
+
+    let dcg: f32 = ranked
+        .iter()
+        .take(k)
+        .enumerate()
+        .map(|(i, e)| gain.gain(qrels.get(&e.id).cloned().unwrap_or(0)) / discount(i))
+        .sum();
+
+    let mut ideal: Vec<i32> = qrels.values().cloned().filter(|&r| r > 0).collect();
+    ideal.sort_unstable_by(|a, b| b.cmp(a));
+    let idcg: f32 = ideal
+        .into_iter()
+        .take(k)
+        .enumerate()
+        .map(|(i, rel)| gain.gain(rel) / discount(i))
+        .sum();
+
+    if idcg > 0. {
+        dcg / idcg
+    } else {
+        0.
+    }
+}
+
+/// The rank which [`relevant_rank_distribution`] gives to relevant
+/// documents that are not in the ranked list.
+///
+/// [`relevant_rank_distribution`]: ./fn.relevant_rank_distribution.html
+pub const UNRETRIEVED: Rank = Rank::MAX;
+
+/// Retrieves the ranks at which the relevant documents of a query landed in
+/// a ranked list, for telling why a query does poorly: whether its relevant
+/// documents were ranked low or not retrieved at all.
+///
+/// Ranks start at 0, as in the runs written by the fusion tools, and are
+/// returned in ascending order, with one rank per relevant document. Those
+/// which are not in the list get the rank [`UNRETRIEVED`], so they come
+/// last.
+///
+/// [`UNRETRIEVED`]: ./constant.UNRETRIEVED.html
+pub fn relevant_rank_distribution<I, S>(ranked: &[EntryInfo<I>], relevant: &HashSet<I, S>) -> Vec<Rank>
+where
+    I: Eq + Hash,
+    S: BuildHasher,
+{
+    // a document listed more than once only counts at its first rank
+    let mut seen = HashSet::new();
+    let mut ranks: Vec<Rank> = ranked
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| relevant.contains(&e.id) && seen.insert(&e.id))
+        .map(|(i, _)| i as Rank)
+        .collect();
+    ranks.resize(relevant.len(), UNRETRIEVED);
+    ranks
+}
+
+/// Calculates how much a fused list improves over the best of the lists
+/// that were fused, with the given metric: `metric(fused)` minus the
+/// highest `metric(list)` among the input lists. A negative gain means that
+/// fusion did worse than just picking the best system for this query.
+///
+/// The metric is any function of a ranked list and the relevance judgments,
+/// such as [`average_precision`], or `|l, q| ndcg_at_k(l, q, 10)`. Each input
+/// list is evaluated in its given order.
+///
+/// Returns 0 if there are no relevant documents, as no ranking can do
+/// better than any other. Without input lists, the gain is the metric of
+/// the fused list.
+///
+/// [`average_precision`]: ./fn.average_precision.html
+pub fn fusion_gain<I, LL, L, R, S, M>(
+    lists: LL,
+    fused: &[EntryInfo<I>],
+    qrels: &HashMap<I, i32, S>,
+    metric: M,
+) -> f32
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    S: BuildHasher,
+    M: Fn(&[EntryInfo<I>], &HashMap<I, i32, S>) -> f32,
+{
+    if !qrels.values().any(|&r| r > 0) {
+        return 0.;
+    }
+    let best = lists
+        .into_iter()
+        .map(|list| {
+            let list: Vec<_> = list.into_iter().map(|r| r.to_entry()).collect();
+            metric(&list, qrels)
+        })
+        .fold(None, |best: Option<f32>, m| Some(best.map_or(m, |b| b.max(m))));
+    metric(fused, qrels) - best.unwrap_or(0.)
+}
+
+/// The weights tried for each system by [`optimize_weights`].
+///
+/// [`optimize_weights`]: ./fn.optimize_weights.html
+const WEIGHT_CANDIDATES: [f32; 9] = [0., 0.1, 0.25, 0.5, 1., 1.5, 2., 4., 8.];
+
+/// The maximum number of passes over all systems in [`optimize_weights`].
+///
+/// [`optimize_weights`]: ./fn.optimize_weights.html
+const MAX_ROUNDS: usize = 10;
+
+/// Learns a weight for each system, for fusion with weighted CombSUM (see
+/// [`fuse_weighted`]), which maximizes the mean of a metric over a set of
+/// training queries, by coordinate ascent.
+///
+/// `queries` holds the lists of each training query, one list per system
+/// in the same order for all queries, and `qrels` the judgments of each
+/// query, in the same order. Starting with a weight of 1 for every
+/// system, each weight in turn is set to the best of a fixed set of values
+/// between 0 and 8, keeping the others fixed, until a whole pass over the
+/// systems brings no improvement. The metric is a function of a ranked
+/// list and the judgments, as in [`fusion_gain`]. Scores should be
+/// normalized beforehand, so that the weights are comparable.
+///
+/// The weights fit the training queries, and possibly their noise as well,
+/// especially with few queries or many systems. Their benefit should be
+/// measured on held-out queries, not on the ones they were learned from.
+///
+/// [`fuse_weighted`]: ../fuser/fn.fuse_weighted.html
+/// [`fusion_gain`]: ./fn.fusion_gain.html
+pub fn optimize_weights<I, S, M>(
+    queries: &[Vec<Vec<EntryInfo<I>>>],
+    qrels: &[HashMap<I, i32, S>],
+    metric: M,
+) -> Vec<f32>
+where
+    I: Eq + Clone + Hash,
+    S: BuildHasher,
+    M: Fn(&[EntryInfo<I>], &HashMap<I, i32, S>) -> f32,
+{
+    assert_eq!(queries.len(), qrels.len(), "each training query needs judgments");
+    let num_systems = queries.iter().map(Vec::len).max().unwrap_or(0);
+    let evaluate = |weights: &[f32]| -> f32 {
+        let total: f32 = queries
+            .iter()
+            .zip(qrels)
+            .map(|(lists, qrels)| metric(&fuse_weighted(lists, weights, comb_sum), qrels))
+            .sum();
+        total / queries.len().max(1) as f32
+    };
+
+    let mut weights = vec![1.; num_systems];
+    let mut best = evaluate(&weights);
+    for _ in 0..MAX_ROUNDS {
+        let mut improved = false;
+        for system in 0..num_systems {
+            for &candidate in &WEIGHT_CANDIDATES {
+                let current = weights[system];
+                weights[system] = candidate;
+                let value = evaluate(&weights);
+                if value > best + 1e-6 {
+                    best = value;
+                    improved = true;
+                } else {
+                    weights[system] = current;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+    weights
+}
+
+/// Builds the oracle fusion of multiple ranked lists, one list per system:
+/// the ranking that a fusion method which knew the relevance judgments
+/// would produce. This is an upper bound for analysis, showing how much
+/// room there is for real fusion methods to improve, and not a fusion
+/// method in itself, since it relies on the judgments of the documents it
+/// ranks.
+///
+/// All documents retrieved by any system are included. Relevant documents
+/// come first, the most relevant first, and then the other documents; the
+/// documents of each relevance level are ordered by the best rank that any
+/// system gave them, in no particular order among equal best ranks. Each
+/// list is taken in its given order. The scores of
+/// the output go from the number of documents for the first one down to 1.
+pub fn oracle_fusion<I, LL, L, R, S>(lists: LL, qrels: &HashMap<I, i32, S>) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    S: BuildHasher,
+{
+    let mut best_ranks: HashMap<I, usize> = HashMap::new();
+    for list in lists {
+        for (rank, r) in list.into_iter().enumerate() {
+            let best = best_ranks.entry(r.id().clone()).or_insert(rank);
+            *best = (*best).min(rank);
+        }
+    }
+    let mut docs: Vec<(I, usize)> = best_ranks.into_iter().collect();
+    docs.sort_by_key(|(id, rank)| (std::cmp::Reverse(qrels.get(id).cloned().unwrap_or(0).max(0)), *rank));
+    let num_docs = docs.len();
+    docs.into_iter()
+        .enumerate()
+        .map(|(i, (id, _))| EntryInfo {
+            id,
+            score: crate::score((num_docs - i) as f32),
+        })
+        .collect()
+}
+
+/// Randomly reorders the documents of each query of a run, for building
+/// random baselines to test the significance of an improvement against.
+/// This is meant for experiments only, as it throws away the ranking.
+///
+/// The documents of each query are shuffled among the entries of that
+/// query, while the other columns stay in place, so every document keeps
+/// appearing in its query, but at a random rank and with the score of that
+/// rank. The run thus remains sorted as it was, and evaluation tools which
+/// sort by score, such as trec_eval, see the same random order. The outcome
+/// only depends on the run and the state of the random number generator.
+pub fn shuffle_within_query(run: &mut [TrecEntryOwned], rng: &mut Rng) {
+    // the positions of the entries of each query, in order of appearance
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut queries: Vec<Vec<usize>> = Vec::new();
+    for (i, e) in run.iter().enumerate() {
+        match index.get(&*e.qid) {
+            Some(&q) => queries[q].push(i),
+            None => {
+                index.insert(&e.qid, queries.len());
+                queries.push(vec![i]);
+            }
+        }
+    }
+    for positions in queries {
+        let mut docnos: Vec<String> = positions.iter().map(|&i| std::mem::take(&mut run[i].docno)).collect();
+        rng.shuffle(&mut docnos);
+        for (&i, docno) in positions.iter().zip(docnos) {
+            run[i].docno = docno;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuser::fuse_scored;
+    use crate::score;
+    use approx::assert_abs_diff_eq;
+
+    fn list(ids: &[&'static str]) -> Vec<EntryInfo<&'static str>> {
+        ids.iter()
+            .enumerate()
+            .map(|(i, &id)| EntryInfo {
+                id,
+                score: score(1. / (i + 1) as f32),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_metrics() {
+        let qrels: HashMap<_, _> = vec![("a", 1), ("c", 2), ("e", 1), ("x", 0)].into_iter().collect();
+        let ranked = list(&["a", "b", "c", "x"]);
+
+        // e is never retrieved
+        assert_abs_diff_eq!(average_precision(&ranked, &qrels), (1. + 2. / 3.) / 3.);
+        assert_abs_diff_eq!(precision_at_k(&ranked, &qrels, 2), 0.5);
+        assert_abs_diff_eq!(precision_at_k(&ranked, &qrels, 10), 0.2);
+
+        let dcg = 1. + 3. / 4f32.log2();
+        let idcg = 3. + 1. / 3f32.log2() + 1. / 4f32.log2();
+        assert_abs_diff_eq!(ndcg_at_k(&ranked, &qrels, 3), dcg / idcg, epsilon = 1e-6);
+        assert_abs_diff_eq!(ndcg_at_k(&list(&["c", "a", "e"]), &qrels, 3), 1.);
+
+        let none: HashMap<&str, i32> = HashMap::new();
+        assert_eq!(average_precision(&ranked, &none), 0.);
+        assert_eq!(ndcg_at_k(&ranked, &none, 3), 0.);
+    }
+
+    #[test]
+    fn test_relevant_rank_distribution() {
+        let fused = list(&["a", "b", "c", "d", "e"]);
+        let relevant: HashSet<_> = vec!["b", "e"].into_iter().collect();
+        assert_eq!(relevant_rank_distribution(&fused, &relevant), vec![1, 4]);
+
+        let relevant: HashSet<_> = vec!["z", "a", "y"].into_iter().collect();
+        assert_eq!(relevant_rank_distribution(&fused, &relevant), vec![0, UNRETRIEVED, UNRETRIEVED]);
+        assert_eq!(relevant_rank_distribution(&fused, &HashSet::new()), vec![]);
+    }
+
+    #[test]
+    fn test_ndcg_gain_kinds() {
+        let qrels: HashMap<_, _> = vec![("a", 3), ("b", 1), ("c", 1)].into_iter().collect();
+        // the most relevant document is ranked last
+        let ranked = list(&["b", "c", "a"]);
+        let discount = [1., 3f32.log2(), 2.];
+
+        let dcg = 1. + 1. / discount[1] + 7. / discount[2];
+        let idcg = 7. + 1. / discount[1] + 1. / discount[2];
+        let exponential = ndcg_at_k_with_gain(&ranked, &qrels, 3, GainKind::Exponential);
+        assert_abs_diff_eq!(exponential, dcg / idcg, epsilon = 1e-6);
+        assert_eq!(exponential, ndcg_at_k(&ranked, &qrels, 3));
+        assert_eq!(GainKind::default(), GainKind::Exponential);
+
+        let dcg = 1. + 1. / discount[1] + 3. / discount[2];
+        let idcg = 3. + 1. / discount[1] + 1. / discount[2];
+        let linear = ndcg_at_k_with_gain(&ranked, &qrels, 3, GainKind::Linear);
+        assert_abs_diff_eq!(linear, dcg / idcg, epsilon = 1e-6);
+        // misplacing a highly relevant document costs less with a linear gain
+        assert!(linear > exponential);
+
+        // both agree with binary judgments
+        let binary: HashMap<_, _> = vec![("a", 1), ("c", 1)].into_iter().collect();
+        assert_eq!(
+            ndcg_at_k_with_gain(&ranked, &binary, 3, GainKind::Linear),
+            ndcg_at_k_with_gain(&ranked, &binary, 3, GainKind::Exponential)
+        );
+    }
+
+    #[test]
+    fn test_optimize_weights() {
+        let mut rng = Rng::new(4);
+        let mut queries = Vec::new();
+        let mut qrels = Vec::new();
+        for _ in 0..10 {
+            // the first 5 of 20 documents are relevant
+            let docs: Vec<u32> = (0..20).collect();
+            let judgments: HashMap<u32, i32> = docs.iter().map(|&d| (d, (d < 5) as i32)).collect();
+            // the first system ranks most relevant documents high, the
+            // second one ranks documents at random
+            let mut good = docs.clone();
+            good.swap(0, 7);
+            let mut bad = docs.clone();
+            rng.shuffle(&mut bad);
+            let to_list = |ids: Vec<u32>| -> Vec<EntryInfo<u32>> {
+                ids.into_iter()
+                    .enumerate()
+                    .map(|(i, id)| EntryInfo { id, score: score(1. - i as f32 / 20.) })
+                    .collect()
+            };
+            queries.push(vec![to_list(bad), to_list(good)]);
+            qrels.push(judgments);
+        }
+
+        let weights = optimize_weights(&queries, &qrels, average_precision);
+        assert_eq!(weights.len(), 2);
+        assert!(weights[1] > weights[0], "unexpected weights {:?}", weights);
+
+        let map = |weights: &[f32]| -> f32 {
+            let sum: f32 = queries
+                .iter()
+                .zip(&qrels)
+                .map(|(lists, q)| average_precision(&fuse_weighted(lists, weights, comb_sum), q))
+                .sum();
+            sum / queries.len() as f32
+        };
+        assert!(map(&weights) > map(&[1., 1.]));
+    }
+
+    #[test]
+    fn test_oracle_fusion() {
+        let qrels: HashMap<_, _> = vec![("a", 1), ("b", 2), ("c", 1), ("x", 0), ("q", 1)].into_iter().collect();
+        let lists = vec![list(&["x", "y", "c", "a"]), list(&["c", "y", "z", "b"]), list(&["x", "a"])];
+        let oracle = oracle_fusion(&lists, &qrels);
+
+        let ids: Vec<_> = oracle.iter().map(|e| e.id).collect();
+        // b is the most relevant, and c was ranked higher than a
+        assert_eq!(ids, vec!["b", "c", "a", "x", "y", "z"]);
+        let num_relevant = 3;
+        assert!(oracle[..num_relevant].iter().all(|e| is_relevant(&e.id, &qrels)));
+        assert!(oracle[num_relevant..].iter().all(|e| !is_relevant(&e.id, &qrels)));
+        assert_eq!(oracle[0].score, score(6.));
+        assert_eq!(oracle[5].score, score(1.));
+
+        // no fusion of these lists can do better
+        let fused = fuse_scored(lists.iter().flatten(), comb_sum);
+        assert!(average_precision(&oracle, &qrels) >= average_precision(&fused, &qrels));
+    }
+
+    #[test]
+    fn test_fusion_gain() {
+        let qrels: HashMap<_, _> = vec![("a", 1), ("b", 1)].into_iter().collect();
+
+        // each system finds one relevant document at the top
+        let lists = vec![list(&["a", "x", "y"]), list(&["b", "z", "x"])];
+        let fused = fuse_scored(lists.iter().flatten(), comb_sum);
+        let gain = fusion_gain(&lists, &fused, &qrels, average_precision);
+        assert!(gain > 0., "unexpected gain {}", gain);
+
+        // a good system is dragged down by two bad ones agreeing
+        let lists = vec![list(&["a", "b", "x"]), list(&["x", "y", "a"]), list(&["x", "y", "b"])];
+        let fused = fuse_scored(lists.iter().flatten(), comb_sum);
+        let gain = fusion_gain(&lists, &fused, &qrels, |l, q| ndcg_at_k(l, q, 3));
+        assert!(gain < 0., "unexpected gain {}", gain);
+
+        let none: HashMap<&str, i32> = vec![("x", 0)].into_iter().collect();
+        assert_eq!(fusion_gain(&lists, &fused, &none, average_precision), 0.);
+    }
+
+    #[test]
+    fn test_shuffle_within_query() {
+        let data: String = (0..20)
+            .map(|i| format!("30{} 0 d{} {} {} run\n", i % 2, i, i / 2, 10 - i / 2))
+            .collect();
+        let parse = || -> Vec<TrecEntryOwned> {
+            let entries = crate::trec::parse_from_trec(&data).unwrap();
+            entries.iter().map(|e| e.to_owned()).collect()
+        };
+        let run = parse();
+        let shuffle = |seed| {
+            let mut shuffled = parse();
+            shuffle_within_query(&mut shuffled, &mut Rng::new(seed));
+            shuffled
+        };
+
+        let shuffled = shuffle(1);
+        assert_ne!(shuffled, run);
+        assert_eq!(shuffled, shuffle(1));
+        assert_ne!(shuffled, shuffle(2));
+        for (a, b) in run.iter().zip(&shuffled) {
+            assert_eq!((&a.qid, a.rank, a.score), (&b.qid, b.rank, b.score));
+        }
+        // each query has the same documents
+        let docs = |run: &[TrecEntryOwned], qid| {
+            let mut docs: Vec<_> = run.iter().filter(|e| e.qid == qid).map(|e| e.docno.clone()).collect();
+            docs.sort();
+            docs
+        };
+        for &qid in &["300", "301"] {
+            assert_eq!(docs(&shuffled, qid), docs(&run, qid));
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! Score fusion algorithms defined by arithmetic expressions at run time,
+//! such as `sum(scores) * len(scores)` for CombMNZ.
+//!
+//! An expression is evaluated once per document, over the scores that the
+//! document was given. It is made of:
+//!
+//! - numbers, such as `2` or `0.5`;
+//! - `n`, the number of scores;
+//! - `sum(scores)`, `len(scores)`, `max(scores)`, `min(scores)` and
+//!   `mean(scores)`, for the sum, number, highest, lowest and mean of the
+//!   scores (`scores` cannot be used in any other way);
+//! - `ln(x)`, `log2(x)`, `exp(x)`, `sqrt(x)` and `abs(x)` of any
+//!   expression `x`;
+//! - the operators `+`, `-`, `*`, `/` and `^` (power), with the usual
+//!   precedence, unary `-`, and parentheses.
+//!
+//! Operations can be nested up to [`MAX_DEPTH`] levels deep, counting
+//! parentheses, function calls and chained operators alike.
+//!
+//! [`MAX_DEPTH`]: ./constant.MAX_DEPTH.html
+use crate::Score;
+use std::fmt;
+use std::str::FromStr;
+
+/// An error in the syntax of an expression, at a byte position of it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ExprError {
+    /// the byte position of the error in the expression
+    pub position: usize,
+    /// what is wrong
+    pub message: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid expression at position {}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+/// The deepest nesting of operations in an expression, which bounds the
+/// recursion when parsing and evaluating it.
+pub const MAX_DEPTH: usize = 256;
+
+/// An error evaluating an expression: its result is not a number, as with
+/// `ln(-1)` or `0 / 0`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EvalError {
+    /// the scores that the expression was evaluated over
+    pub scores: Vec<Score>,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("expression is not a number for the scores [")?;
+        for (i, s) in self.scores.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", s)?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Aggregate {
+    Sum,
+    Len,
+    Max,
+    Min,
+    Mean,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Function {
+    Ln,
+    Log2,
+    Exp,
+    Sqrt,
+    Abs,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f32),
+    Count,
+    Aggregate(Aggregate),
+    Call(Function, Box<Node>),
+    Neg(Box<Node>),
+    Binary(u8, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn eval(&self, scores: &[Score]) -> f32 {
+        match self {
+            Node::Number(x) => *x,
+            Node::Count => scores.len() as f32,
+            Node::Aggregate(a) => {
+                let values = scores.iter().map(|s| s.raw());
+                match a {
+                    Aggregate::Sum => values.sum(),
+                    Aggregate::Len => scores.len() as f32,
+                    Aggregate::Max => values.fold(None, |m: Option<f32>, x| Some(m.map_or(x, |m| m.max(x)))).unwrap_or(0.),
+                    Aggregate::Min => values.fold(None, |m: Option<f32>, x| Some(m.map_or(x, |m| m.min(x)))).unwrap_or(0.),
+                    Aggregate::Mean if scores.is_empty() => 0.,
+                    Aggregate::Mean => values.sum::<f32>() / scores.len() as f32,
+                }
+            }
+            Node::Call(f, x) => {
+                let x = x.eval(scores);
+                match f {
+                    Function::Ln => x.ln(),
+                    Function::Log2 => x.log2(),
+                    Function::Exp => x.exp(),
+                    Function::Sqrt => x.sqrt(),
+                    Function::Abs => x.abs(),
+                }
+            }
+            Node::Neg(x) => -x.eval(scores),
+            Node::Binary(op, a, b) => {
+                let (a, b) = (a.eval(scores), b.eval(scores));
+                match op {
+                    b'+' => a + b,
+                    b'-' => a - b,
+                    b'*' => a * b,
+                    b'/' => a / b,
+                    _ => a.powf(b),
+                }
+            }
+        }
+    }
+}
+
+/// A score fusion algorithm given by an expression, in the syntax
+/// described in the [module documentation](index.html).
+///
+/// # Example
+///
+/// ```
+/// use vindicator::expr::Expression;
+/// use vindicator::score;
+///
+/// let mnz: Expression = "sum(scores) * len(scores)".parse().unwrap();
+/// assert_eq!(mnz.eval(&[score(0.5), score(1.)]).unwrap(), 3.);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    root: Node,
+}
+
+impl Expression {
+    /// Fuses the scores of a document. Fails if the result is not a number,
+    /// such as `ln(-1)`, whereas infinite results are kept.
+    pub fn eval(&self, scores: &[Score]) -> Result<Score, EvalError> {
+        Score::try_new(self.root.eval(scores)).ok_or_else(|| EvalError { scores: scores.to_vec() })
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { text: s, pos: 0, depth: 0 };
+        let root = parser.expr()?;
+        parser.skip_whitespace();
+        if parser.pos < s.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(Expression { root })
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// the nesting depth at the current position, up to `MAX_DEPTH`
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ExprError {
+        ExprError {
+            position: self.pos,
+            message: message.to_string(),
+        }
+    }
+
+    /// Goes one level deeper, failing past `MAX_DEPTH`.
+    fn enter(&mut self) -> Result<(), ExprError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("expression is nested too deeply"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes the given operator character if it comes next.
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_whitespace();
+        if self.text.as_bytes().get(self.pos) == Some(&c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), ExprError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c as char)))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Node, ExprError> {
+        let depth = self.depth;
+        let mut node = self.term()?;
+        loop {
+            let op = if self.eat(b'+') {
+                b'+'
+            } else if self.eat(b'-') {
+                b'-'
+            } else {
+                self.depth = depth;
+                return Ok(node);
+            };
+            // each operator in a chain nests the previous ones
+            self.enter()?;
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Node, ExprError> {
+        let depth = self.depth;
+        let mut node = self.unary()?;
+        loop {
+            let op = if self.eat(b'*') {
+                b'*'
+            } else if self.eat(b'/') {
+                b'/'
+            } else {
+                self.depth = depth;
+                return Ok(node);
+            };
+            self.enter()?;
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        self.enter()?;
+        let node = if self.eat(b'-') {
+            Node::Neg(Box::new(self.unary()?))
+        } else {
+            let base = self.atom()?;
+            if self.eat(b'^') {
+                // right associative, and binding tighter than unary minus on
+                // its left: -2^2 is -(2^2)
+                Node::Binary(b'^', Box::new(base), Box::new(self.unary()?))
+            } else {
+                base
+            }
+        };
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    fn atom(&mut self) -> Result<Node, ExprError> {
+        self.skip_whitespace();
+        if self.eat(b'(') {
+            let node = self.expr()?;
+            self.expect(b')')?;
+            return Ok(node);
+        }
+        let start = self.pos;
+        let rest = &self.text[start..];
+        let first = match rest.chars().next() {
+            Some(c) => c,
+            None => return Err(self.error("unexpected end of expression")),
+        };
+        if first.is_ascii_digit() || first == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = rest[..len].parse().map_err(|_| self.error("invalid number"))?;
+            self.pos += len;
+            return Ok(Node::Number(number));
+        }
+        if !first.is_ascii_alphabetic() {
+            return Err(self.error(&format!("unexpected `{}`", first)));
+        }
+        let len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        let name = &rest[..len];
+        self.pos += len;
+
+        let aggregate = match name {
+            "sum" => Some(Aggregate::Sum),
+            "len" => Some(Aggregate::Len),
+            "max" => Some(Aggregate::Max),
+            "min" => Some(Aggregate::Min),
+            "mean" => Some(Aggregate::Mean),
+            _ => None,
+        };
+        if let Some(aggregate) = aggregate {
+            self.expect(b'(')?;
+            self.skip_whitespace();
+            if !self.text[self.pos..].starts_with("scores") {
+                return Err(self.error(&format!("`{}` only takes `scores`", name)));
+            }
+            self.pos += "scores".len();
+            self.expect(b')')?;
+            return Ok(Node::Aggregate(aggregate));
+        }
+        let function = match name {
+            "ln" => Function::Ln,
+            "log2" => Function::Log2,
+            "exp" => Function::Exp,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "n" => return Ok(Node::Count),
+            "scores" => {
+                self.pos = start;
+                return Err(self.error("`scores` can only be given to sum, len, max, min or mean"));
+            }
+            _ => {
+                self.pos = start;
+                return Err(self.error(&format!("unknown name `{}`", name)));
+            }
+        };
+        self.expect(b'(')?;
+        let arg = self.expr()?;
+        self.expect(b')')?;
+        Ok(Node::Call(function, Box::new(arg)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuser::{comb_max, comb_mnz, comb_sum};
+    use crate::score;
+
+    fn parse(s: &str) -> Expression {
+        s.parse().unwrap_or_else(|e| panic!("{}: {}", s, e))
+    }
+
+    #[test]
+    fn test_expression_builtins() {
+        let lists = [vec![score(0.5)], vec![score(1.), score(4.), score(-2.)], vec![score(0.); 3]];
+        for scores in &lists {
+            assert_eq!(parse("sum(scores) * len(scores)").eval(scores), Ok(comb_mnz(scores)));
+            assert_eq!(parse("sum( scores )").eval(scores), Ok(comb_sum(scores)));
+            assert_eq!(parse("max(scores)").eval(scores), Ok(comb_max(scores)));
+        }
+        assert_eq!(parse("sum(scores) * n").eval(&[]), Ok(score(0.)));
+    }
+
+    #[test]
+    fn test_expression_syntax() {
+        let scores = [score(1.), score(3.)];
+        let eval = |s| parse(s).eval(&scores).unwrap().raw();
+        assert_eq!(eval("1 + 2 * 3"), 7.);
+        assert_eq!(eval("(1 + 2) * 3"), 9.);
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.);
+        assert_eq!(eval("-2 ^ 2"), -4.);
+        assert_eq!(eval("10 - 4 - 3"), 3.);
+        assert_eq!(eval("mean(scores) / min(scores)"), 2.);
+        assert_eq!(eval("sqrt(abs(-16)) + log2(8) - ln(exp(0))"), 7.);
+        assert_eq!(parse("ln(-1)").eval(&scores), Err(EvalError { scores: scores.to_vec() }));
+        assert_eq!(parse("1 / 0").eval(&scores), Ok(score(f32::INFINITY)));
+
+        let position = |s: &str| s.parse::<Expression>().unwrap_err().position;
+        assert_eq!(position("sum(scores) +"), 13);
+        assert_eq!(position("2 * scores"), 4);
+        assert_eq!(position("sum(n)"), 4);
+        assert_eq!(position("avg(scores)"), 0);
+        assert_eq!(position("(1 + 2"), 6);
+        assert_eq!(position("1 2"), 2);
+        assert_eq!(position("1.2.3"), 0);
+    }
+
+    #[test]
+    fn test_expression_depth() {
+        let nested = |open: &str, close: &str, n: usize| format!("{}1{}", open.repeat(n), close.repeat(n));
+        let depth = MAX_DEPTH / 2;
+        assert_eq!(parse(&nested("(", ")", depth)).eval(&[]), Ok(score(1.)));
+        assert_eq!(parse(&nested("-", "", depth)).eval(&[]), Ok(score(1.)));
+        assert_eq!(parse(&nested("1 + ", "", depth)).eval(&[]).unwrap(), depth as f32 + 1.);
+
+        let message = |s: &str| s.parse::<Expression>().unwrap_err().message;
+        let too_deep = "expression is nested too deeply";
+        assert_eq!(message(&nested("(", ")", 10 * MAX_DEPTH)), too_deep);
+        assert_eq!(message(&nested("abs(", ")", 10 * MAX_DEPTH)), too_deep);
+        assert_eq!(message(&nested("-", "", 10 * MAX_DEPTH)), too_deep);
+        assert_eq!(message(&nested("2 ^ ", "", 10 * MAX_DEPTH)), too_deep);
+        assert_eq!(message(&nested("1 * ", "", 10 * MAX_DEPTH)), too_deep);
+        assert_eq!(message(&nested("1 - ", "", 10 * MAX_DEPTH)), too_deep);
+    }
+}
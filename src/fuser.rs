@@ -1,35 +1,423 @@
 //! Late fusion algorithms.
 
-use crate::{EntryInfo, Rank, RankedSearchEntry, Score, SearchEntry, score};
+use crate::json;
+use crate::normalize::{MinMax, Normalizer};
+use crate::{EntryInfo, Rank, RankedEntryInfo, RankedSearchEntry, RunSearchEntry, Score, SearchEntry, score};
 use noisy_float::prelude::*;
 use smallvec::{smallvec, SmallVec};
-use std::collections::HashMap;
-use std::hash::Hash;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// CombMAX algorithm
 ///
-/// Returns the highest score.
+/// Returns the highest score, or 0 if there are no scores. Note that the
+/// latter is indistinguishable from a document with a highest score of 0.
+/// The fusion functions in this module never call a fusion algorithm
+/// without scores.
 pub fn comb_max(scores: &[Score]) -> Score {
-    scores.into_iter().cloned().max().unwrap_or(n32(0.))
+    scores.iter().cloned().max().unwrap_or(n32(0.))
 }
 
 /// CombSUM algorithm
 ///
-/// Returns the sum of all scores.
+/// Returns the sum of all scores, or 0 if there are no scores.
 pub fn comb_sum(scores: &[Score]) -> Score {
-    scores.into_iter().cloned().sum::<Score>()
+    scores.iter().cloned().sum::<Score>()
+}
+
+/// CombSUM algorithm with compensated summation
+///
+/// Returns the sum of all scores, like [`comb_sum`], but keeps track of the
+/// rounding error of each addition (Kahan-Babuška summation), so that the
+/// error of the result does not grow with the number of scores. This
+/// matters when many small scores are added to a few large ones, as the
+/// small scores may otherwise be lost entirely. It is about four times as
+/// many floating point operations as [`comb_sum`].
+///
+/// [`comb_sum`]: ./fn.comb_sum.html
+pub fn comb_sum_kahan(scores: &[Score]) -> Score {
+    let mut sum = 0.0f32;
+    let mut compensation = 0.0f32;
+    for s in scores {
+        let x = s.raw();
+        let t = sum + x;
+        if sum.abs() >= x.abs() {
+            compensation += (sum - t) + x;
+        } else {
+            compensation += (x - t) + sum;
+        }
+        sum = t;
+    }
+    n32(sum + compensation)
 }
 
 /// CombMNZ algorithm
 ///
-/// Returns the sum of all scores, multiplied by the number of scores.
+/// Returns the sum of all scores, multiplied by the number of scores, or 0
+/// if there are no scores. A single score is returned as is.
 pub fn comb_mnz(scores: &[Score]) -> Score {
     n32(scores.len() as f32) * comb_sum(scores)
 }
 
+/// CombMNZ algorithm with a capped multiplier
+///
+/// Creates a score-based fuser which returns the sum of all scores,
+/// multiplied by the number of scores but at most by `cap`. CombMNZ assumes
+/// that each system is independent evidence of relevance; when several
+/// runs are near-duplicates of one another, such as the same model with
+/// different parameters, a document they all retrieve is rewarded once per
+/// run. Capping the multiplier bounds that reward, while the sum still
+/// accounts for every score. With a cap at least as large as the number of
+/// systems, this is the same as [`comb_mnz`], and with a cap of 1, the same
+/// as [`comb_sum`].
+///
+/// Panics if `cap` is 0.
+///
+/// [`comb_mnz`]: ./fn.comb_mnz.html
+/// [`comb_sum`]: ./fn.comb_sum.html
+pub fn comb_mnz_capped(cap: usize) -> impl Fn(&[Score]) -> Score {
+    assert!(cap > 0, "CombMNZ multiplier cap must be at least 1");
+    move |scores| n32(scores.len().min(cap) as f32) * comb_sum(scores)
+}
+
+/// CombAVG algorithm
+///
+/// Returns the mean of all scores, or 0 if there are no scores. Whereas
+/// [`comb_sum`] rewards documents for being retrieved by more systems, this
+/// only considers how high the systems which retrieved a document scored
+/// it, so a document found by a single system can outrank one found by
+/// many.
+///
+/// [`comb_sum`]: ./fn.comb_sum.html
+pub fn comb_avg(scores: &[Score]) -> Score {
+    if scores.is_empty() {
+        return n32(0.);
+    }
+    comb_sum(scores) / n32(scores.len() as f32)
+}
+
+/// Tuning constant of [`comb_robust`], in units of median absolute
+/// deviation (MAD).
+///
+/// Scores further than `ROBUST_K` MADs away from the median are clamped to
+/// that boundary before summing. Lower values are more aggressive.
+///
+/// [`comb_robust`]: ./fn.comb_robust.html
+pub const ROBUST_K: f32 = 3.;
+
+/// Tuning constant of [`comb_huber`], in units of normalized median absolute
+/// deviation (`1.4826 * MAD`, a consistent estimator of the standard
+/// deviation).
+///
+/// `1.345` is the conventional choice for Huber's estimator, retaining 95%
+/// efficiency on normally distributed scores.
+///
+/// [`comb_huber`]: ./fn.comb_huber.html
+pub const HUBER_K: f32 = 1.345;
+
+/// Robust CombSUM algorithm (winsorized sum)
+///
+/// Returns the sum of all scores, after clamping each score to the range
+/// `median ± ROBUST_K * MAD`. A single extreme score can therefore only
+/// contribute as much as the boundary of that range. When the majority of
+/// scores are identical (MAD is zero), outliers are clamped to the median.
+///
+/// At least three scores are needed for this to differ from [`comb_sum`],
+/// so a single score is returned as is, and no scores yield 0.
+///
+/// [`comb_sum`]: ./fn.comb_sum.html
+pub fn comb_robust(scores: &[Score]) -> Score {
+    let (median, mad) = median_and_mad(scores);
+    let (low, high) = (median - ROBUST_K * mad, median + ROBUST_K * mad);
+    scores
+        .iter()
+        .map(|s| s.raw().max(low).min(high))
+        .map(score)
+        .sum()
+}
+
+/// Huber CombSUM algorithm
+///
+/// Returns the weighted sum of all scores, where scores deviating from the
+/// median by more than `HUBER_K` normalized MADs are downweighted in inverse
+/// proportion to their deviation. The contribution of an extreme score is
+/// thus bounded, rather than growing with its magnitude. When the majority
+/// of scores are identical (MAD is zero), outliers are given no weight.
+/// An infinite score, being infinitely far from a finite median,
+/// contributes the threshold itself, with its sign.
+///
+/// At least three scores are needed for this to differ from [`comb_sum`],
+/// so a single score is returned as is, and no scores yield 0.
+///
+/// [`comb_sum`]: ./fn.comb_sum.html
+pub fn comb_huber(scores: &[Score]) -> Score {
+    let (median, mad) = median_and_mad(scores);
+    let threshold = HUBER_K * 1.4826 * mad;
+    scores
+        .iter()
+        .map(|s| {
+            let deviation = deviation(s.raw(), median);
+            if deviation <= threshold {
+                s.raw()
+            } else if deviation.is_infinite() {
+                s.raw().signum() * threshold
+            } else {
+                s.raw() * threshold / deviation
+            }
+        })
+        .map(score)
+        .sum()
+}
+
+/// Clamping margin of [`comb_logodds`]: scores are clamped to the range
+/// `[LOGODDS_EPSILON, 1 - LOGODDS_EPSILON]`, where the log-odds are finite
+/// (about ±13.8).
+///
+/// [`comb_logodds`]: ./fn.comb_logodds.html
+pub const LOGODDS_EPSILON: f32 = 1e-6;
+
+/// Log-odds CombSUM algorithm
+///
+/// Treats each score as a calibrated probability of relevance, and returns
+/// the sum of their log-odds, `ln(p / (1 - p))`. Under the assumption that
+/// the systems are independent given relevance, and with equal prior odds,
+/// this is the log-odds of relevance given all systems' evidence, which can
+/// be turned back into a probability with `1 / (1 + exp(-x))`. Correlated
+/// systems, such as variants of the same model, break that assumption and
+/// make the result overconfident.
+///
+/// Scores are first clamped to within [`LOGODDS_EPSILON`] of 0 and 1, so
+/// that certain or out of range scores cannot make the sum infinite.
+/// Returns 0, that is, even odds, if there are no scores.
+///
+/// [`LOGODDS_EPSILON`]: ./constant.LOGODDS_EPSILON.html
+pub fn comb_logodds(scores: &[Score]) -> Score {
+    scores
+        .iter()
+        .map(|s| {
+            let p = s.raw().clamp(LOGODDS_EPSILON, 1. - LOGODDS_EPSILON);
+            (p / (1. - p)).ln()
+        })
+        .map(score)
+        .sum()
+}
+
+/// Calculates the median and the median absolute deviation of the given
+/// scores. Both are zero if the slice is empty.
+///
+/// Infinite scores are supported: an infinity deviates nothing from itself,
+/// and the median between opposite infinities is zero.
+fn median_and_mad(scores: &[Score]) -> (f32, f32) {
+    fn median(values: &mut [Score]) -> f32 {
+        if values.is_empty() {
+            return 0.;
+        }
+        values.sort_unstable();
+        let mid = values.len() / 2;
+        if values.len() % 2 == 1 {
+            values[mid].raw()
+        } else {
+            let m = (values[mid - 1].raw() + values[mid].raw()) / 2.;
+            if m.is_nan() {
+                0.
+            } else {
+                m
+            }
+        }
+    }
+
+    let mut values: SmallVec<[Score; 4]> = scores.iter().cloned().collect();
+    let m = median(&mut values);
+    for v in values.iter_mut() {
+        *v = score(deviation(v.raw(), m));
+    }
+    (m, median(&mut values))
+}
+
+/// The absolute difference between a score and the median, which is zero
+/// rather than NaN between equal infinities.
+fn deviation(s: f32, median: f32) -> f32 {
+    if s == median {
+        0.
+    } else {
+        (s - median).abs()
+    }
+}
+
 /// Reciprocal rank fusion algorithm
+///
+/// Returns the sum of `1 / (1 + rank)` over all ranks, or 0 if there are no
+/// ranks.
 pub fn rrf(ranks: &[Rank]) -> Score {
-    ranks.into_iter().map(|&r| 1. / (1. + r as f32)).map(score).sum()
+    ranks.iter().map(|&r| 1. / (1. + r as f32)).map(score).sum()
+}
+
+/// Function pointer type of a score-based fusion algorithm.
+pub type ScoreFuserFn = fn(&[Score]) -> Score;
+
+/// Function pointer type of a rank-based fusion algorithm.
+pub type RankFuserFn = fn(&[Rank]) -> Score;
+
+/// The built-in score-based fusion algorithms, by name.
+const SCORE_FUSERS: &[(&str, ScoreFuserFn)] = &[
+    ("combMAX", comb_max),
+    ("combSUM", comb_sum),
+    ("combMNZ", comb_mnz),
+    ("combAVG", comb_avg),
+    ("combROBUST", comb_robust),
+    ("combHUBER", comb_huber),
+];
+
+/// The built-in rank-based fusion algorithms, by name.
+const RANK_FUSERS: &[(&str, RankFuserFn)] = &[("RRF", rrf)];
+
+/// Looks up a built-in score-based fusion algorithm by name (e.g.
+/// `"combSUM"`), ignoring case.
+pub fn score_fuser(name: &str) -> Option<ScoreFuserFn> {
+    SCORE_FUSERS
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, f)| f)
+}
+
+/// Looks up a built-in rank-based fusion algorithm by name (e.g. `"RRF"`),
+/// ignoring case.
+pub fn rank_fuser(name: &str) -> Option<RankFuserFn> {
+    RANK_FUSERS
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, f)| f)
+}
+
+/// Retrieves the names of all built-in fusion algorithms which can be
+/// looked up with [`score_fuser`] or [`rank_fuser`]: score-based algorithms
+/// first, then rank-based ones.
+///
+/// [`score_fuser`]: ./fn.score_fuser.html
+/// [`rank_fuser`]: ./fn.rank_fuser.html
+pub fn available_fusers() -> Vec<&'static str> {
+    SCORE_FUSERS
+        .iter()
+        .map(|&(n, _)| n)
+        .chain(RANK_FUSERS.iter().map(|&(n, _)| n))
+        .collect()
+}
+
+/// Linear hybrid fusion algorithm
+///
+/// Creates a hybrid fuser which sums `alpha * score + beta / (k + rank)`
+/// across systems, thus linearly combining CombSUM with reciprocal rank
+/// fusion. With `beta = 0`, this is CombSUM scaled by `alpha`. With
+/// `alpha = 0`, `beta = 1` and `k = 1`, this is the same as [`rrf`], since
+/// ranks start at 0.
+///
+/// # Panic
+///
+/// Panics if `beta` is not 0 and `k` is not positive, since the rank term
+/// would be infinite at rank 0. With `beta = 0`, the rank term is skipped
+/// and `k` is not used.
+///
+/// [`rrf`]: ./fn.rrf.html
+pub fn hybrid_linear(alpha: f32, beta: f32, k: f32) -> impl Fn(&[(Rank, Score)]) -> Score {
+    assert!(beta == 0. || k > 0., "k must be positive, got {}", k);
+    move |entries| {
+        entries
+            .iter()
+            .map(|&(r, s)| {
+                let rank_term = if beta == 0. { 0. } else { beta / (k + r as f32) };
+                alpha * s.raw() + rank_term
+            })
+            .map(score)
+            .sum()
+    }
+}
+
+/// Exponential rank decay fusion algorithm
+///
+/// Creates a rank-based fuser which sums `base^rank` across systems, so
+/// that each system contributes 1 for its first result, and geometrically
+/// less for the following ones. Lower bases concentrate the fused score on
+/// the first few ranks of each system. Returns 0 if there are no ranks.
+///
+/// Panics if `base` is not strictly between 0 and 1.
+pub fn exp_decay(base: f32) -> impl Fn(&[Rank]) -> Score {
+    assert!(
+        base > 0. && base < 1.,
+        "exponential decay base must be between 0 and 1, got {}",
+        base
+    );
+    move |ranks| ranks.iter().map(|&r| base.powi(r as i32)).map(score).sum()
+}
+
+/// Borda count fusion algorithm
+///
+/// Creates a rank-based fuser where each system gives
+/// `num_candidates - rank` points to each document that it retrieved, and
+/// the fused score is the sum of all points. Documents at ranks beyond the
+/// number of candidates get no points. The number of candidates is usually
+/// the number of unique documents across all systems.
+///
+/// Unlike reciprocal rank fusion, the points given to a document which a
+/// system did not retrieve depend on where it is assumed to be ranked, see
+/// [`fuse_ranked_multi`].
+///
+/// [`fuse_ranked_multi`]: ./fn.fuse_ranked_multi.html
+pub fn borda(num_candidates: Rank) -> impl Fn(&[Rank]) -> Score {
+    move |ranks| {
+        ranks
+            .iter()
+            .map(|&r| num_candidates.saturating_sub(r) as f32)
+            .map(score)
+            .sum()
+    }
+}
+
+/// Creates a score-based fuser which applies `f` to the fused score of
+/// `fuser`, for example to squash unbounded fused scores into a range
+/// that is easier to display, such as with a sigmoid or a logarithm.
+///
+/// A strictly increasing `f` keeps the order of the fused list, only
+/// changing the scores. Any other function may reorder the documents or
+/// tie them: a decreasing one reverses the ranking, and one which is
+/// constant over a range of scores ties all documents in that range.
+pub fn post_transform<F, T>(fuser: F, f: T) -> impl Fn(&[Score]) -> Score
+where
+    F: Fn(&[Score]) -> Score,
+    T: Fn(Score) -> Score,
+{
+    move |scores| f(fuser(scores))
+}
+
+/// Transforms the document IDs of search results into a canonical form, so
+/// that variants of the same ID are merged together when fused.
+///
+/// Fusion functions such as [`fuse_scored`] group results by exact
+/// document ID, so this must be applied to their input, before the
+/// results are grouped. See [`canonical_id`] for a sensible default
+/// canonicalization function.
+///
+/// [`fuse_scored`]: ./fn.fuse_scored.html
+/// [`canonical_id`]: ./fn.canonical_id.html
+pub fn canonicalize_ids<L, R, F>(results: L, f: F) -> impl Iterator<Item = EntryInfo<String>>
+where
+    L: IntoIterator<Item = R>,
+    R: SearchEntry,
+    R::Id: AsRef<str>,
+    F: Fn(&str) -> String,
+{
+    results.into_iter().map(move |r| EntryInfo {
+        id: f(r.id().as_ref()),
+        score: r.score(),
+    })
+}
+
+/// Default document ID canonicalization function, which removes leading
+/// and trailing whitespace and converts the ID to lowercase.
+pub fn canonical_id(id: &str) -> String {
+    id.trim().to_lowercase()
 }
 
 /// Combines two lists of scored results with a score-based fusion algorithm.
@@ -56,126 +444,2627 @@ where
     fuse_scored(Iterator::chain(results1, results2), fuser)
 }
 
-/// Combines multiple scored results with a score-based fusion algorithm.
-pub fn fuse_scored<I, L, R, F>(results: L, fuser: F) -> Vec<EntryInfo<I>>
+/// Combines multiple results by fusing a signal extracted from each entry,
+/// such as its score or rank, with a fusion algorithm over the signals of
+/// all entries of the same document. The output is sorted by descending
+/// fused score.
+///
+/// This is the common ground of [`fuse_scored`], [`fuse_ranked`] and
+/// [`fuse_hybrid`], and can also fuse on any other signal that an entry
+/// carries.
+///
+/// Documents with the same fused score are ordered by their first
+/// appearance in the input. This does not depend on the signal, so data
+/// which ties under both score-based and rank-based fusion comes out in
+/// the same order from either.
+///
+/// [`fuse_scored`]: ./fn.fuse_scored.html
+/// [`fuse_ranked`]: ./fn.fuse_ranked.html
+/// [`fuse_hybrid`]: ./fn.fuse_hybrid.html
+pub fn fuse_by<I, L, R, T, E, F>(results: L, extract: E, fuser: F) -> Vec<EntryInfo<I>>
 where
     I: Eq + Clone + Hash,
     L: IntoIterator<Item = R>,
     R: SearchEntry<Id = I>,
-    F: Fn(&[Score]) -> Score,
+    E: Fn(&R) -> T,
+    F: Fn(&[T]) -> Score,
 {
-    let mut map: HashMap<I, SmallVec<[_; 4]>> = HashMap::new();
+    rank_by_first_appearance(fuse_by_indexed(results, R::id, extract, fuser), |e| e.score)
+}
 
-    for r in results {
-        if let Some(v) = map.get_mut(r.id()) {
-            v.push(r.score());
-        } else {
-            map.insert(r.id().clone(), smallvec![r.score()]);
-        }
-    }
+/// Does the work of [`fuse_by`], without sorting the output.
+///
+/// [`fuse_by`]: ./fn.fuse_by.html
+fn fuse_by_unsorted<I, L, R, T, E, F>(results: L, extract: E, fuser: F) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    E: Fn(&R) -> T,
+    F: Fn(&[T]) -> Score,
+{
+    fuse_by_indexed(results, R::id, extract, fuser)
+        .into_iter()
+        .map(|(_, e)| e)
+        .collect()
+}
+
+/// Fuses like [`fuse_by`], pairing each fused entry with the position of
+/// the first input entry of its document, for breaking ties. The document
+/// of each entry is given by `id`, so the entries need not be search entries.
+///
+/// [`fuse_by`]: ./fn.fuse_by.html
+fn fuse_by_indexed<I, L, R, K, T, E, F>(results: L, id: K, extract: E, fuser: F) -> Vec<(usize, EntryInfo<I>)>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    K: Fn(&R) -> &I,
+    E: Fn(&R) -> T,
+    F: Fn(&[T]) -> Score,
+{
+    try_fuse_by_indexed(results, id, extract, fuser, |_, _, _| Ok::<_, Infallible>(()))
+        .unwrap_or_else(|e| match e {})
+}
 
-    let mut flat: Vec<_> = map
+/// Fuses like [`fuse_by_indexed`], stopping at the first error of `check`,
+/// which is called before taking in each entry, as in [`try_group_by_id`].
+///
+/// [`fuse_by_indexed`]: ./fn.fuse_by_indexed.html
+/// [`try_group_by_id`]: ./fn.try_group_by_id.html
+fn try_fuse_by_indexed<I, L, R, K, T, E, F, C, X>(
+    results: L,
+    id: K,
+    extract: E,
+    fuser: F,
+    check: C,
+) -> Result<Vec<(usize, EntryInfo<I>)>, X>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    K: Fn(&R) -> &I,
+    E: Fn(&R) -> T,
+    F: Fn(&[T]) -> Score,
+    C: FnMut(usize, bool, usize) -> Result<(), X>,
+{
+    let groups = try_group_by_id(
+        results,
+        id,
+        |r| -> SmallVec<[T; 4]> { smallvec![extract(&r)] },
+        |signals, r| signals.push(extract(&r)),
+        check,
+    )?;
+    Ok(groups
         .into_iter()
-        .map(|(id, scores)| {
+        .map(|(id, (first, signals))| {
+            debug_assert!(!signals.is_empty(), "document without signals");
             // score fusion happens here
-            let score = fuser(&scores);
-            EntryInfo { id, score }
+            let score = fuser(&signals);
+            (first, EntryInfo { id, score })
         })
-        .collect();
-
-    flat.sort_unstable_by_key(|e| -e.score);
-    flat
+        .collect())
 }
 
-/// Combines multiple ranked results with a rank-based fusion algorithm.
-pub fn fuse_ranked<I, L, R, F>(results: L, fuser: F) -> Vec<EntryInfo<I>>
+/// Groups entries by document ID, folding the entries of each document into
+/// a value which starts with `init` of its first entry and takes in each
+/// other entry with `add`. Each group also has the position of its first
+/// entry in the input, for breaking ties.
+fn group_by_id<I, L, R, K, A, N, G>(results: L, id: K, init: N, add: G) -> HashMap<I, (usize, A)>
 where
     I: Eq + Clone + Hash,
     L: IntoIterator<Item = R>,
-    R: RankedSearchEntry<Id = I>,
-    F: Fn(&[Rank]) -> Score,
+    K: Fn(&R) -> &I,
+    N: Fn(R) -> A,
+    G: Fn(&mut A, R),
 {
-    let mut map: HashMap<I, SmallVec<[_; 4]>> = HashMap::new();
+    try_group_by_id(results, id, init, add, |_, _, _| Ok::<_, Infallible>(()))
+        .unwrap_or_else(|e| match e {})
+}
 
-    for r in results {
-        if let Some(v) = map.get_mut(r.id()) {
-            v.push(r.rank());
+/// Groups entries like [`group_by_id`], calling `check` before taking in
+/// each entry, with its position in the input, whether it starts a new
+/// group, and the number of groups so far. Stops at the first error.
+///
+/// [`group_by_id`]: ./fn.group_by_id.html
+fn try_group_by_id<I, L, R, K, A, N, G, C, E>(
+    results: L,
+    id: K,
+    init: N,
+    add: G,
+    mut check: C,
+) -> Result<HashMap<I, (usize, A)>, E>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    K: Fn(&R) -> &I,
+    N: Fn(R) -> A,
+    G: Fn(&mut A, R),
+    C: FnMut(usize, bool, usize) -> Result<(), E>,
+{
+    let mut map: HashMap<I, (usize, A)> = HashMap::new();
+    for (i, r) in results.into_iter().enumerate() {
+        let len = map.len();
+        if let Some((_, group)) = map.get_mut(id(&r)) {
+            check(i, false, len)?;
+            add(group, r);
         } else {
-            map.insert(r.id().clone(), smallvec![r.rank()]);
+            check(i, true, len)?;
+            let key = id(&r).clone();
+            map.insert(key, (i, init(r)));
         }
     }
+    Ok(map)
+}
+
+/// Sorts fused entries by descending score, and documents with the same
+/// score by the position of their first input entry, which is the order of
+/// all fused lists. The positions are dropped.
+fn rank_by_first_appearance<T, S>(mut flat: Vec<(usize, T)>, score: S) -> Vec<T>
+where
+    S: Fn(&T) -> Score,
+{
+    flat.sort_unstable_by_key(|(first, e)| (-score(e), *first));
+    flat.into_iter().map(|(_, e)| e).collect()
+}
+
+/// Combines multiple scored results with a score-based fusion algorithm.
+pub fn fuse_scored<I, L, R, F>(results: L, fuser: F) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    fuse_by(results, R::score, fuser)
+}
+
+/// Combines multiple scored results with a score-based fusion algorithm,
+/// like [`fuse_scored`], but without cloning any document ID: the fused
+/// entries borrow their IDs from the input entries.
+///
+/// The input entries are taken by reference, as in `fuse_scored_ref(&list,
+/// comb_sum)`, and the fused list cannot outlive them. To keep only some of
+/// the fused entries beyond the input, clone their IDs afterwards, with
+/// `EntryInfo { id: e.id.clone(), score: e.score }`. This pays off when IDs
+/// are expensive to clone, such as long strings, and most fused entries
+/// are only needed briefly, for example to be written out or cut off.
+///
+/// [`fuse_scored`]: ./fn.fuse_scored.html
+pub fn fuse_scored_ref<'a, I, L, R, F>(results: L, fuser: F) -> Vec<EntryInfo<&'a I>>
+where
+    I: Eq + Hash + 'a,
+    L: IntoIterator<Item = &'a R>,
+    R: SearchEntry<Id = I> + 'a,
+    F: Fn(&[Score]) -> Score,
+{
+    let borrowed = results.into_iter().map(|r| EntryInfo {
+        id: r.id(),
+        score: r.score(),
+    });
+    fuse_by(borrowed, |e| e.score, fuser)
+}
+
+/// Combines multiple scored results with a score-based fusion algorithm,
+/// like [`fuse_scored`], but only retrieves the `k` documents with the
+/// highest fused scores. This avoids sorting the whole fused list, see
+/// [`top_k_by_score`].
+///
+/// [`fuse_scored`]: ./fn.fuse_scored.html
+/// [`top_k_by_score`]: ../postprocess/fn.top_k_by_score.html
+pub fn fuse_scored_top_k<I, L, R, F>(results: L, fuser: F, k: usize) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    crate::postprocess::top_k_by_score(fuse_by_unsorted(results, R::score, fuser), k)
+}
 
-    let mut flat: Vec<_> = map
+/// Combines multiple scored results with a score-based fusion algorithm,
+/// like [`fuse_scored`], keeping one of the original entries of each fused
+/// document alongside it, so that any other data in the entries, such as a
+/// title or a snippet, is not lost.
+///
+/// The representative entry of a document is the one with the highest
+/// score. Among entries with the same score, the first one in the input is
+/// kept.
+///
+/// [`fuse_scored`]: ./fn.fuse_scored.html
+pub fn fuse_scored_keep<I, L, R, F>(results: L, fuser: F) -> Vec<(EntryInfo<I>, R)>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    let groups = group_by_id(
+        results,
+        R::id,
+        |r| -> (SmallVec<[Score; 4]>, R) { (smallvec![r.score()], r) },
+        |(scores, representative), r| {
+            scores.push(r.score());
+            if r.score() > representative.score() {
+                *representative = r;
+            }
+        },
+    );
+    let flat = groups
         .into_iter()
-        .map(|(id, ranks)| {
+        .map(|(id, (first, (scores, representative)))| {
+            debug_assert!(!scores.is_empty(), "document without scores");
             // score fusion happens here
-            let score = fuser(&ranks);
-            EntryInfo { id, score }
+            let score = fuser(&scores);
+            (first, (EntryInfo { id, score }, representative))
         })
         .collect();
-
-    flat.sort_unstable_by_key(|e| -e.score);
-    flat
+    rank_by_first_appearance(flat, |(e, _)| e.score)
 }
 
-/// Combines multiple ranked results with a fusion algorithm based on both rank
-/// and score.
-pub fn fuse_hybrid<I, L, R, F>(results: L, fuser: F) -> Vec<EntryInfo<I>>
+/// Combines multiple scored results with a score-based fusion algorithm,
+/// like [`fuse_scored`], and ranks the fused list.
+///
+/// Ranks start at 0 for the document with the highest fused score and
+/// follow the order of the output, so that each entry's rank is its
+/// position in the list.
+///
+/// [`fuse_scored`]: ./fn.fuse_scored.html
+pub fn fuse_scored_ranked<I, L, R, F>(results: L, fuser: F) -> Vec<RankedEntryInfo<I>>
 where
     I: Eq + Clone + Hash,
     L: IntoIterator<Item = R>,
-    R: RankedSearchEntry<Id = I>,
-    F: Fn(&[(Rank, Score)]) -> Score,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
 {
-    let mut map: HashMap<I, SmallVec<[_; 4]>> = HashMap::new();
-
-    for r in results {
-        if let Some(v) = map.get_mut(r.id()) {
-            v.push((r.rank(), r.score()));
-        } else {
-            map.insert(r.id().clone(), smallvec![(r.rank(), r.score())]);
-        }
-    }
+    fuse_scored(results, fuser)
+        .into_iter()
+        .enumerate()
+        .map(|(i, e)| RankedEntryInfo {
+            id: e.id,
+            score: e.score,
+            rank: i as Rank,
+        })
+        .collect()
+}
 
-    let mut flat: Vec<_> = map
+/// Combines results with multiple scores per entry (feature vectors), such
+/// as those parsed by [`parse_from_trec_multiscore`], with a score-based
+/// fusion algorithm. The algorithm receives all scores of each document,
+/// across all of its entries, and its output becomes the document's score.
+///
+/// For a single run with pre-aligned score columns, this fuses the columns
+/// of each document: with [`comb_sum`], the columns are simply added up.
+///
+/// [`parse_from_trec_multiscore`]: ../trec/fn.parse_from_trec_multiscore.html
+/// [`comb_sum`]: ./fn.comb_sum.html
+pub fn fuse_features<I, L, S, F>(entries: L, fuser: F) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = (I, S)>,
+    S: AsRef<[Score]>,
+    F: Fn(&[Score]) -> Score,
+{
+    let groups = group_by_id(
+        entries,
+        |(id, _)| id,
+        |(_, features)| -> SmallVec<[Score; 4]> { SmallVec::from_slice(features.as_ref()) },
+        |scores, (_, features)| scores.extend_from_slice(features.as_ref()),
+    );
+    let flat = groups
         .into_iter()
-        .map(|(id, scores)| {
+        .map(|(id, (first, scores))| {
             // score fusion happens here
             let score = fuser(&scores);
-            EntryInfo { id, score }
+            (first, EntryInfo { id, score })
         })
         .collect();
-
-    flat.sort_unstable_by_key(|e| -e.score);
-    flat
+    rank_by_first_appearance(flat, |e| e.score)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::score;
+/// Combines two lists of scored results with a score-based fusion algorithm,
+/// taking a fast path when both lists are sorted by document ID.
+///
+/// If both lists are sorted by ID in ascending order, they are merged in a
+/// single linear pass, without hashing the document IDs. Otherwise, this
+/// falls back to [`fuse_scored_lists`]. Either way, the outcome is the same
+/// as that of [`fuse_scored_lists`], including the order of documents with
+/// equal fused scores.
+///
+/// [`fuse_scored_lists`]: ./fn.fuse_scored_lists.html
+pub fn fuse_two_sorted<I, R1, R2, F>(results1: &[R1], results2: &[R2], fuser: F) -> Vec<EntryInfo<I>>
+where
+    I: Ord + Clone + Hash,
+    R1: SearchEntry<Id = I>,
+    R2: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    let sorted1 = results1.windows(2).all(|w| w[0].id() <= w[1].id());
+    let sorted2 = results2.windows(2).all(|w| w[0].id() <= w[1].id());
+    if !sorted1 || !sorted2 {
+        return fuse_scored_lists(results1, results2, fuser);
+    }
 
-    #[test]
-    fn test_comb_max() {
-        assert_eq!(
-            comb_max(&[score(1.), score(40.), score(0.5), score(12.)]),
-            40.
-        )
+    let mut flat = Vec::with_capacity(results1.len().max(results2.len()));
+    let mut scores: SmallVec<[Score; 4]> = SmallVec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < results1.len() || j < results2.len() {
+        let id = match (results1.get(i), results2.get(j)) {
+            (Some(a), Some(b)) => a.id().min(b.id()),
+            (Some(a), None) => a.id(),
+            (None, Some(b)) => b.id(),
+            (None, None) => unreachable!(),
+        };
+        // position of the document's first entry in both lists chained
+        let first = if results1.get(i).map(|r| r.id()) == Some(id) {
+            i
+        } else {
+            results1.len() + j
+        };
+        // take all entries with this ID from both lists
+        scores.clear();
+        while let Some(r) = results1.get(i).filter(|r| r.id() == id) {
+            scores.push(r.score());
+            i += 1;
+        }
+        while let Some(r) = results2.get(j).filter(|r| r.id() == id) {
+            scores.push(r.score());
+            j += 1;
+        }
+        let score = fuser(&scores);
+        flat.push((first, EntryInfo { id: id.clone(), score }));
     }
+    rank_by_first_appearance(flat, |e| e.score)
+}
 
-    #[test]
-    fn test_comb_sum() {
-        assert_eq!(
-            comb_sum(&[score(1.), score(40.), score(0.5), score(12.)]),
-            53.5
-        )
+/// Error type for fusion procedures which may stop before consuming all
+/// of their input.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FusionError {
+    /// The number of unique documents exceeded the given limit
+    TooManyDocuments {
+        /// the maximum number of unique documents allowed
+        limit: usize,
+        /// the number of result entries read before stopping
+        seen: usize,
+    },
+    /// The fusion was cancelled before it was complete
+    Cancelled,
+}
+
+impl fmt::Display for FusionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FusionError::TooManyDocuments { limit, seen } => write!(
+                f,
+                "too many unique documents (limit is {}, stopped after {} entries)",
+                limit, seen
+            ),
+            FusionError::Cancelled => f.write_str("fusion was cancelled"),
+        }
     }
+}
 
-    #[test]
-    fn test_comb_mnz() {
-        assert_eq!(
-            comb_mnz(&[score(1.), score(40.), score(0.5), score(12.)]),
-            214.
-        )
+impl std::error::Error for FusionError {}
+
+/// Combines multiple scored results with a score-based fusion algorithm,
+/// considering only the documents in `allowed`, such as a fixed set of
+/// candidates to re-rank. All other entries are skipped before they are
+/// accumulated, which is cheaper than fusing everything and filtering the
+/// fused list afterwards.
+pub fn fuse_scored_restricted<I, L, R, F, S>(
+    results: L,
+    fuser: F,
+    allowed: &HashSet<I, S>,
+) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+    S: BuildHasher,
+{
+    let results = results.into_iter().filter(|r| allowed.contains(r.id()));
+    fuse_scored(results, fuser)
+}
+
+/// Combines multiple scored results with a score-based fusion algorithm,
+/// failing if more than `max_unique_docs` distinct documents are found.
+///
+/// This bounds the memory used by the fusion process, which is otherwise
+/// proportional to the number of unique documents in the input.
+pub fn fuse_scored_limited<I, L, R, F>(
+    results: L,
+    fuser: F,
+    max_unique_docs: usize,
+) -> Result<Vec<EntryInfo<I>>, FusionError>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    let flat = try_fuse_by_indexed(results, R::id, R::score, fuser, |i, is_new, len| {
+        if is_new && len >= max_unique_docs {
+            return Err(FusionError::TooManyDocuments {
+                limit: max_unique_docs,
+                seen: i + 1,
+            });
+        }
+        Ok(())
+    })?;
+    Ok(rank_by_first_appearance(flat, |e| e.score))
+}
+
+/// Type alias for the identifier of a system taking part in a fusion: its
+/// index in the order that the systems' lists were given.
+pub type SystemId = usize;
+
+/// The number of result entries accumulated between each check of the
+/// cancellation flag in [`fuse_scored_with_cancel`].
+///
+/// [`fuse_scored_with_cancel`]: ./fn.fuse_scored_with_cancel.html
+pub const CANCEL_CHECK_INTERVAL: usize = 1024;
+
+/// Combines multiple scored results with a score-based fusion algorithm,
+/// stopping early if the given flag is set (e.g. by another thread, when a
+/// deadline is reached).
+///
+/// The flag is checked before reading the first entry, then once every
+/// [`CANCEL_CHECK_INTERVAL`] entries, and once more before sorting the
+/// fused list. Hence, at most `CANCEL_CHECK_INTERVAL` entries are read
+/// after the flag is set.
+///
+/// [`CANCEL_CHECK_INTERVAL`]: ./constant.CANCEL_CHECK_INTERVAL.html
+pub fn fuse_scored_with_cancel<I, L, R, F>(
+    results: L,
+    fuser: F,
+    cancel: &AtomicBool,
+) -> Result<Vec<EntryInfo<I>>, FusionError>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    let flat = try_fuse_by_indexed(results, R::id, R::score, fuser, |i, _, _| {
+        if i % CANCEL_CHECK_INTERVAL == 0 && cancel.load(Ordering::Relaxed) {
+            return Err(FusionError::Cancelled);
+        }
+        Ok(())
+    })?;
+
+    if cancel.load(Ordering::Relaxed) {
+        return Err(FusionError::Cancelled);
+    }
+    Ok(rank_by_first_appearance(flat, |e| e.score))
+}
+
+/// A single system's contribution to a document's fused score.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Contribution {
+    /// the index of the contributing system
+    pub system: SystemId,
+    /// the rank attributed by the system
+    pub rank: Rank,
+    /// the score attributed by the system
+    pub score: Score,
+    /// the index of the run ID of the entry, if known (see
+    /// [`ScoreAccumulator::runid`])
+    ///
+    /// [`ScoreAccumulator::runid`]: ./struct.ScoreAccumulator.html#method.runid
+    pub run: Option<usize>,
+}
+
+/// An accumulator of search results from multiple systems, one list at a
+/// time. Unlike the single-list fusion functions, this keeps track of which
+/// system contributed each score to a document, and of how many systems
+/// took part in the fusion.
+#[derive(Debug, Clone)]
+pub struct ScoreAccumulator<I>
+where
+    I: Eq + Hash,
+{
+    /// the contributions to each document, with the number of entries
+    /// pushed before its first one, for breaking ties
+    contributions: HashMap<I, (usize, SmallVec<[Contribution; 4]>)>,
+    num_entries: usize,
+    num_systems: usize,
+    runids: Vec<String>,
+}
+
+impl<I> Default for ScoreAccumulator<I>
+where
+    I: Eq + Hash,
+{
+    fn default() -> Self {
+        ScoreAccumulator {
+            contributions: HashMap::new(),
+            num_entries: 0,
+            num_systems: 0,
+            runids: Vec::new(),
+        }
+    }
+}
+
+impl<I> ScoreAccumulator<I>
+where
+    I: Eq + Clone + Hash,
+{
+    /// Creates an empty accumulator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the results of a new system, ranked by their order of
+    /// appearance. Returns the index attributed to the system.
+    pub fn add_list<L, R>(&mut self, results: L) -> usize
+    where
+        L: IntoIterator<Item = R>,
+        R: SearchEntry<Id = I>,
+    {
+        self.add_ranked_list(crate::ranked_list(results).map(|r| RankedEntryInfo {
+            id: r.id().clone(),
+            score: r.score(),
+            rank: r.rank,
+        }))
+    }
+
+    /// Adds the results of a new system, using the ranks that they already
+    /// have. Returns the index attributed to the system.
+    pub fn add_ranked_list<L, R>(&mut self, results: L) -> usize
+    where
+        L: IntoIterator<Item = R>,
+        R: RankedSearchEntry<Id = I>,
+    {
+        let system = self.num_systems;
+        self.num_systems += 1;
+        for r in results {
+            let c = Contribution {
+                system,
+                rank: r.rank(),
+                score: r.score(),
+                run: None,
+            };
+            self.push(r.id(), c);
+        }
+        system
+    }
+
+    /// Adds the results of a new system, using the ranks that they already
+    /// have, and keeping track of the run ID of each entry. Returns the
+    /// index attributed to the system.
+    pub fn add_run_list<L, R>(&mut self, results: L) -> usize
+    where
+        L: IntoIterator<Item = R>,
+        R: RunSearchEntry<Id = I>,
+    {
+        let system = self.num_systems;
+        self.num_systems += 1;
+        for r in results {
+            let run = match self.runids.iter().position(|x| x == r.runid()) {
+                Some(run) => run,
+                None => {
+                    self.runids.push(r.runid().to_string());
+                    self.runids.len() - 1
+                }
+            };
+            let c = Contribution {
+                system,
+                rank: r.rank(),
+                score: r.score(),
+                run: Some(run),
+            };
+            self.push(r.id(), c);
+        }
+        system
+    }
+
+    /// Adds all results of another accumulator, as if its systems had been
+    /// added to this one after the existing systems, in the same order. The
+    /// systems of `other` are renumbered accordingly, so that fusing the
+    /// merged accumulator is the same as adding all lists to a single
+    /// accumulator.
+    pub fn merge(&mut self, other: Self) {
+        let offset = self.num_systems;
+        self.num_systems += other.num_systems;
+        let runs: Vec<usize> = other
+            .runids
+            .into_iter()
+            .map(|runid| match self.runids.iter().position(|x| *x == runid) {
+                Some(run) => run,
+                None => {
+                    self.runids.push(runid);
+                    self.runids.len() - 1
+                }
+            })
+            .collect();
+        let entry_offset = self.num_entries;
+        self.num_entries += other.num_entries;
+        for (id, (first, contributions)) in other.contributions {
+            let contributions = contributions.into_iter().map(|c| Contribution {
+                system: c.system + offset,
+                run: c.run.map(|run| runs[run]),
+                ..c
+            });
+            self.contributions
+                .entry(id)
+                .or_insert_with(|| (entry_offset + first, SmallVec::new()))
+                .1
+                .extend(contributions);
+        }
+    }
+
+    fn push(&mut self, id: &I, c: Contribution) {
+        if let Some((_, v)) = self.contributions.get_mut(id) {
+            v.push(c);
+        } else {
+            self.contributions.insert(id.clone(), (self.num_entries, smallvec![c]));
+        }
+        self.num_entries += 1;
+    }
+
+    /// Retrieves the run ID of the entry behind a contribution, if it was
+    /// added with [`add_run_list`].
+    ///
+    /// [`add_run_list`]: #method.add_run_list
+    pub fn runid(&self, contribution: &Contribution) -> Option<&str> {
+        contribution.run.map(|run| &*self.runids[run])
+    }
+
+    /// Retrieves the number of systems added so far.
+    pub fn num_systems(&self) -> usize {
+        self.num_systems
+    }
+
+    /// Retrieves the number of unique documents found so far.
+    pub fn num_documents(&self) -> usize {
+        self.contributions.len()
+    }
+
+    /// Retrieves all contributions to the document with the given ID, in
+    /// the order that they were added.
+    pub fn contributions(&self, id: &I) -> &[Contribution] {
+        self.contributions.get(id).map(|(_, v)| &v[..]).unwrap_or(&[])
+    }
+
+    /// Describes how the document with the given ID would be scored by a
+    /// score-based fusion algorithm. A document which was not found has no
+    /// contributions and a fused score of 0, without calling the algorithm.
+    pub fn explain<F>(&self, id: &I, fuser: F) -> Explanation
+    where
+        F: Fn(&[Score]) -> Score,
+    {
+        let contributions = self.contributions(id);
+        let scores: SmallVec<[_; 4]> = contributions.iter().map(|c| c.score).collect();
+        let fused = if scores.is_empty() { n32(0.) } else { fuser(&scores) };
+        Explanation {
+            per_system: contributions
+                .iter()
+                .map(|c| (c.system, c.rank, c.score))
+                .collect(),
+            runids: contributions
+                .iter()
+                .map(|c| self.runid(c).map(str::to_string))
+                .collect(),
+            fused,
+        }
+    }
+
+    /// Iterates over all documents found and their contributions, in no
+    /// particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&I, &[Contribution])> {
+        self.contributions.iter().map(|(id, (_, v))| (id, &v[..]))
+    }
+
+    /// Fuses the accumulated results with a score-based fusion algorithm.
+    /// Documents with the same fused score are in the order that they were
+    /// first added.
+    pub fn finish<F>(self, fuser: F) -> Vec<EntryInfo<I>>
+    where
+        F: Fn(&[Score]) -> Score,
+    {
+        let flat = self
+            .contributions
+            .into_iter()
+            .map(|(id, (first, contributions))| {
+                let scores: SmallVec<[_; 4]> = contributions.iter().map(|c| c.score).collect();
+                debug_assert!(!scores.is_empty(), "document without scores");
+                // score fusion happens here
+                let score = fuser(&scores);
+                (first, EntryInfo { id, score })
+            })
+            .collect();
+        rank_by_first_appearance(flat, |e| e.score)
+    }
+}
+
+/// A fused ranking which can be updated incrementally, when the results of
+/// one of the fused systems change.
+///
+/// This keeps the contributions of every system to every document, so that
+/// replacing the list of one system only recomputes the fused scores of the
+/// documents in the old or in the new list, instead of fusing everything
+/// again.
+#[derive(Debug)]
+pub struct FusionState<I, F>
+where
+    I: Eq + Hash,
+{
+    acc: ScoreAccumulator<I>,
+    fuser: F,
+    fused: HashMap<I, Score>,
+    system_docs: Vec<HashSet<I>>,
+}
+
+impl<I, F> FusionState<I, F>
+where
+    I: Eq + Clone + Hash,
+    F: Fn(&[Score]) -> Score,
+{
+    /// Fuses the results accumulated so far with a score-based fusion
+    /// algorithm, keeping them for later updates.
+    pub fn new(acc: ScoreAccumulator<I>, fuser: F) -> Self {
+        let mut system_docs = vec![HashSet::new(); acc.num_systems];
+        for (id, (_, contributions)) in &acc.contributions {
+            for c in contributions {
+                system_docs[c.system].insert(id.clone());
+            }
+        }
+        let mut state = FusionState {
+            acc,
+            fuser,
+            fused: HashMap::new(),
+            system_docs,
+        };
+        let ids: Vec<I> = state.acc.contributions.keys().cloned().collect();
+        for id in ids {
+            state.refuse(id);
+        }
+        state
+    }
+
+    /// Replaces the results of a system, ranked by their order of
+    /// appearance, and updates the fused scores of the affected documents.
+    ///
+    /// # Panic
+    ///
+    /// Panics if there is no system with the given index.
+    pub fn replace_system<L, R>(&mut self, system: SystemId, results: L)
+    where
+        L: IntoIterator<Item = R>,
+        R: SearchEntry<Id = I>,
+    {
+        assert!(system < self.acc.num_systems, "no such system {}", system);
+        let mut affected = std::mem::take(&mut self.system_docs[system]);
+        for id in &affected {
+            if let Some((_, contributions)) = self.acc.contributions.get_mut(id) {
+                contributions.retain(|c| c.system != system);
+                if contributions.is_empty() {
+                    self.acc.contributions.remove(id);
+                }
+            }
+        }
+
+        let mut docs = HashSet::new();
+        for (i, r) in results.into_iter().enumerate() {
+            let c = Contribution {
+                system,
+                rank: i as Rank,
+                score: r.score(),
+                run: None,
+            };
+            self.acc.push(r.id(), c);
+            docs.insert(r.id().clone());
+        }
+        affected.extend(docs.iter().cloned());
+        self.system_docs[system] = docs;
+
+        for id in affected {
+            self.refuse(id);
+        }
+    }
+
+    fn refuse(&mut self, id: I) {
+        match self.acc.contributions.get(&id) {
+            Some((_, contributions)) => {
+                let scores: SmallVec<[_; 4]> = contributions.iter().map(|c| c.score).collect();
+                self.fused.insert(id, (self.fuser)(&scores));
+            }
+            None => {
+                self.fused.remove(&id);
+            }
+        }
+    }
+
+    /// Retrieves the current fused list, sorted by score. Documents with the
+    /// same score are in the order that they were first added, across all
+    /// updates.
+    pub fn ranking(&self) -> Vec<EntryInfo<I>> {
+        let flat = self
+            .fused
+            .iter()
+            .map(|(id, &score)| {
+                let first = self.acc.contributions[id].0;
+                (first, EntryInfo { id: id.clone(), score })
+            })
+            .collect();
+        rank_by_first_appearance(flat, |e| e.score)
+    }
+}
+
+/// A breakdown of a document's fused score, for explaining its position in
+/// the fused list.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Explanation {
+    /// the system, rank and score of each contribution to the document
+    pub per_system: Vec<(SystemId, Rank, Score)>,
+    /// the run ID behind each contribution in `per_system`, if known
+    pub runids: Vec<Option<String>>,
+    /// the document's fused score
+    pub fused: Score,
+}
+
+impl Explanation {
+    /// Serializes this explanation into a JSON object of the form
+    /// `{"per_system": [{"system": 0, "rank": 0, "score": 1.5}], "fused": 1.5}`.
+    /// Contributions with a known run ID also include a `"runid"` field.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"per_system\": [");
+        for (i, &(system, rank, score)) in self.per_system.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            out.push_str(&format!("{{\"system\": {}, \"rank\": {}, \"score\": ", system, rank));
+            json::write_number(&mut out, score.raw());
+            if let Some(Some(runid)) = self.runids.get(i) {
+                out.push_str(", \"runid\": ");
+                json::write_string(&mut out, runid);
+            }
+            out.push('}');
+        }
+        out.push_str("], \"fused\": ");
+        json::write_number(&mut out, self.fused.raw());
+        out.push('}');
+        out
+    }
+}
+
+/// Describes how the document with the given ID is scored when fusing the
+/// results of multiple systems, one list per system, with a score-based
+/// fusion algorithm. Systems are identified by the position of their list.
+pub fn explain<I, LL, L, R, F>(id: &I, lists: LL, fuser: F) -> Explanation
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    let mut acc = ScoreAccumulator::new();
+    for list in lists {
+        acc.add_list(list);
+    }
+    acc.explain(id, fuser)
+}
+
+/// Combines the scored results of multiple systems, one list per system,
+/// with a score-based fusion algorithm.
+pub fn fuse_scored_multi<I, LL, L, R, F>(lists: LL, fuser: F) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    let mut acc = ScoreAccumulator::new();
+    for list in lists {
+        acc.add_list(list);
+    }
+    acc.finish(fuser)
+}
+
+/// How rank-based fusion treats the systems which did not retrieve a
+/// document, in [`fuse_ranked_multi`].
+///
+/// [`fuse_ranked_multi`]: ./fn.fuse_ranked_multi.html
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Unretrieved {
+    /// Leave the systems out of the document's ranks, so that only the
+    /// ranks of the systems which retrieved it are fused. This is what
+    /// [`fuse_ranked`] does, and is the same as a rank at infinity for
+    /// fusers such as RRF, where a rank's contribution vanishes as the rank
+    /// grows.
+    ///
+    /// [`fuse_ranked`]: ./fn.fuse_ranked.html
+    Omit,
+    /// Rank the document right after the end of each system's list, that
+    /// is, at the number of entries in the list. This assumes that all
+    /// unretrieved documents are tied below the retrieved ones, as in the
+    /// classic Borda count over truncated lists.
+    AfterLast,
+    /// Rank the document at this rank for each system.
+    Rank(Rank),
+}
+
+/// Combines the ranked results of multiple systems, one list per system,
+/// with a rank-based fusion algorithm, using the ranks that the results
+/// already have. Each system which did not retrieve a document contributes
+/// to it according to `unretrieved`.
+///
+/// With [`Unretrieved::Omit`], this is the same as fusing all lists with
+/// [`fuse_ranked`].
+///
+/// [`Unretrieved::Omit`]: ./enum.Unretrieved.html#variant.Omit
+/// [`fuse_ranked`]: ./fn.fuse_ranked.html
+pub fn fuse_ranked_multi<I, LL, L, R, F>(lists: LL, fuser: F, unretrieved: Unretrieved) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: RankedSearchEntry<Id = I>,
+    F: Fn(&[Rank]) -> Score,
+{
+    let lists: Vec<Vec<R>> = lists.into_iter().map(|list| list.into_iter().collect()).collect();
+    let lens: Vec<usize> = lists.iter().map(Vec::len).collect();
+    // the system and rank behind each contribution to each document
+    let contributions = lists
+        .into_iter()
+        .enumerate()
+        .flat_map(|(system, list)| list.into_iter().map(move |r| (system, r)));
+    let flat = fuse_by_indexed(
+        contributions,
+        |(_, r): &(SystemId, R)| r.id(),
+        |(system, r)| (*system, r.rank()),
+        |contributions: &[(SystemId, Rank)]| {
+            let mut ranks: SmallVec<[Rank; 4]> = contributions.iter().map(|&(_, r)| r).collect();
+            if unretrieved != Unretrieved::Omit {
+                for (system, &len) in lens.iter().enumerate() {
+                    if contributions.iter().all(|&(s, _)| s != system) {
+                        ranks.push(match unretrieved {
+                            Unretrieved::Rank(rank) => rank,
+                            _ => len as Rank,
+                        });
+                    }
+                }
+            }
+            fuser(&ranks)
+        },
+    );
+    rank_by_first_appearance(flat, |e| e.score)
+}
+
+/// Splits a list of search results into one list per run ID, in order of
+/// first appearance, so that each run can be fused as a separate system.
+///
+/// The score-based fusion functions over a single list do not tell systems
+/// apart, so a file containing multiple runs would otherwise be fused as if
+/// it came from a single system. The output can be passed to the functions
+/// over multiple lists, such as [`fuse_scored_multi`].
+///
+/// [`fuse_scored_multi`]: ./fn.fuse_scored_multi.html
+pub fn group_by_runid<I, L, R>(entries: L) -> Vec<Vec<EntryInfo<I>>>
+where
+    I: Clone,
+    L: IntoIterator<Item = R>,
+    R: RunSearchEntry<Id = I>,
+{
+    let mut runids: Vec<String> = Vec::new();
+    let mut groups: Vec<Vec<EntryInfo<I>>> = Vec::new();
+    for r in entries {
+        let i = match runids.iter().position(|x| x == r.runid()) {
+            Some(i) => i,
+            None => {
+                runids.push(r.runid().to_string());
+                groups.push(Vec::new());
+                groups.len() - 1
+            }
+        };
+        groups[i].push(r.to_entry());
+    }
+    groups
+}
+
+/// A dense document-by-system score matrix: the document IDs (one per row),
+/// the system names (one per column), and the rows of scores, with `None`
+/// where a system did not retrieve the document.
+pub type ScoreMatrix<I> = (Vec<I>, Vec<String>, Vec<Vec<Option<Score>>>);
+
+/// Builds the document-by-system score matrix of the results of multiple
+/// systems, one list per system, for use in matrix-based fusion methods.
+///
+/// Rows are sorted by document ID. Each system is named after the run ID of
+/// the first entry of its list, or after its index if the list is empty. If
+/// a system retrieves the same document more than once, only the first score
+/// is kept.
+///
+/// The matrix is dense: with `n` unique documents and `m` systems, its
+/// scores take `n * m * size_of::<Option<Score>>()` bytes (8 bytes per
+/// cell), even when most systems retrieve few of the documents.
+pub fn to_score_matrix<I, LL, L, R>(lists: LL) -> ScoreMatrix<I>
+where
+    I: Ord + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: RunSearchEntry<Id = I>,
+{
+    let mut acc = ScoreAccumulator::new();
+    let mut systems = Vec::new();
+    for list in lists {
+        let mut list = list.into_iter().peekable();
+        let name = match list.peek() {
+            Some(r) => r.runid().to_string(),
+            None => systems.len().to_string(),
+        };
+        systems.push(name);
+        acc.add_run_list(list);
+    }
+
+    let mut ids: Vec<I> = acc.contributions.keys().cloned().collect();
+    ids.sort();
+    let rows = ids
+        .iter()
+        .map(|id| {
+            let mut row = vec![None; systems.len()];
+            for c in acc.contributions(id) {
+                row[c.system].get_or_insert(c.score);
+            }
+            row
+        })
+        .collect();
+    (ids, systems, rows)
+}
+
+/// Merges multiple lists into their union, keeping only the highest score
+/// of each document, for deduplicating results rather than fusing them.
+///
+/// This is equivalent to [`fuse_scored`] with [`comb_max`] over all lists
+/// chained together, and so the output is sorted by score as well.
+///
+/// [`fuse_scored`]: ./fn.fuse_scored.html
+/// [`comb_max`]: ./fn.comb_max.html
+pub fn union_max<I, LL, L, R>(lists: LL) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+{
+    fuse_scored(lists.into_iter().flatten(), comb_max)
+}
+
+/// Combines the scored results of multiple systems, one list per system,
+/// keeping only the documents retrieved by every system.
+///
+/// This is a strict form of a minimum support threshold, in which documents
+/// retrieved by at least a given number of systems are kept: here, the
+/// threshold is always the number of input lists, so any document missing
+/// from one of them is dropped. An empty output therefore means that the
+/// systems have no document in common.
+pub fn fuse_intersection<I, LL, L, R, F>(lists: LL, fuser: F) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    let mut acc = ScoreAccumulator::new();
+    for list in lists {
+        acc.add_list(list);
+    }
+    let num_systems = acc.num_systems;
+    acc.contributions.retain(|_, (_, contributions)| {
+        // contributions are grouped by system, in the order they were added
+        let systems = 1 + contributions
+            .windows(2)
+            .filter(|w| w[0].system != w[1].system)
+            .count();
+        systems == num_systems
+    });
+    acc.finish(fuser)
+}
+
+/// Combines the scored results of multiple systems, one list per system,
+/// with a score-based fusion algorithm, after multiplying the scores of
+/// each system by its weight.
+///
+/// `weights[i]` is the weight of the `i`-th list. Lists without a weight
+/// are given a weight of 1.
+pub fn fuse_weighted<I, LL, L, R, F>(lists: LL, weights: &[f32], fuser: F) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    let mut acc = ScoreAccumulator::new();
+    for (i, list) in lists.into_iter().enumerate() {
+        let weight = n32(weights.get(i).cloned().unwrap_or(1.));
+        acc.add_list(list.into_iter().map(|r| EntryInfo {
+            id: r.id().clone(),
+            score: r.score() * weight,
+        }));
+    }
+    acc.finish(fuser)
+}
+
+/// The fusion of a pair of systems, by their list positions.
+pub type PairFusion<I> = ((SystemId, SystemId), Vec<EntryInfo<I>>);
+
+/// Combines the scored results of every pair of systems, one list per
+/// system, with a score-based fusion algorithm, for comparing the
+/// combinations of systems against each other.
+///
+/// Each pair of list positions `(i, j)`, with `i < j`, is returned along
+/// with the fusion of the two lists, in order of `i` and then `j`. There
+/// are no pairs with fewer than two lists.
+pub fn all_pairs_fusion<I, LL, L, R, F>(lists: LL, fuser: F) -> Vec<PairFusion<I>>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    F: Fn(&[Score]) -> Score,
+{
+    let lists: Vec<Vec<EntryInfo<I>>> = lists
+        .into_iter()
+        .map(|list| list.into_iter().map(|r| r.to_entry()).collect())
+        .collect();
+    let mut out = Vec::with_capacity(lists.len() * lists.len().saturating_sub(1) / 2);
+    for i in 0..lists.len() {
+        for j in i + 1..lists.len() {
+            let fused = fuse_scored(lists[i].iter().chain(&lists[j]), &fuser);
+            out.push(((i, j), fused));
+        }
+    }
+    out
+}
+
+/// Combines scored results with a score-based fusion algorithm separately
+/// within each group of results, where the group of each result is given by
+/// `key`. Results are only fused with other results of the same group.
+///
+/// Per-query fusion is the special case of grouping by query ID, for
+/// example `fuse_grouped(&entries, |e| e.qid, comb_sum)` for TREC entries,
+/// but results can be grouped by anything else that they carry, such as a
+/// user, a session or a language. The key can also be transformed, as in
+/// `|e| e.qid.split('.').next()` to fuse the results of the sub-topics
+/// `301.1` and `301.2` together under the topic `301`.
+pub fn fuse_grouped<I, K, L, R, G, F>(results: L, key: G, fuser: F) -> HashMap<K, Vec<EntryInfo<I>>>
+where
+    I: Eq + Clone + Hash,
+    K: Eq + Hash,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+    G: Fn(&R) -> K,
+    F: Fn(&[Score]) -> Score,
+{
+    let mut groups: HashMap<K, Vec<R>> = HashMap::new();
+    for r in results {
+        groups.entry(key(&r)).or_default().push(r);
+    }
+    groups
+        .into_iter()
+        .map(|(k, group)| (k, fuse_scored(group, &fuser)))
+        .collect()
+}
+
+/// Estimates the reliability of multiple systems, one list per system, by
+/// how well each system's ranking agrees with the consensus of all systems.
+/// This requires no relevance judgments. The output contains one weight per
+/// list, for use in [`fuse_weighted`].
+///
+/// The consensus is the centroid ranking: the average rank of each document
+/// over all systems, where a document missing from a list is ranked right
+/// after the last entry of that list. The weight of a system is the Pearson
+/// correlation between its ranks and the average ranks, over all documents
+/// retrieved by any system. Systems which disagree with the consensus
+/// (negative correlation), or whose correlation is undefined because all of
+/// their ranks are equal, receive a weight of 0. Ranks are taken from each
+/// entry's position in its list.
+///
+/// This takes `O(n * m)` time and memory, for `n` unique documents and `m`
+/// systems.
+///
+/// [`fuse_weighted`]: ./fn.fuse_weighted.html
+pub fn agreement_weights<I, LL, L, R>(lists: LL) -> Vec<f32>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+{
+    let mut acc = ScoreAccumulator::new();
+    let mut lengths = Vec::new();
+    for list in lists {
+        let list: Vec<_> = list.into_iter().collect();
+        lengths.push(list.len());
+        acc.add_list(list);
+    }
+    let num_systems = lengths.len();
+    if num_systems == 0 {
+        return Vec::new();
+    }
+
+    // rank of each document in each system
+    let ranks: Vec<Vec<f32>> = acc
+        .iter()
+        .map(|(_, contributions)| {
+            let mut row: Vec<Option<f32>> = vec![None; num_systems];
+            for c in contributions {
+                row[c.system].get_or_insert(c.rank as f32);
+            }
+            row.iter()
+                .zip(&lengths)
+                .map(|(r, &len)| r.unwrap_or(len as f32))
+                .collect()
+        })
+        .collect();
+    let centroid: Vec<f32> = ranks
+        .iter()
+        .map(|row| row.iter().sum::<f32>() / num_systems as f32)
+        .collect();
+
+    (0..num_systems)
+        .map(|s| {
+            let n = ranks.len() as f32;
+            let mean_x = ranks.iter().map(|row| row[s]).sum::<f32>() / n;
+            let mean_y = centroid.iter().sum::<f32>() / n;
+            let (mut cov, mut var_x, mut var_y) = (0., 0., 0.);
+            for (row, &y) in ranks.iter().zip(&centroid) {
+                let (dx, dy) = (row[s] - mean_x, y - mean_y);
+                cov += dx * dy;
+                var_x += dx * dx;
+                var_y += dy * dy;
+            }
+            if var_x > 0. && var_y > 0. {
+                (cov / (var_x * var_y).sqrt()).max(0.)
+            } else {
+                0.
+            }
+        })
+        .collect()
+}
+
+/// Combines the results of heterogeneous systems, some of which provide
+/// meaningful scores (`score_lists`) and others only ranks (`rank_lists`),
+/// one list per system.
+///
+/// The scores of each score-based system are min-max normalized into the
+/// range `[0, 1]` (see [`MinMax`]), while each entry of a rank-based system
+/// is given the reciprocal rank score `1 / (k + rank)`, ignoring its own
+/// score. All of these are then summed, as in CombSUM. Since ranks start at
+/// 0, `k = 1` gives the top document of every system a score of 1, whereas
+/// larger values of `k` lower the influence of the rank-based systems.
+///
+/// # Panic
+///
+/// Panics if `k` is not positive, since the reciprocal rank score would be
+/// infinite at rank 0.
+///
+/// [`MinMax`]: ../normalize/struct.MinMax.html
+pub fn fuse_mixed<I, SL, L1, R1, RL, L2, R2>(score_lists: SL, rank_lists: RL, k: f32) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    SL: IntoIterator<Item = L1>,
+    L1: IntoIterator<Item = R1>,
+    R1: SearchEntry<Id = I>,
+    RL: IntoIterator<Item = L2>,
+    L2: IntoIterator<Item = R2>,
+    R2: RankedSearchEntry<Id = I>,
+{
+    assert!(k > 0., "k must be positive, got {}", k);
+    let mut entries = Vec::new();
+    for list in score_lists {
+        let mut list: Vec<_> = list.into_iter().map(|r| r.to_entry()).collect();
+        MinMax.normalize(&mut list);
+        entries.extend(list);
+    }
+    for list in rank_lists {
+        entries.extend(list.into_iter().map(|r| EntryInfo {
+            id: r.id().clone(),
+            score: n32(1. / (k + r.rank() as f32)),
+        }));
+    }
+    fuse_scored(entries, comb_sum)
+}
+
+/// The fusion method chosen by [`fuse_auto`].
+///
+/// [`fuse_auto`]: ./fn.fuse_auto.html
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AutoMethod {
+    /// CombSUM over min-max normalized scores, when all systems have scores
+    NormalizedCombSum,
+    /// Reciprocal rank fusion, when no system has scores
+    Rrf,
+    /// [`fuse_mixed`], when only some systems have scores
+    ///
+    /// [`fuse_mixed`]: ./fn.fuse_mixed.html
+    Mixed,
+}
+
+/// Combines the results of multiple systems, one list per system, with a
+/// fusion method chosen from the signals that the systems provide. Returns
+/// the chosen method along with the fused list.
+///
+/// A system is considered to provide meaningful scores unless all of its
+/// scores are equal, as is the case for lists which only carry ranks.
+/// Lists with fewer than two entries are not considered either way.
+///
+/// - If every system has scores, they are min-max normalized and fused
+///   with CombSUM.
+/// - If no system has scores, the lists are fused with [`rrf`]. This is also
+///   the case when all lists have fewer than two entries, since there are
+///   no scores to go by.
+/// - Otherwise, they are fused with [`fuse_mixed`], with `k = 1`.
+///
+/// This is meant as a reasonable default for getting started. Since the
+/// best method depends on the systems involved, it should be chosen
+/// explicitly when this is known, or can be evaluated.
+///
+/// [`rrf`]: ./fn.rrf.html
+/// [`fuse_mixed`]: ./fn.fuse_mixed.html
+pub fn fuse_auto<I, LL, L, R>(lists: LL) -> (AutoMethod, Vec<EntryInfo<I>>)
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: RankedSearchEntry<Id = I>,
+{
+    let lists: Vec<Vec<RankedEntryInfo<I>>> = lists
+        .into_iter()
+        .map(|list| {
+            list.into_iter()
+                .map(|r| RankedEntryInfo {
+                    id: r.id().clone(),
+                    score: r.score(),
+                    rank: r.rank(),
+                })
+                .collect()
+        })
+        .collect();
+    let has_scores = |list: &[RankedEntryInfo<I>]| list.iter().any(|e| e.score != list[0].score);
+    let informative = || lists.iter().filter(|l| l.len() >= 2);
+    let all_scored = informative().next().is_some() && informative().all(|l| has_scores(l));
+    let none_scored = informative().all(|l| !has_scores(l));
+
+    if all_scored {
+        let fused = fuse_mixed(&lists, Vec::<Vec<RankedEntryInfo<I>>>::new(), 1.);
+        (AutoMethod::NormalizedCombSum, fused)
+    } else if none_scored {
+        (AutoMethod::Rrf, fuse_ranked(lists.iter().flatten(), rrf))
+    } else {
+        let (scored, ranked): (Vec<_>, Vec<_>) = lists.iter().partition(|l| has_scores(l));
+        (AutoMethod::Mixed, fuse_mixed(scored, ranked, 1.))
+    }
+}
+
+/// Combines the results of multiple systems, one list per system, by
+/// majority vote: each document is scored by the number of systems which
+/// rank it within their top `k` results.
+///
+/// Documents with the same number of votes are ordered by the sum of
+/// their scores (CombSUM), the highest first. Note that the output scores
+/// are the vote counts, so the CombSUM tie-break is only reflected in the
+/// order of the list, and remaining ties follow the order of first
+/// appearance. Ranks are taken from each entry's position in its list.
+pub fn majority_topk_fuse<I, LL, L, R>(lists: LL, k: usize) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+{
+    let mut acc = ScoreAccumulator::new();
+    for list in lists {
+        acc.add_list(list);
+    }
+    let mut flat: Vec<_> = acc
+        .contributions
+        .into_iter()
+        .map(|(id, (first, contributions))| {
+            let hits = contributions.iter().filter(|c| (c.rank as usize) < k).count();
+            let sum: Score = contributions.iter().map(|c| c.score).sum();
+            (EntryInfo { id, score: n32(hits as f32) }, sum, first)
+        })
+        .collect();
+
+    flat.sort_unstable_by_key(|(e, sum, first)| (-e.score, -*sum, *first));
+    flat.into_iter().map(|(e, _, _)| e).collect()
+}
+
+/// Normalized CombMNZ algorithm
+///
+/// Returns the CombMNZ score divided by `num_systems * max_score_sum`, where
+/// `num_systems` is the number of systems participating in the fusion and
+/// `max_score_sum` is the highest sum of scores (CombSUM) among all fused
+/// documents. For non-negative scores, this places the fused scores in the
+/// range `[0, 1]`, reaching 1 only for the best document when retrieved by
+/// all systems, regardless of how many systems there are. This makes
+/// normalized scores comparable across queries.
+///
+/// Since these parameters are not known from a single document's scores,
+/// see [`fuse_mnz_normalized`] for fusing lists with this algorithm.
+///
+/// [`fuse_mnz_normalized`]: ./fn.fuse_mnz_normalized.html
+pub fn comb_mnz_normalized(scores: &[Score], num_systems: usize, max_score_sum: Score) -> Score {
+    let factor = n32(num_systems as f32) * max_score_sum;
+    if factor == 0. {
+        return n32(0.);
+    }
+    comb_mnz(scores) / factor
+}
+
+/// Combines the scored results of multiple systems, one list per system,
+/// with the normalized CombMNZ algorithm.
+///
+/// See [`comb_mnz_normalized`] for the normalization factor.
+///
+/// [`comb_mnz_normalized`]: ./fn.comb_mnz_normalized.html
+pub fn fuse_mnz_normalized<I, LL, L, R>(lists: LL) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry<Id = I>,
+{
+    let mut acc = ScoreAccumulator::new();
+    for list in lists {
+        acc.add_list(list);
+    }
+    let num_systems = acc.num_systems();
+    let max_score_sum = acc
+        .iter()
+        .map(|(_, c)| c.iter().map(|c| c.score).sum::<Score>())
+        .max()
+        .unwrap_or_else(|| n32(0.));
+    acc.finish(|scores| comb_mnz_normalized(scores, num_systems, max_score_sum))
+}
+
+/// Re-ranks a system's ranked results so that documents sharing the same
+/// rank are told apart by their score, the highest score first.
+///
+/// Entries are ordered by rank, then by descending score, and then given
+/// consecutive ranks starting at 0. Rank-based fusion algorithms use
+/// ranks as they are by default, so ties in the input remain tied unless
+/// the list is processed with this function first.
+pub fn break_rank_ties_by_score<I, L, R>(results: L) -> Vec<RankedEntryInfo<I>>
+where
+    I: Clone,
+    L: IntoIterator<Item = R>,
+    R: RankedSearchEntry<Id = I>,
+{
+    let mut list: Vec<_> = results
+        .into_iter()
+        .map(|r| RankedEntryInfo {
+            id: r.id().clone(),
+            score: r.score(),
+            rank: r.rank(),
+        })
+        .collect();
+    list.sort_by_key(|e| (e.rank, -e.score));
+    for (i, e) in list.iter_mut().enumerate() {
+        e.rank = i as Rank;
+    }
+    list
+}
+
+/// Keeps only the best-ranked entry of each document per system, where the
+/// system of each entry is its run ID, so that a document listed more than
+/// once by the same system, such as from several sub-queries, contributes
+/// a single rank to fusion.
+///
+/// Rank-based fusion functions such as [`fuse_ranked`] take every entry of
+/// a document into account, so without this, each repeated listing counts
+/// as if another system had retrieved the document. The kept entries are
+/// in order of first appearance of their document and run ID, with the
+/// rank and score of the entry with the lowest rank (the first one among
+/// equal ranks).
+///
+/// [`fuse_ranked`]: ./fn.fuse_ranked.html
+pub fn best_rank_per_system<I, L, R>(results: L) -> Vec<RankedEntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: RunSearchEntry<Id = I>,
+{
+    let mut runids: Vec<String> = Vec::new();
+    let mut positions: HashMap<(usize, I), usize> = HashMap::new();
+    let mut list: Vec<RankedEntryInfo<I>> = Vec::new();
+    for r in results {
+        let system = match runids.iter().position(|x| x == r.runid()) {
+            Some(i) => i,
+            None => {
+                runids.push(r.runid().to_string());
+                runids.len() - 1
+            }
+        };
+        match positions.get(&(system, r.id().clone())) {
+            Some(&i) => {
+                if r.rank() < list[i].rank {
+                    list[i].rank = r.rank();
+                    list[i].score = r.score();
+                }
+            }
+            None => {
+                positions.insert((system, r.id().clone()), list.len());
+                list.push(RankedEntryInfo {
+                    id: r.id().clone(),
+                    score: r.score(),
+                    rank: r.rank(),
+                });
+            }
+        }
+    }
+    list
+}
+
+/// Combines multiple ranked results with a rank-based fusion algorithm.
+///
+/// Only the ranks found for a document are fused, so systems which did not
+/// retrieve it are left out. See [`fuse_ranked_multi`] for other ways of
+/// treating them.
+///
+/// [`fuse_ranked_multi`]: ./fn.fuse_ranked_multi.html
+pub fn fuse_ranked<I, L, R, F>(results: L, fuser: F) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: RankedSearchEntry<Id = I>,
+    F: Fn(&[Rank]) -> Score,
+{
+    fuse_by(results, R::rank, fuser)
+}
+
+/// Combines multiple ranked results with a fusion algorithm based on both rank
+/// and score.
+pub fn fuse_hybrid<I, L, R, F>(results: L, fuser: F) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Clone + Hash,
+    L: IntoIterator<Item = R>,
+    R: RankedSearchEntry<Id = I>,
+    F: Fn(&[(Rank, Score)]) -> Score,
+{
+    fuse_by(results, |r| (r.rank(), r.score()), fuser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score;
+
+    #[test]
+    fn test_comb_max() {
+        assert_eq!(
+            comb_max(&[score(1.), score(40.), score(0.5), score(12.)]),
+            40.
+        )
+    }
+
+    #[test]
+    fn test_comb_sum() {
+        assert_eq!(
+            comb_sum(&[score(1.), score(40.), score(0.5), score(12.)]),
+            53.5
+        )
+    }
+
+    #[test]
+    fn test_comb_sum_kahan() {
+        assert_eq!(
+            comb_sum_kahan(&[score(1.), score(40.), score(0.5), score(12.)]),
+            53.5
+        );
+        assert_eq!(comb_sum_kahan(&[]), 0.);
+
+        // each 1 is lost when added to the large score
+        let mut scores = vec![score(1e8)];
+        scores.extend(vec![score(1.); 10_000]);
+        assert_eq!(comb_sum(&scores), 1e8);
+        assert_eq!(comb_sum_kahan(&scores), 1.0001e8);
+    }
+
+    #[test]
+    fn test_comb_mnz() {
+        assert_eq!(
+            comb_mnz(&[score(1.), score(40.), score(0.5), score(12.)]),
+            214.
+        )
+    }
+
+    #[test]
+    fn test_comb_mnz_capped() {
+        // a document found by 10 systems
+        let scores = vec![score(0.5); 10];
+        assert_eq!(comb_mnz(&scores), 50.);
+        assert_eq!(comb_mnz_capped(3)(&scores), 15.);
+
+        // found by fewer systems than the cap, the multiplier is the same
+        assert_eq!(comb_mnz_capped(3)(&scores[..2]), comb_mnz(&scores[..2]));
+        assert_eq!(comb_mnz_capped(1)(&scores), comb_sum(&scores));
+        assert_eq!(comb_mnz_capped(3)(&[]), 0.);
+    }
+
+    #[test]
+    fn test_post_transform() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let results = vec![e("a", 3.), e("b", -2.), e("c", 0.5), e("a", 1.), e("d", -0.25), e("b", 4.)];
+        let sigmoid = |s: Score| score(1. / (1. + (-s.raw()).exp()));
+
+        let fused = fuse_scored(&results, comb_sum);
+        let squashed = fuse_scored(&results, post_transform(comb_sum, sigmoid));
+        assert_eq!(
+            squashed.iter().map(|e| e.id).collect::<Vec<_>>(),
+            fused.iter().map(|e| e.id).collect::<Vec<_>>()
+        );
+        assert!(squashed.iter().all(|e| e.score > 0. && e.score < 1.));
+        assert_eq!(squashed[0].score, sigmoid(score(4.)));
+    }
+
+    #[test]
+    fn test_comb_avg() {
+        assert_eq!(
+            comb_avg(&[score(1.), score(40.), score(0.5), score(12.)]),
+            13.375
+        );
+        // unlike CombSUM, a single high score beats many lower ones
+        assert!(comb_avg(&[score(3.)]) > comb_avg(&[score(2.), score(2.)]));
+        assert!(comb_sum(&[score(3.)]) < comb_sum(&[score(2.), score(2.)]));
+    }
+
+    #[test]
+    fn test_fusers_empty_and_single() {
+        for &(name, fuser) in SCORE_FUSERS {
+            assert_eq!(fuser(&[]), 0., "{} without scores", name);
+            assert_eq!(fuser(&[score(0.75)]), 0.75, "{} with a single score", name);
+            assert_eq!(fuser(&[score(-2.)]), -2., "{} with a single score", name);
+        }
+        for &(name, fuser) in RANK_FUSERS {
+            assert_eq!(fuser(&[]), 0., "{} without ranks", name);
+            assert_eq!(fuser(&[0]), 1., "{} with a single rank", name);
+            assert_eq!(fuser(&[3]), 0.25, "{} with a single rank", name);
+        }
+    }
+
+    #[test]
+    fn test_comb_logodds() {
+        use approx::assert_abs_diff_eq;
+
+        // odds of 4 and 1.5 make odds of 6
+        let fused = comb_logodds(&[score(0.8), score(0.6)]);
+        assert_abs_diff_eq!(fused.raw(), 6f32.ln(), epsilon = 1e-5);
+        assert_abs_diff_eq!(1. / (1. + (-fused.raw()).exp()), 6. / 7., epsilon = 1e-5);
+        assert_eq!(comb_logodds(&[]), 0.);
+        assert_abs_diff_eq!(comb_logodds(&[score(0.5)]).raw(), 0.);
+
+        // certain scores are clamped
+        let certain = comb_logodds(&[score(1.), score(1.5)]);
+        assert!(certain.raw().is_finite() && certain > 0.);
+    }
+
+    #[test]
+    fn test_comb_robust() {
+        use approx::assert_abs_diff_eq;
+
+        // median 1.25, MAD 0.5: 40 is clamped to 2.75
+        assert_eq!(
+            comb_robust(&[score(1.), score(40.), score(0.5), score(1.5)]),
+            5.75
+        );
+        // no outliers, same as CombSUM
+        assert_eq!(
+            comb_robust(&[score(1.), score(0.5), score(1.5)]),
+            3.
+        );
+        assert_eq!(comb_robust(&[]), 0.);
+
+        // infinities
+        let inf = f32::INFINITY;
+        assert_eq!(comb_robust(&[score(inf), score(inf)]), inf);
+        assert_eq!(comb_robust(&[score(inf), score(inf), score(1.)]), inf);
+        // median 1, MAD 1: -inf is clamped to the lower bound
+        assert_abs_diff_eq!(
+            comb_robust(&[score(-inf), score(1.), score(2.)]).raw(),
+            (1. - ROBUST_K) + 1. + 2.
+        );
+    }
+
+    #[test]
+    fn test_comb_huber() {
+        use approx::assert_abs_diff_eq;
+
+        // no outliers, same as CombSUM
+        assert_eq!(
+            comb_huber(&[score(1.), score(0.5), score(1.5)]),
+            3.
+        );
+        // identical majority, outlier is disregarded
+        assert_eq!(
+            comb_huber(&[score(1.), score(1.), score(90.)]),
+            2.
+        );
+        assert_eq!(comb_huber(&[]), 0.);
+
+        // infinities
+        let inf = f32::INFINITY;
+        assert_eq!(comb_huber(&[score(inf), score(inf)]), inf);
+        // identical majority, infinite outlier is disregarded
+        assert_eq!(comb_huber(&[score(-inf), score(1.), score(1.)]), 2.);
+        // median 2, MAD 1: inf contributes the threshold
+        assert_abs_diff_eq!(
+            comb_huber(&[score(inf), score(1.), score(2.)]).raw(),
+            1. + 2. + HUBER_K * 1.4826,
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn test_fuse_scored_restricted() {
+        let list = vec![
+            EntryInfo { id: "a", score: score(1.) },
+            EntryInfo { id: "b", score: score(0.5) },
+            EntryInfo { id: "a", score: score(0.5) },
+            EntryInfo { id: "c", score: score(0.25) },
+            EntryInfo { id: "d", score: score(2.) },
+        ];
+        let allowed: HashSet<_> = vec!["a", "c", "e"].into_iter().collect();
+
+        let out = fuse_scored_restricted(&list, comb_sum, &allowed);
+        assert_eq!(
+            out,
+            vec![
+                EntryInfo { id: "a", score: score(1.5) },
+                EntryInfo { id: "c", score: score(0.25) },
+            ]
+        );
+        assert!(out.iter().all(|e| allowed.contains(e.id)));
+    }
+
+    #[test]
+    fn test_fuse_scored_limited() {
+        let list = vec![
+            EntryInfo { id: "a", score: score(1.) },
+            EntryInfo { id: "b", score: score(0.5) },
+            EntryInfo { id: "a", score: score(0.5) },
+            EntryInfo { id: "c", score: score(0.25) },
+            EntryInfo { id: "d", score: score(0.1) },
+        ];
+
+        let out = fuse_scored_limited(&list, comb_sum, 3);
+        assert_eq!(
+            out,
+            Err(FusionError::TooManyDocuments { limit: 3, seen: 5 })
+        );
+
+        let out = fuse_scored_limited(&list, comb_sum, 4).unwrap();
+        assert_eq!(out, fuse_scored(&list, comb_sum));
+    }
+
+    #[test]
+    fn test_fuse_scored_ranked() {
+        let list = vec![
+            EntryInfo { id: "a", score: score(0.5) },
+            EntryInfo { id: "b", score: score(2.) },
+            EntryInfo { id: "a", score: score(0.75) },
+            EntryInfo { id: "c", score: score(0.25) },
+        ];
+
+        let out = fuse_scored_ranked(&list, comb_sum);
+        assert_eq!(
+            out,
+            vec![
+                RankedEntryInfo { id: "b", score: score(2.), rank: 0 },
+                RankedEntryInfo { id: "a", score: score(1.25), rank: 1 },
+                RankedEntryInfo { id: "c", score: score(0.25), rank: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fuse_features() {
+        let data = "301 0 d1 0 2 0.25 run\n301 0 d2 1 1 2 run\n301 0 d1 0 0.5 0.5 run2\n";
+        let list = crate::trec::parse_from_trec_multiscore(data).unwrap();
+
+        let out = fuse_features(list.iter().map(|e| (e.docno, &e.scores)), comb_sum);
+        assert_eq!(
+            out,
+            vec![
+                EntryInfo { id: "d1", score: score(3.25) },
+                EntryInfo { id: "d2", score: score(3.) },
+            ]
+        );
+        let out = fuse_features(list.iter().map(|e| (e.docno, &e.scores)), comb_mnz);
+        assert_eq!(
+            out,
+            vec![
+                EntryInfo { id: "d1", score: score(13.) },
+                EntryInfo { id: "d2", score: score(6.) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fuse_two_sorted() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let by_id = |mut v: Vec<EntryInfo<&'static str>>| {
+            v.sort_by_key(|e| e.id);
+            v
+        };
+
+        let list1 = vec![e("a", 1.), e("b", 0.5), e("d", 0.25), e("d", 0.125)];
+        let list2 = vec![e("b", 0.75), e("c", 0.5), e("e", 2.)];
+        for fuser in [comb_max, comb_sum, comb_mnz].iter() {
+            assert_eq!(
+                by_id(fuse_two_sorted(&list1, &list2, fuser)),
+                by_id(fuse_scored_lists(&list1, &list2, fuser)),
+            );
+        }
+
+        // unsorted input takes the general path
+        let list3 = vec![e("c", 1.), e("a", 0.5)];
+        assert_eq!(
+            by_id(fuse_two_sorted(&list1, &list3, comb_sum)),
+            by_id(fuse_scored_lists(&list1, &list3, comb_sum)),
+        );
+
+        let empty: Vec<EntryInfo<&str>> = Vec::new();
+        assert_eq!(fuse_two_sorted(&empty, &list2, comb_sum), fuse_scored(&list2, comb_sum));
+    }
+
+    #[test]
+    fn test_union_max() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let lists = vec![
+            vec![e("a", 1.), e("b", 0.5)],
+            vec![e("b", 2.), e("c", 0.25)],
+            vec![e("a", 0.75)],
+        ];
+        assert_eq!(union_max(&lists), vec![e("b", 2.), e("a", 1.), e("c", 0.25)]);
+    }
+
+    #[test]
+    fn test_fuse_intersection() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let lists = vec![
+            vec![e("a", 1.), e("b", 0.5), e("c", 0.25)],
+            vec![e("c", 1.), e("a", 0.5), e("d", 0.25)],
+            // "b" appears twice, but in a single system
+            vec![e("b", 1.), e("a", 0.75), e("c", 0.5), e("b", 0.25)],
+        ];
+
+        let out = fuse_intersection(&lists, comb_sum);
+        assert_eq!(out, vec![e("a", 2.25), e("c", 1.75)]);
+
+        let mut out = fuse_intersection(&lists[..2], comb_max);
+        out.sort_by_key(|e| e.id);
+        assert_eq!(out, vec![e("a", 1.), e("c", 1.)]);
+
+        let disjoint = vec![vec![e("a", 1.)], vec![e("b", 1.)]];
+        assert_eq!(fuse_intersection(&disjoint, comb_sum), vec![]);
+    }
+
+    #[test]
+    fn test_fuse_mnz_normalized() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+
+        // query 1: two systems
+        let q1 = vec![
+            vec![e("a", 1.), e("b", 0.5)],
+            vec![e("a", 1.), e("c", 0.5)],
+        ];
+        // query 2: four systems
+        let q2 = vec![
+            vec![e("a", 1.), e("b", 0.5)],
+            vec![e("a", 1.), e("b", 0.5)],
+            vec![e("a", 1.), e("c", 0.5)],
+            vec![e("a", 1.)],
+        ];
+
+        // plain CombMNZ scores depend on the number of systems
+        assert_eq!(fuse_scored_multi(&q1, comb_mnz)[0], e("a", 4.));
+        assert_eq!(fuse_scored_multi(&q2, comb_mnz)[0], e("a", 16.));
+
+        let out1 = fuse_mnz_normalized(&q1);
+        let out2 = fuse_mnz_normalized(&q2);
+        assert_eq!(out1[0], e("a", 1.));
+        assert_eq!(out2[0], e("a", 1.));
+        // b: 1 × 0.5 / (2 × 2)
+        assert!(out1.contains(&e("b", 0.125)));
+        // b: 2 × 1 / (4 × 4)
+        assert!(out2.contains(&e("b", 0.125)));
+        // c: 1 × 0.5 / (4 × 4)
+        assert!(out2.contains(&e("c", 0.03125)));
+    }
+
+    #[test]
+    fn test_fuse_scored_ref() {
+        let e = |id: &str, s| EntryInfo { id: id.to_string(), score: score(s) };
+        let results = vec![e("a", 1.), e("b", 4.), e("c", 2.), e("a", 2.5), e("d", 0.5)];
+        let fused = fuse_scored_ref(&results, comb_mnz);
+        // the IDs are those of the input entries, not copies
+        assert!(fused.iter().all(|f| results.iter().any(|r| std::ptr::eq(f.id, &r.id))));
+
+        let owned: Vec<_> = fused.iter().map(|f| EntryInfo { id: f.id.clone(), score: f.score }).collect();
+        assert_eq!(owned, fuse_scored(&results, comb_mnz));
+    }
+
+    #[test]
+    fn test_fuse_scored_top_k() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let results = vec![e("a", 1.), e("b", 4.), e("c", 2.), e("a", 2.5), e("d", 0.5)];
+        assert_eq!(fuse_scored_top_k(&results, comb_sum, 2), vec![e("b", 4.), e("a", 3.5)]);
+        assert_eq!(fuse_scored_top_k(&results, comb_sum, 10), fuse_scored(&results, comb_sum));
+    }
+
+    #[test]
+    fn test_fuse_scored_keep() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Hit {
+            id: &'static str,
+            score: Score,
+            title: &'static str,
+        }
+        impl SearchEntry for Hit {
+            type Id = &'static str;
+            fn id(&self) -> &Self::Id {
+                &self.id
+            }
+            fn score(&self) -> Score {
+                self.score
+            }
+        }
+        let hit = |id, s, title| Hit { id, score: score(s), title };
+        let results = vec![
+            hit("a", 0.5, "A from bm25"),
+            hit("b", 2., "B from bm25"),
+            hit("a", 1.5, "A from dense"),
+            hit("a", 1.5, "A from sparse"),
+        ];
+
+        let fused = fuse_scored_keep(results, comb_sum);
+        assert_eq!(
+            fused,
+            vec![
+                (EntryInfo { id: "a", score: score(3.5) }, hit("a", 1.5, "A from dense")),
+                (EntryInfo { id: "b", score: score(2.) }, hit("b", 2., "B from bm25")),
+            ]
+        );
+
+        // tied documents in order of first appearance
+        let ties = vec![hit("c", 1., "C"), hit("a", 1., "A"), hit("b", 1., "B")];
+        let ids: Vec<_> = fuse_scored_keep(ties, comb_sum).into_iter().map(|(e, _)| e.id).collect();
+        assert_eq!(ids, vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_fuse_by() {
+        let r = |id, s, rank| RankedEntryInfo { id, score: score(s), rank };
+        let results = vec![r("a", 2., 0), r("b", 1., 1), r("b", 3., 0), r("c", 0.5, 1), r("a", 0.25, 2)];
+        let e = |id, s| EntryInfo { id, score: score(s) };
+
+        let sums = vec![e("b", 4.), e("a", 2.25), e("c", 0.5)];
+        assert_eq!(fuse_by(&results, |r| r.score, comb_sum), sums);
+        assert_eq!(fuse_scored(&results, comb_sum), sums);
+
+        let rrfs = vec![e("b", 1.5), e("a", 1. + 1. / 3.), e("c", 0.5)];
+        assert_eq!(fuse_by(&results, |r| r.rank, rrf), rrfs);
+        assert_eq!(fuse_ranked(&results, rrf), rrfs);
+
+        // each entry scores its score plus its RRF term
+        let hybrid = hybrid_linear(1., 1., 1.);
+        let hybrids = vec![e("b", 5.5), e("a", 3. + (0.25 + 1. / 3.)), e("c", 1.)];
+        assert_eq!(fuse_by(&results, |r| (r.rank, r.score), &hybrid), hybrids);
+        assert_eq!(fuse_hybrid(&results, &hybrid), hybrids);
+
+        // number of systems retrieving each document, tied in order of
+        // first appearance
+        let counts = fuse_by(&results, |_| (), |v: &[()]| score(v.len() as f32));
+        assert_eq!(counts, vec![e("a", 2.), e("b", 2.), e("c", 1.)]);
+    }
+
+    #[test]
+    fn test_fuse_ties_by_first_appearance() {
+        let r = |id, rank| RankedEntryInfo { id, score: score(4. - rank as f32), rank };
+        let list1 = vec![r("x", 0), r("y", 1), r("z", 2), r("w", 3)];
+        let list2 = vec![r("y", 0), r("x", 1), r("w", 2), r("z", 3)];
+        let ids = |v: Vec<EntryInfo<&'static str>>| v.into_iter().map(|e| e.id).collect::<Vec<_>>();
+
+        // x and y tie, and so do z and w, under both CombSUM and RRF
+        let forward = list1.iter().chain(&list2);
+        assert_eq!(ids(fuse_scored(forward.clone(), comb_sum)), vec!["x", "y", "z", "w"]);
+        assert_eq!(ids(fuse_ranked(forward, rrf)), vec!["x", "y", "z", "w"]);
+        let backward = list2.iter().chain(&list1);
+        assert_eq!(ids(fuse_scored(backward.clone(), comb_sum)), vec!["y", "x", "w", "z"]);
+        assert_eq!(ids(fuse_ranked(backward, rrf)), vec!["y", "x", "w", "z"]);
+
+        // the same goes for the other fusion procedures
+        let expected = vec!["x", "y", "z", "w"];
+        let forward = list1.iter().chain(&list2);
+        let limited = fuse_scored_limited(forward.clone(), comb_sum, 4).unwrap();
+        assert_eq!(ids(limited), expected);
+        let cancel = AtomicBool::new(false);
+        let cancellable = fuse_scored_with_cancel(forward.clone(), comb_sum, &cancel).unwrap();
+        assert_eq!(ids(cancellable), expected);
+        let features = forward.map(|e| (e.id, [e.score]));
+        assert_eq!(ids(fuse_features(features, comb_sum)), expected);
+        let multi = fuse_ranked_multi(vec![&list1, &list2], rrf, Unretrieved::Omit);
+        assert_eq!(ids(multi), expected);
+        let mut acc = ScoreAccumulator::new();
+        acc.add_ranked_list(&list1);
+        let mut other = ScoreAccumulator::new();
+        other.add_ranked_list(&list2);
+        acc.merge(other);
+        let state = FusionState::new(acc.clone(), comb_sum);
+        assert_eq!(ids(state.ranking()), expected);
+        assert_eq!(ids(acc.finish(comb_sum)), expected);
+
+        // and to the merge of lists sorted by ID, with ties in both lists
+        let list1 = vec![r("a", 1), r("c", 0)];
+        let list2 = vec![r("b", 0), r("d", 1)];
+        let merged = fuse_two_sorted(&list1, &list2, comb_sum);
+        assert_eq!(ids(merged), vec!["c", "b", "a", "d"]);
+    }
+
+    #[test]
+    fn test_borda_unretrieved() {
+        let r = |id, rank| RankedEntryInfo { id, score: score(1.), rank };
+        let lists = vec![vec![r("a", 0), r("b", 1), r("c", 2)], vec![r("b", 0), r("c", 1)]];
+        let by_id = |mut v: Vec<EntryInfo<&'static str>>| {
+            v.sort_by_key(|e| e.id);
+            v
+        };
+        let e = |id, s| EntryInfo { id, score: score(s) };
+
+        // a gets nothing from the second system
+        let omitted = fuse_ranked_multi(&lists, borda(3), Unretrieved::Omit);
+        assert_eq!(by_id(omitted.clone()), vec![e("a", 3.), e("b", 5.), e("c", 3.)]);
+        assert_eq!(by_id(omitted), by_id(fuse_ranked(lists.iter().flatten(), borda(3))));
+
+        // a is ranked third by the second system, after b and c
+        let after_last = fuse_ranked_multi(&lists, borda(3), Unretrieved::AfterLast);
+        assert_eq!(by_id(after_last), vec![e("a", 4.), e("b", 5.), e("c", 3.)]);
+
+        // a rank at the number of candidates is worth no points
+        let at_end = fuse_ranked_multi(&lists, borda(3), Unretrieved::Rank(3));
+        assert_eq!(by_id(at_end), vec![e("a", 3.), e("b", 5.), e("c", 3.)]);
+    }
+
+    #[test]
+    fn test_exp_decay() {
+        let fuser = exp_decay(0.5);
+        assert_eq!(fuser(&[0]), 1.);
+        assert_eq!(fuser(&[1]), 0.5);
+        assert_eq!(fuser(&[2]), 0.25);
+        assert_eq!(fuser(&[0, 1, 2]), 1.75);
+        assert_eq!(fuser(&[]), 0.);
+
+        let ranked = vec![
+            RankedEntryInfo { id: "a", score: score(1.), rank: 0 },
+            RankedEntryInfo { id: "b", score: score(1.), rank: 1 },
+            RankedEntryInfo { id: "b", score: score(1.), rank: 0 },
+            RankedEntryInfo { id: "a", score: score(1.), rank: 2 },
+        ];
+        let fused = fuse_ranked(&ranked, exp_decay(0.5));
+        assert_eq!(
+            fused,
+            vec![EntryInfo { id: "b", score: score(1.5) }, EntryInfo { id: "a", score: score(1.25) }]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_exp_decay_out_of_range() {
+        let _ = exp_decay(1.);
+    }
+
+    #[test]
+    #[should_panic(expected = "k must be positive")]
+    fn test_hybrid_linear_zero_k() {
+        let _ = hybrid_linear(0., 1., 0.);
+    }
+
+    #[test]
+    fn test_hybrid_linear() {
+        let fuser = hybrid_linear(0.5, 2., 60.);
+        assert_eq!(fuser(&[(0, score(1.)), (40, score(4.))]), 2.5 + 2. / 60. + 2. / 100.);
+
+        let list1 = vec![
+            EntryInfo { id: "a", score: score(4.) },
+            EntryInfo { id: "b", score: score(2.) },
+            EntryInfo { id: "c", score: score(1.) },
+        ];
+        let list2 = vec![
+            EntryInfo { id: "c", score: score(10.) },
+            EntryInfo { id: "a", score: score(0.5) },
+        ];
+        let ranked: Vec<_> = crate::ranked_list(&list1)
+            .chain(crate::ranked_list(&list2))
+            .map(|r| RankedEntryInfo {
+                id: r.inner.id,
+                score: r.inner.score,
+                rank: r.rank,
+            })
+            .collect();
+
+        let sort = |mut list: Vec<EntryInfo<&'static str>>| {
+            list.sort_by_key(|e| e.id);
+            list
+        };
+
+        // pure score, for which k does not matter
+        let out = fuse_hybrid(&ranked, hybrid_linear(1., 0., 60.));
+        let expected = fuse_scored(&ranked, comb_sum);
+        assert_eq!(sort(out), sort(expected.clone()));
+        let out = fuse_hybrid(&ranked, hybrid_linear(1., 0., 0.));
+        assert_eq!(sort(out), sort(expected));
+
+        // pure reciprocal rank
+        let out = fuse_hybrid(&ranked, hybrid_linear(0., 1., 1.));
+        let expected = fuse_ranked(&ranked, rrf);
+        assert_eq!(sort(out), sort(expected));
+    }
+
+    #[test]
+    fn test_canonicalize_ids() {
+        let list = vec![
+            EntryInfo { id: "DOC1", score: score(1.) },
+            EntryInfo { id: "doc2", score: score(0.75) },
+            EntryInfo { id: "doc1 ", score: score(0.5) },
+        ];
+
+        assert_eq!(fuse_scored(&list, comb_sum).len(), 3);
+
+        let out = fuse_scored(canonicalize_ids(&list, canonical_id), comb_sum);
+        assert_eq!(
+            out,
+            vec![
+                EntryInfo { id: "doc1".to_string(), score: score(1.5) },
+                EntryInfo { id: "doc2".to_string(), score: score(0.75) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let lists = vec![
+            vec![e("a", 1.), e("b", 0.5)],
+            vec![e("c", 2.)],
+            vec![e("c", 1.), e("b", 0.25)],
+        ];
+
+        let explanation = explain(&"b", &lists, comb_sum);
+        assert_eq!(
+            explanation,
+            Explanation {
+                per_system: vec![(0, 1, score(0.5)), (2, 1, score(0.25))],
+                runids: vec![None, None],
+                fused: score(0.75),
+            }
+        );
+        assert_eq!(
+            explanation.to_json(),
+            r#"{"per_system": [{"system": 0, "rank": 1, "score": 0.5}, {"system": 2, "rank": 1, "score": 0.25}], "fused": 0.75}"#
+        );
+
+        // the fuser is not called for a document which was not found
+        let explanation = explain(&"z", &lists, |scores: &[Score]| {
+            assert!(!scores.is_empty(), "fuser called without scores");
+            comb_sum(scores)
+        });
+        assert!(explanation.per_system.is_empty());
+        assert_eq!(explanation.fused, score(0.));
+    }
+
+    #[test]
+    fn test_fuse_scored_with_cancel() {
+        let list: Vec<_> = (0..10_000)
+            .map(|i| EntryInfo { id: i % 100, score: score(1.) })
+            .collect();
+
+        let cancel = AtomicBool::new(false);
+        let out = fuse_scored_with_cancel(&list, comb_sum, &cancel).unwrap();
+        assert_eq!(out.len(), 100);
+
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(
+            fuse_scored_with_cancel(&list, comb_sum, &cancel),
+            Err(FusionError::Cancelled)
+        );
+
+        // cancel midway
+        let cancel = AtomicBool::new(false);
+        let read = std::cell::Cell::new(0);
+        let input = list.iter().inspect(|_| {
+            read.set(read.get() + 1);
+            if read.get() == 3000 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        });
+        assert_eq!(
+            fuse_scored_with_cancel(input, comb_sum, &cancel),
+            Err(FusionError::Cancelled)
+        );
+        assert!(read.get() <= 3000 + CANCEL_CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn test_best_rank_per_system() {
+        // d1 is listed twice by a, for two sub-queries
+        let data = "301 0 d1 0 2 a\n301 0 d2 1 1.5 a\n301 0 d1 3 0.5 a\n301 0 d2 0 3 b\n301 0 d1 1 1 b\n";
+        let run = crate::trec::parse_from_trec(data).unwrap();
+        let e = |id, s, rank| RankedEntryInfo { id, score: score(s), rank };
+
+        let list = best_rank_per_system(&run);
+        assert_eq!(list, vec![e("d1", 2., 0), e("d2", 1.5, 1), e("d2", 3., 0), e("d1", 1., 1)]);
+
+        let mut out = fuse_ranked(&list, rrf);
+        out.sort_by_key(|e| e.id);
+        let expected = 1. + 0.5;
+        assert_eq!(
+            out,
+            vec![
+                EntryInfo { id: "d1", score: score(expected) },
+                EntryInfo { id: "d2", score: score(expected) },
+            ]
+        );
+        // with all ranks, the second listing of d1 counts as well
+        let all = fuse_ranked(&run, rrf);
+        assert_eq!(all[0], EntryInfo { id: "d1", score: score(expected + 0.25) });
+    }
+
+    #[test]
+    fn test_break_rank_ties_by_score() {
+        let e = |id, s, rank| RankedEntryInfo { id, score: score(s), rank };
+        let list = vec![e("a", 0.5, 0), e("b", 0.9, 0), e("c", 0.1, 1)];
+
+        // by default, ties are kept
+        let out = fuse_ranked(&list, rrf);
+        assert_eq!(out[0].score, out[1].score);
+
+        let list = break_rank_ties_by_score(list);
+        assert_eq!(list, vec![e("b", 0.9, 0), e("a", 0.5, 1), e("c", 0.1, 2)]);
+
+        let out = fuse_ranked(&list, rrf);
+        assert_eq!(
+            out,
+            vec![
+                EntryInfo { id: "b", score: score(1.) },
+                EntryInfo { id: "a", score: score(0.5) },
+                EntryInfo { id: "c", score: score(1. / 3.) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_majority_topk_fuse() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let lists = vec![
+            vec![e("big", 100.), e("broad", 0.5), e("x", 0.25)],
+            vec![e("y", 0.75), e("broad", 0.5), e("z", 0.25)],
+            vec![e("broad", 0.5), e("z", 0.4), e("big", 0.1)],
+        ];
+
+        let out = majority_topk_fuse(&lists, 2);
+        assert_eq!(out[0], e("broad", 3.));
+        // one vote each, tie broken by CombSUM
+        assert_eq!(out[1], e("big", 1.));
+        assert_eq!(out[2], e("y", 1.));
+        assert_eq!(out[3], e("z", 1.));
+        assert_eq!(out[4], e("x", 0.));
+        assert_eq!(out.len(), 5);
+        // CombSUM prefers the single high-scoring document
+        assert_eq!(fuse_scored_multi(&lists, comb_sum)[0].id, "big");
+    }
+
+    #[test]
+    fn test_fusion_state_replace_system() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let by_id = |mut v: Vec<EntryInfo<&'static str>>| {
+            v.sort_by_key(|e| e.id);
+            v
+        };
+        let list1 = vec![e("a", 1.), e("b", 0.5)];
+        let list2 = vec![e("b", 1.), e("c", 0.5)];
+        let list3 = vec![e("c", 2.), e("d", 0.25)];
+
+        let mut acc = ScoreAccumulator::new();
+        acc.add_list(&list1);
+        acc.add_list(&list2);
+        acc.add_list(&list3);
+        let mut state = FusionState::new(acc, comb_mnz);
+        assert_eq!(
+            by_id(state.ranking()),
+            by_id(fuse_scored_multi(vec![&list1, &list2, &list3], comb_mnz))
+        );
+
+        // "c" and "d" lose a contribution, and "e" is new
+        let new_list3 = vec![e("a", 0.5), e("e", 0.75)];
+        state.replace_system(2, &new_list3);
+        assert_eq!(
+            by_id(state.ranking()),
+            by_id(fuse_scored_multi(vec![&list1, &list2, &new_list3], comb_mnz))
+        );
+        assert!(state.ranking().iter().all(|e| e.id != "d"));
+    }
+
+    #[test]
+    fn test_explain_runids() {
+        let run1 = crate::trec::parse_from_trec("301 0 d1 0 1 bm25\n301 0 d2 1 0.5 bm25\n").unwrap();
+        let run2 = crate::trec::parse_from_trec("301 0 d2 0 2 dense\n301 0 d3 1 1 dense\n").unwrap();
+        let run3 = crate::trec::parse_from_trec("301 0 d2 0 3 bm25\n").unwrap();
+
+        let mut acc = ScoreAccumulator::new();
+        acc.add_run_list(&run1);
+        acc.add_run_list(&run2);
+        acc.add_run_list(&run3);
+
+        let explanation = acc.explain(&"d2", comb_sum);
+        assert_eq!(
+            explanation,
+            Explanation {
+                per_system: vec![(0, 1, score(0.5)), (1, 0, score(2.)), (2, 0, score(3.))],
+                runids: vec![
+                    Some("bm25".to_string()),
+                    Some("dense".to_string()),
+                    Some("bm25".to_string()),
+                ],
+                fused: score(5.5),
+            }
+        );
+        assert_eq!(
+            acc.explain(&"d3", comb_sum).to_json(),
+            r#"{"per_system": [{"system": 1, "rank": 1, "score": 1, "runid": "dense"}], "fused": 1}"#
+        );
+    }
+
+    #[test]
+    fn test_accumulator_merge() {
+        let run1 = crate::trec::parse_from_trec("301 0 d1 0 1 bm25\n301 0 d2 1 0.5 bm25\n").unwrap();
+        let run2 = crate::trec::parse_from_trec("301 0 d2 0 2 dense\n301 0 d3 1 1 dense\n").unwrap();
+        let run3 = crate::trec::parse_from_trec("301 0 d2 0 3 bm25\n301 0 d1 1 1 bm25\n").unwrap();
+
+        let mut single = ScoreAccumulator::new();
+        single.add_run_list(&run1);
+        single.add_run_list(&run2);
+        single.add_run_list(&run3);
+
+        let mut shard1 = ScoreAccumulator::new();
+        shard1.add_run_list(&run1);
+        let mut shard2 = ScoreAccumulator::new();
+        shard2.add_run_list(&run2);
+        shard2.add_run_list(&run3);
+        let mut merged = shard1.clone();
+        merged.merge(shard2);
+
+        assert_eq!(merged.num_systems(), 3);
+        assert_eq!(merged.num_documents(), 3);
+        for id in &["d1", "d2", "d3"] {
+            assert_eq!(merged.explain(id, comb_sum), single.explain(id, comb_sum));
+        }
+        let by_id = |mut v: Vec<EntryInfo<&'static str>>| {
+            v.sort_by_key(|e| e.id);
+            v
+        };
+        assert_eq!(by_id(merged.finish(comb_mnz)), by_id(single.finish(comb_mnz)));
+
+        // the original is left untouched
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        assert_eq!(shard1.finish(comb_sum), vec![e("d1", 1.), e("d2", 0.5)]);
+    }
+
+    #[test]
+    fn test_group_by_runid() {
+        let data = "301 0 d1 0 1 bm25\n301 0 d1 0 2 dense\n301 0 d2 1 0.5 bm25\n301 0 d3 1 1 dense\n";
+        let run = crate::trec::parse_from_trec(data).unwrap();
+
+        let groups = group_by_runid(&run);
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        assert_eq!(
+            groups,
+            vec![vec![e("d1", 1.), e("d2", 0.5)], vec![e("d1", 2.), e("d3", 1.)]]
+        );
+
+        // only d1 is retrieved by both runs
+        assert_eq!(fuse_intersection(&groups, comb_sum), vec![e("d1", 3.)]);
+        let mut acc = ScoreAccumulator::new();
+        for group in &groups {
+            acc.add_list(group);
+        }
+        assert_eq!(acc.num_systems(), 2);
+    }
+
+    #[test]
+    fn test_fuse_mixed() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        // dense retriever, with scores
+        let scored = vec![e("a", 1.), e("b", 0.75), e("c", 0.5)];
+        // sparse retriever, with ranks only
+        let ranked = vec![
+            RankedEntryInfo { id: "c", score: score(0.), rank: 0 },
+            RankedEntryInfo { id: "d", score: score(0.), rank: 1 },
+        ];
+
+        let mut out = fuse_mixed(vec![&scored], vec![&ranked], 1.);
+        out.sort_by_key(|e| e.id);
+        // "c" is last in one system but first in the other
+        assert_eq!(out, vec![e("a", 1.), e("b", 0.5), e("c", 1.), e("d", 0.5)]);
+
+        let out = fuse_mixed(vec![&scored], vec![&ranked], 3.);
+        assert_eq!(out[0], e("a", 1.));
+    }
+
+    #[test]
+    #[should_panic(expected = "k must be positive")]
+    fn test_fuse_mixed_zero_k() {
+        let ranked = vec![RankedEntryInfo { id: "a", score: score(0.), rank: 0 }];
+        let _ = fuse_mixed(Vec::<Vec<EntryInfo<&str>>>::new(), vec![&ranked], 0.);
+    }
+
+    #[test]
+    fn test_fuse_auto() {
+        let r = |id, s, rank| RankedEntryInfo { id, score: score(s), rank };
+        let scored1 = vec![r("a", 4., 0), r("b", 2., 1), r("c", 0., 2)];
+        let scored2 = vec![r("b", 0.75, 0), r("c", 0.5, 1)];
+        let ranked1 = vec![r("c", 1., 0), r("d", 1., 1)];
+        let ranked2 = vec![r("d", 0., 0), r("a", 0., 1), r("b", 0., 2)];
+
+        let by_id = |mut v: Vec<EntryInfo<&'static str>>| {
+            v.sort_by_key(|e| e.id);
+            v
+        };
+
+        let (method, out) = fuse_auto(vec![&scored1, &scored2]);
+        assert_eq!(method, AutoMethod::NormalizedCombSum);
+        assert_eq!(
+            by_id(out),
+            by_id(fuse_mixed(vec![&scored1, &scored2], Vec::<&[RankedEntryInfo<&str>]>::new(), 1.))
+        );
+
+        let (method, out) = fuse_auto(vec![&ranked1, &ranked2]);
+        assert_eq!(method, AutoMethod::Rrf);
+        assert_eq!(by_id(out), by_id(fuse_ranked(ranked1.iter().chain(&ranked2), rrf)));
+
+        let (method, out) = fuse_auto(vec![&scored1, &ranked1, &ranked2]);
+        assert_eq!(method, AutoMethod::Mixed);
+        assert_eq!(by_id(out), by_id(fuse_mixed(vec![&scored1], vec![&ranked1, &ranked2], 1.)));
+
+        // lists this short tell nothing about their scores
+        let short1 = vec![r("a", 4., 0)];
+        let short2 = vec![r("b", 2., 0)];
+        let (method, out) = fuse_auto(vec![&short1, &short2, &vec![]]);
+        assert_eq!(method, AutoMethod::Rrf);
+        assert_eq!(by_id(out), by_id(fuse_ranked(short1.iter().chain(&short2), rrf)));
+    }
+
+    #[test]
+    fn test_fuse_weighted() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let lists = vec![
+            vec![e("a", 1.), e("b", 0.5)],
+            vec![e("b", 1.), e("c", 0.5)],
+        ];
+        let out = fuse_weighted(&lists, &[2., 0.5], comb_sum);
+        assert_eq!(out, vec![e("a", 2.), e("b", 1.5), e("c", 0.25)]);
+        // missing weights default to 1
+        let mut out = fuse_weighted(&lists, &[], comb_sum);
+        out.sort_by_key(|e| e.id);
+        let mut expected = fuse_scored_multi(&lists, comb_sum);
+        expected.sort_by_key(|e| e.id);
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_all_pairs_fusion() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let lists = vec![
+            vec![e("a", 1.), e("b", 0.5)],
+            vec![e("b", 1.), e("c", 0.5)],
+            vec![e("c", 2.)],
+            vec![e("a", 0.25)],
+        ];
+        let pairs = all_pairs_fusion(&lists, comb_sum);
+        assert_eq!(pairs.len(), 6);
+        assert_eq!(
+            pairs.iter().map(|(pair, _)| *pair).collect::<Vec<_>>(),
+            vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]
+        );
+        assert_eq!(pairs[3].1, vec![e("c", 2.5), e("b", 1.)]);
+        assert_eq!(pairs[3].1, fuse_scored(lists[1].iter().chain(&lists[2]), comb_sum));
+
+        assert!(all_pairs_fusion(&lists[..1], comb_sum).is_empty());
+    }
+
+    #[test]
+    fn test_fuse_grouped() {
+        let data = "301 0 d1 0 1 bm25.en\n301 0 d2 1 0.5 bm25.en\n301 0 d1 0 2 dense.en\n\
+                    301 0 d1 0 3 bm25.pt\n302 0 d3 0 1 bm25.en\n";
+        let run = crate::trec::parse_from_trec(data).unwrap();
+        let e = |id, s| EntryInfo { id, score: score(s) };
+
+        // by language, across queries
+        let by_language = fuse_grouped(&run, |r| r.runid.rsplit('.').next().unwrap(), comb_sum);
+        assert_eq!(by_language.len(), 2);
+        let mut en = by_language["en"].clone();
+        en.sort_by_key(|e| e.id);
+        assert_eq!(en, vec![e("d1", 3.), e("d2", 0.5), e("d3", 1.)]);
+        assert_eq!(by_language["pt"], vec![e("d1", 3.)]);
+
+        // by query
+        let by_query = fuse_grouped(&run, |r| r.qid, comb_sum);
+        assert_eq!(by_query["301"][0], e("d1", 6.));
+        assert_eq!(by_query["302"], vec![e("d3", 1.)]);
+    }
+
+    #[test]
+    fn test_agreement_weights() {
+        let e = |id| EntryInfo { id, score: score(1.) };
+        let lists = vec![
+            vec![e("a"), e("b"), e("c"), e("d")],
+            vec![e("a"), e("b"), e("d"), e("c")],
+            vec![e("a"), e("c"), e("b"), e("d")],
+            // contrarian system
+            vec![e("d"), e("c"), e("b"), e("a")],
+        ];
+        let weights = agreement_weights(&lists);
+        assert_eq!(weights.len(), 4);
+        for &w in &weights[..3] {
+            assert!(w > 0.5, "unexpected weights {:?}", weights);
+            assert!(w > weights[3], "unexpected weights {:?}", weights);
+        }
+
+        let empty: Vec<Vec<EntryInfo<&str>>> = vec![];
+        assert!(agreement_weights(&empty).is_empty());
+    }
+
+    #[test]
+    fn test_to_score_matrix() {
+        let run1 = crate::trec::parse_from_trec("301 0 d2 0 1 bm25\n301 0 d1 1 0.5 bm25\n").unwrap();
+        let run2 = crate::trec::parse_from_trec("301 0 d3 0 2 dense\n301 0 d2 1 1 dense\n").unwrap();
+
+        let (ids, systems, rows) = to_score_matrix(vec![&run1, &run2]);
+        assert_eq!(ids, vec!["d1", "d2", "d3"]);
+        assert_eq!(systems, vec!["bm25", "dense"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Some(score(0.5)), None],
+                vec![Some(score(1.)), Some(score(1.))],
+                vec![None, Some(score(2.))],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_available_fusers() {
+        let names = available_fusers();
+        for name in &["combMAX", "combSUM", "combMNZ", "RRF"] {
+            assert!(names.contains(name), "{} is not available", name);
+        }
+        for name in names {
+            assert!(score_fuser(name).is_some() || rank_fuser(name).is_some());
+        }
+
+        let scores = [score(1.), score(40.), score(0.5), score(12.)];
+        assert_eq!(score_fuser("combsum").unwrap()(&scores), comb_sum(&scores));
+        assert_eq!(rank_fuser("rrf").unwrap()(&[0, 1]), rrf(&[0, 1]));
+        assert!(score_fuser("RRF").is_none());
+        assert!(score_fuser("combFOO").is_none());
+    }
+
+    #[test]
+    fn test_robust_bounded_influence() {
+        let normal = [score(0.8), score(1.), score(0.9), score(1.1)];
+        let broken = [score(0.8), score(1.), score(0.9), score(1e6)];
+
+        let sum_increase = comb_sum(&broken) - comb_sum(&normal);
+        assert!(sum_increase > 1e5);
+
+        for fuser in &[comb_robust as ScoreFuserFn, comb_huber] {
+            let increase = fuser(&broken) - fuser(&normal);
+            assert!(increase < 1., "unbounded increase: {}", increase);
+        }
     }
 }
@@ -0,0 +1,250 @@
+//! JSON Lines result list and JSON search response parsing module
+use crate::json::{self, Value};
+use crate::trec::{self, ParseError, TrecEntry, TrecEntryOwned};
+use crate::{parsed_score, EntryInfo, Rank};
+use std::io::{self, Write};
+
+/// Parses search results in JSON Lines, one object per line.
+///
+/// Expected format:
+///
+/// `{"qid": "301", "docno": "d1", "rank": 0, "score": 2.5, "runid": "run"}`
+///
+/// `qid`, `docno` and `runid` must be strings, whereas `rank` and `score`
+/// must be numbers. Other fields are ignored, and so are blank lines.
+pub fn parse_from_jsonl(file_data: &str) -> Result<Vec<TrecEntryOwned>, ParseError> {
+    file_data
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            let value = json::parse(l).map_err(ParseError::Other)?;
+            let string_field = |name| match value.get(name) {
+                Some(Value::String(s)) => Ok(s.clone()),
+                Some(v) => Err(ParseError::Other(format!("invalid {} `{}`", name, v))),
+                None => Err(ParseError::Eol(name)),
+            };
+            let qid = string_field("qid")?;
+            let docno = string_field("docno")?;
+            let rank = match value.get("rank") {
+                Some(Value::Number(n)) if *n >= 0. && n.fract() == 0. && *n <= Rank::MAX as f64 => {
+                    *n as Rank
+                }
+                Some(v) => return Err(ParseError::InvalidRank(v.to_string())),
+                None => return Err(ParseError::Eol("rank")),
+            };
+            let score = match value.get("score") {
+                Some(Value::Number(n)) => parsed_score(*n as f32)
+                    .ok_or_else(|| ParseError::InvalidScore(n.to_string()))?,
+                Some(v) => return Err(ParseError::InvalidScore(v.to_string())),
+                None => return Err(ParseError::Eol("score")),
+            };
+            let runid = string_field("runid")?;
+            Ok(TrecEntryOwned {
+                qid,
+                docno,
+                rank,
+                score,
+                runid,
+            })
+        })
+        .collect()
+}
+
+/// Writes search results in JSON Lines, in the format read by
+/// [`parse_from_jsonl`], one object per line.
+///
+/// # Errors
+///
+/// Besides I/O errors, returns an error of kind `InvalidData` if a score is
+/// infinite, as JSON has no number for it.
+///
+/// [`parse_from_jsonl`]: ./fn.parse_from_jsonl.html
+pub fn write_all<'a, I, W>(mut writer: W, list: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = TrecEntry<'a>>,
+    W: Write,
+{
+    let mut line = String::new();
+    for e in list {
+        if !e.score.raw().is_finite() {
+            return Err(trec::invalid_field("score", &e.score.to_string(), "JSON Lines"));
+        }
+        line.clear();
+        line.push_str("{\"qid\": ");
+        json::write_string(&mut line, e.qid);
+        line.push_str(", \"docno\": ");
+        json::write_string(&mut line, e.docno);
+        line.push_str(", \"rank\": ");
+        line.push_str(&e.rank.to_string());
+        line.push_str(", \"score\": ");
+        json::write_number(&mut line, e.score.raw());
+        line.push_str(", \"runid\": ");
+        json::write_string(&mut line, e.runid);
+        line.push('}');
+        writeln!(writer, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Where to find the search hits in a JSON search response, and the fields
+/// of each hit holding its document ID and score.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct HitsFormat<'a> {
+    /// the sequence of object fields leading from the root of the response
+    /// to the array of hits (empty if the response is the array itself)
+    pub hits_path: &'a [&'a str],
+    /// the field of each hit with its document ID (a string or a number)
+    pub id_field: &'a str,
+    /// the field of each hit with its score (a number)
+    pub score_field: &'a str,
+}
+
+impl HitsFormat<'static> {
+    /// Elasticsearch (and OpenSearch) search responses:
+    /// `{"hits": {"hits": [{"_id": "d1", "_score": 2.5}]}}`
+    pub const ELASTICSEARCH: Self = HitsFormat {
+        hits_path: &["hits", "hits"],
+        id_field: "_id",
+        score_field: "_score",
+    };
+
+    /// Meilisearch search responses, with ranking scores enabled:
+    /// `{"hits": [{"id": "d1", "_rankingScore": 0.9}]}`
+    pub const MEILISEARCH: Self = HitsFormat {
+        hits_path: &["hits"],
+        id_field: "id",
+        score_field: "_rankingScore",
+    };
+}
+
+/// Parses the search hits of a JSON search response body, such as those of
+/// Elasticsearch or Meilisearch, so that responses from different search
+/// engines can be fused.
+///
+/// The array of hits is found by following `format.hits_path` from the root
+/// of the response, and each hit must be an object with the document ID in
+/// field `format.id_field` and the score in field `format.score_field`.
+/// Other fields are ignored. The hits keep their order in the response.
+pub fn from_search_hits(body: &str, format: &HitsFormat) -> Result<Vec<EntryInfo<String>>, ParseError> {
+    let mut value = json::parse(body).map_err(ParseError::Other)?;
+    for &field in format.hits_path {
+        value = match value {
+            Value::Object(fields) => fields
+                .into_iter()
+                .find(|(k, _)| k == field)
+                .map(|(_, v)| v)
+                .ok_or_else(|| ParseError::Other(format!("missing field `{}`", field)))?,
+            v => return Err(ParseError::Other(format!("expected object with `{}`, found `{}`", field, v))),
+        };
+    }
+    let hits = match value {
+        Value::Array(hits) => hits,
+        v => return Err(ParseError::Other(format!("expected array of hits, found `{}`", v))),
+    };
+    hits.iter()
+        .map(|hit| {
+            let id = match hit.get(format.id_field) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Number(n)) => n.to_string(),
+                Some(v) => return Err(ParseError::Other(format!("invalid id `{}`", v))),
+                None => return Err(ParseError::Other(format!("missing field `{}`", format.id_field))),
+            };
+            let score = match hit.get(format.score_field) {
+                Some(Value::Number(n)) => parsed_score(*n as f32)
+                    .ok_or_else(|| ParseError::InvalidScore(n.to_string()))?,
+                Some(v) => return Err(ParseError::InvalidScore(v.to_string())),
+                None => return Err(ParseError::Other(format!("missing field `{}`", format.score_field))),
+            };
+            Ok(EntryInfo { id, score })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score;
+
+    #[test]
+    fn test_parse_from_jsonl() {
+        let data = r#"{"qid": "301", "docno": "d1", "rank": 0, "score": 2.5, "runid": "a"}
+
+{"runid": "a", "score": -1, "rank": 1, "docno": "d\"2", "qid": "301", "extra": null}
+"#;
+        let list = parse_from_jsonl(data).unwrap();
+        assert_eq!(
+            list,
+            vec![
+                TrecEntryOwned {
+                    qid: "301".to_string(),
+                    docno: "d1".to_string(),
+                    rank: 0,
+                    score: score(2.5),
+                    runid: "a".to_string(),
+                },
+                TrecEntryOwned {
+                    qid: "301".to_string(),
+                    docno: "d\"2".to_string(),
+                    rank: 1,
+                    score: score(-1.),
+                    runid: "a".to_string(),
+                },
+            ]
+        );
+
+        match parse_from_jsonl(r#"{"qid": "301", "docno": "d1", "rank": 0.5}"#) {
+            Err(ParseError::InvalidRank(s)) => assert_eq!(s, "0.5"),
+            e => panic!("unexpected result {:?}", e),
+        }
+        match parse_from_jsonl(r#"{"qid": "301", "docno": "d1", "rank": 0}"#) {
+            Err(ParseError::Eol("score")) => {}
+            e => panic!("unexpected result {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_write_all() {
+        let data = r#"{"qid": "301", "docno": "d\"1", "rank": 0, "score": 2.5, "runid": "a"}
+{"qid": "301", "docno": "d 2", "rank": 1, "score": -0.1, "runid": "a"}
+"#;
+        let list = parse_from_jsonl(data).unwrap();
+        let mut out = Vec::new();
+        write_all(&mut out, list.iter().map(TrecEntryOwned::as_entry)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), data);
+
+        let entry = TrecEntry {
+            qid: "301",
+            docno: "d1",
+            rank: 0,
+            score: score(f32::INFINITY),
+            runid: "a",
+        };
+        let err = write_all(Vec::new(), vec![entry]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_from_search_hits() {
+        let e = |id: &str, s| EntryInfo { id: id.to_string(), score: score(s) };
+        let body = r#"{"took": 3, "hits": {"total": {"value": 2},
+            "hits": [{"_id": "d1", "_score": 2.5, "_source": {}}, {"_id": "d2", "_score": 1}]}}"#;
+        let list = from_search_hits(body, &HitsFormat::ELASTICSEARCH).unwrap();
+        assert_eq!(list, vec![e("d1", 2.5), e("d2", 1.)]);
+
+        let body = r#"{"hits": [{"id": 7, "_rankingScore": 0.75}], "query": "x"}"#;
+        let list = from_search_hits(body, &HitsFormat::MEILISEARCH).unwrap();
+        assert_eq!(list, vec![e("7", 0.75)]);
+
+        // custom fields, bare array of hits
+        let format = HitsFormat {
+            hits_path: &[],
+            id_field: "doc",
+            score_field: "relevance",
+        };
+        let list = from_search_hits(r#"[{"doc": "a", "relevance": -1}]"#, &format).unwrap();
+        assert_eq!(list, vec![e("a", -1.)]);
+
+        assert!(from_search_hits(r#"{"hits": {}}"#, &HitsFormat::ELASTICSEARCH).is_err());
+        assert!(from_search_hits(r#"{"hits": [{"id": "a"}]}"#, &HitsFormat::MEILISEARCH).is_err());
+    }
+}
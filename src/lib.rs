@@ -30,11 +30,26 @@
 
 use noisy_float::prelude::*;
 use approx::AbsDiffEq;
+use std::borrow::Borrow;
+use std::hash::{Hash, Hasher};
 
 pub use noisy_float;
 pub use approx;
 
+pub mod cache;
+pub mod config;
+pub mod csv;
+pub mod eval;
+pub mod expr;
 pub mod fuser;
+mod json;
+pub mod jsonl;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mmap;
+pub mod normalize;
+pub mod postprocess;
+pub mod report;
+pub mod rng;
 pub mod trec;
 
 pub use fuser::fuse_scored;
@@ -45,6 +60,11 @@ pub type Score = N32;
 /// Type alias for a search result's ran.
 pub type Rank = u32;
 
+/// Retrieves the version of this library, in semantic versioning.
+pub fn crate_version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
 /// Creates a score value.
 /// 
 /// # Panic
@@ -54,6 +74,35 @@ pub fn score(value: f32) -> Score {
     n32(value)
 }
 
+/// Creates a score value from a number read from a result list, as done by
+/// all parsers in this crate. Negative zero is turned into positive zero, so
+/// that it compares, prints and gets fused exactly like 0. Subnormal and
+/// other tiny values are kept as they are: they are still greater than 0
+/// and sort accordingly.
+///
+/// Returns `None` if the given value is `NaN`.
+pub fn parsed_score(value: f32) -> Option<Score> {
+    // -0.0 + 0.0 == +0.0, every other value is unchanged
+    Score::try_new(value + 0.)
+}
+
+/// Retrieves the plain value of a score.
+pub fn raw_score(s: Score) -> f32 {
+    s.raw()
+}
+
+/// Error type for a score which is not a number (`NaN`).
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct NanError;
+
+impl std::fmt::Display for NanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("score is not a number")
+    }
+}
+
+impl std::error::Error for NanError {}
+
 /// A search result entry with a unique document identifier and a similarity
 /// score. Types need to implement this type in order to be admitted as a
 /// search result.
@@ -90,6 +139,40 @@ pub struct EntryInfo<I> {
     pub score: Score,
 }
 
+impl<I> EntryInfo<I> {
+    /// Creates a new entry, validating the given score.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NanError` if the score is `NaN`.
+    pub fn new(id: I, score: f32) -> Result<Self, NanError> {
+        let score = Score::try_new(score).ok_or(NanError)?;
+        Ok(EntryInfo { id, score })
+    }
+
+    /// Creates a new entry without validating the given score. The score is
+    /// still checked in debug builds, in which case this function panics if
+    /// the score is `NaN`.
+    ///
+    /// In release builds, a `NaN` score is not caught, leaving an invalid
+    /// `Score` in the entry, with which sorting and fusion behave in an
+    /// unspecified way. Use [`new`] unless the score is known not to be
+    /// `NaN`.
+    ///
+    /// [`new`]: #method.new
+    pub fn new_unchecked(id: I, score: f32) -> Self {
+        EntryInfo {
+            id,
+            score: n32(score),
+        }
+    }
+
+    /// Retrieves the entry's score as a plain `f32`.
+    pub fn score_f32(&self) -> f32 {
+        self.score.raw()
+    }
+}
+
 impl<I> AbsDiffEq for EntryInfo<I>
 where
     I: PartialEq<I>,
@@ -97,7 +180,7 @@ where
     type Epsilon = f32;
 
     fn default_epsilon() -> Self::Epsilon {
-        1e-5
+        DEFAULT_EPSILON
     }
     
     fn abs_diff_eq(
@@ -110,6 +193,63 @@ where
     }
 }
 
+/// The default tolerance of approximate comparisons between entries, as
+/// the absolute difference between their scores.
+pub const DEFAULT_EPSILON: f32 = 1e-5;
+
+/// Checks whether two lists of entries are approximately equal: they have
+/// the same length, and the entries at each position have the same ID and
+/// scores which differ by at most `epsilon`.
+///
+/// The tolerance should be chosen according to the magnitude of the
+/// scores, as [`DEFAULT_EPSILON`] is too strict for scores in the
+/// thousands, where consecutive `f32` values are further apart, and too
+/// loose for scores which are all close to zero.
+///
+/// [`DEFAULT_EPSILON`]: ./constant.DEFAULT_EPSILON.html
+pub fn entries_approx_eq<I>(a: &[EntryInfo<I>], b: &[EntryInfo<I>], epsilon: f32) -> bool
+where
+    I: PartialEq,
+{
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.abs_diff_eq(y, epsilon))
+}
+
+/// Wrapper type for comparing and hashing search result entries by their
+/// document ID alone, ignoring their score. This enables set operations
+/// over result lists, such as finding the documents retrieved by one
+/// system but not another.
+///
+/// It also implements `Borrow<I>`, so that sets of entries can be queried
+/// by document ID.
+#[derive(Debug, Copy, Clone)]
+pub struct ById<I>(pub EntryInfo<I>);
+
+impl<I> PartialEq for ById<I>
+where
+    I: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.0.id == other.0.id
+    }
+}
+
+impl<I> Eq for ById<I> where I: Eq {}
+
+impl<I> Hash for ById<I>
+where
+    I: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.id.hash(state)
+    }
+}
+
+impl<I> Borrow<I> for ById<I> {
+    fn borrow(&self) -> &I {
+        &self.0.id
+    }
+}
+
 /// Wrapper type for assigning a rank to an arbitrary value.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Ranked<T> {
@@ -145,7 +285,14 @@ pub struct RankedEntryInfo<I> {
     pub rank: Rank,
 }
 
-impl<'a, T: ?Sized> SearchEntry for &'a T
+impl<I> RankedEntryInfo<I> {
+    /// Retrieves the entry's score as a plain `f32`.
+    pub fn score_f32(&self) -> f32 {
+        self.score.raw()
+    }
+}
+
+impl<T: ?Sized> SearchEntry for &T
 where
     T: SearchEntry,
 {
@@ -170,7 +317,7 @@ pub trait RankedSearchEntry: SearchEntry {
     fn rank(&self) -> Rank;
 }
 
-impl<'a, T: ?Sized> RankedSearchEntry for &'a T
+impl<T: ?Sized> RankedSearchEntry for &T
 where
     T: RankedSearchEntry,
 {
@@ -179,6 +326,22 @@ where
     }
 }
 
+/// A ranked search entry which is also aware of the run that produced it,
+/// for keeping track of provenance through fusion.
+pub trait RunSearchEntry: RankedSearchEntry {
+    /// Retrieves the unique ID of the run which produced this entry.
+    fn runid(&self) -> &str;
+}
+
+impl<T: ?Sized> RunSearchEntry for &T
+where
+    T: RunSearchEntry,
+{
+    fn runid(&self) -> &str {
+        (**self).runid()
+    }
+}
+
 impl<I> SearchEntry for EntryInfo<I>
 where
     I: Eq,
@@ -224,6 +387,78 @@ where
     }
 }
 
+/// A plain `(id, score)` pair is also a search entry, for quickly fusing
+/// ad-hoc data. See [`scored_tuples`] for pairs with `f32` scores.
+///
+/// [`scored_tuples`]: fn.scored_tuples.html
+impl<I> SearchEntry for (I, Score)
+where
+    I: Eq,
+{
+    type Id = I;
+
+    fn id(&self) -> &Self::Id {
+        &self.0
+    }
+
+    fn score(&self) -> Score {
+        self.1
+    }
+}
+
+/// Converts `(id, score)` pairs with `f32` scores into search entries.
+///
+/// # Errors
+///
+/// Returns `NanError` if any of the scores is `NaN`, in which case no pair
+/// is converted.
+pub fn scored_tuples<I, L>(list: L) -> Result<Vec<(I, Score)>, NanError>
+where
+    L: IntoIterator<Item = (I, f32)>,
+{
+    list.into_iter()
+        .map(|(id, s)| Ok((id, Score::try_new(s).ok_or(NanError)?)))
+        .collect()
+}
+
+/// Converts `(score, document)` pairs, such as the top documents collected
+/// by a search engine library, into search entries, mapping each document
+/// to its ID with the given function.
+///
+/// This matches the output of tantivy's `TopDocs` collector, a list of
+/// `(Score, DocAddress)` pairs. A `DocAddress` is only meaningful within
+/// one index (and one version of it), so results from different indexes or
+/// queries should be mapped to a stable ID before fusion, typically the
+/// value of a stored field holding the external document key:
+///
+/// ```
+/// # use vindicator::{from_top_docs, fuse_scored};
+/// # use vindicator::fuser::comb_sum;
+/// # #[derive(Copy, Clone)]
+/// # struct DocAddress(u32);
+/// # fn stored_key(addr: DocAddress) -> String { format!("doc{}", addr.0) }
+/// // e.g. `searcher.search(&query, &TopDocs::with_limit(10))?`
+/// let top_docs: Vec<(f32, DocAddress)> = vec![(1.5, DocAddress(3)), (0.5, DocAddress(1))];
+/// let list = from_top_docs(top_docs, stored_key)?;
+/// let fused = fuse_scored(&list, comb_sum);
+/// assert_eq!(fused[0].id, "doc3");
+/// # Ok::<(), vindicator::NanError>(())
+/// ```
+///
+/// # Errors
+///
+/// Returns `NanError` if any of the scores is `NaN`.
+pub fn from_top_docs<D, I, L, F>(top_docs: L, mut id_of: F) -> Result<Vec<EntryInfo<I>>, NanError>
+where
+    L: IntoIterator<Item = (f32, D)>,
+    F: FnMut(D) -> I,
+{
+    top_docs
+        .into_iter()
+        .map(|(s, doc)| EntryInfo::new(id_of(doc), s))
+        .collect()
+}
+
 /// Builds a new iterator containing search results ranked on their order of
 /// appearance.
 pub fn ranked_list<L, R>(results: L) -> impl Iterator<Item = Ranked<R>>
@@ -236,3 +471,190 @@ where
         rank: i as Rank,
     })
 }
+
+/// Derives a pseudo-score from a rank, as `1 / (rank + 1)`.
+pub fn rank_score(rank: Rank) -> Score {
+    n32(1. / (rank as f32 + 1.))
+}
+
+/// Wrapper type for a ranked search result whose score is derived from its
+/// rank, for entries without a meaningful score of their own.
+///
+/// This allows rank-only results to be fused with score-based fusion
+/// algorithms, as the pseudo-score decreases with the rank. See
+/// [`rank_score`].
+///
+/// [`rank_score`]: fn.rank_score.html
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RankScored<T> {
+    /// the inner value
+    pub inner: T,
+}
+
+impl<T> SearchEntry for RankScored<T>
+where
+    T: RankedSearchEntry,
+{
+    type Id = T::Id;
+
+    fn id(&self) -> &Self::Id {
+        self.inner.id()
+    }
+
+    fn score(&self) -> Score {
+        rank_score(self.inner.rank())
+    }
+}
+
+impl<T> RankedSearchEntry for RankScored<T>
+where
+    T: RankedSearchEntry,
+{
+    fn rank(&self) -> Rank {
+        self.inner.rank()
+    }
+}
+
+/// Builds a new iterator containing ranked search results scored by their
+/// rank, ignoring their original scores.
+pub fn rank_scored_list<L, R>(results: L) -> impl Iterator<Item = RankScored<R>>
+where
+    L: IntoIterator<Item = R>,
+    R: RankedSearchEntry,
+{
+    results.into_iter().map(|x| RankScored { inner: x })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_approx_eq() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let a = vec![e("a", 1000.), e("b", 500.)];
+        let b = vec![e("a", 1000.01), e("b", 499.995)];
+
+        assert!(!entries_approx_eq(&a, &b, DEFAULT_EPSILON));
+        assert!(entries_approx_eq(&a, &b, 0.02));
+        assert!(!entries_approx_eq(&a, &b, 0.001));
+        assert!(!entries_approx_eq(&a, &b[..1], 0.02));
+        assert!(!entries_approx_eq(&a, &[e("a", 1000.), e("c", 500.)], 0.02));
+    }
+
+    #[test]
+    fn test_crate_version() {
+        let version: Vec<_> = crate_version().split('.').collect();
+        assert_eq!(version.len(), 3);
+        assert!(version.iter().all(|v| v.parse::<u32>().is_ok()));
+    }
+
+    #[test]
+    fn test_entry_info_new() {
+        let e = EntryInfo::new("doc", 0.5).unwrap();
+        assert_eq!(e, EntryInfo { id: "doc", score: score(0.5) });
+        assert_eq!(e, EntryInfo::new_unchecked("doc", 0.5));
+
+        assert_eq!(EntryInfo::new("doc", f32::NAN), Err(NanError));
+    }
+
+    #[test]
+    fn test_parsed_score() {
+        let zero = parsed_score(-0.).unwrap();
+        assert!(zero.raw().is_sign_positive());
+        assert_eq!(zero.to_string(), "0");
+        assert_eq!(parsed_score(0.).unwrap(), zero);
+
+        let tiny = f32::from_bits(1);
+        assert!(!tiny.is_normal());
+        assert_eq!(parsed_score(tiny).unwrap().raw(), tiny);
+        assert!(parsed_score(tiny).unwrap() > zero);
+        assert!(parsed_score(-tiny).unwrap() < zero);
+        assert_eq!(parsed_score(-1e-30).unwrap().raw(), -1e-30);
+
+        assert_eq!(parsed_score(f32::NAN), None);
+    }
+
+    #[test]
+    fn test_score_f32() {
+        assert_eq!(EntryInfo { id: "doc", score: score(0.5) }.score_f32(), 0.5);
+        assert_eq!(RankedEntryInfo { id: "doc", score: score(-2.), rank: 1 }.score_f32(), -2.);
+        assert_eq!(raw_score(score(0.25)), 0.25);
+    }
+
+    #[test]
+    fn test_fuse_tuples() {
+        let list: Vec<(String, Score)> = vec![
+            ("a".to_string(), score(0.5)),
+            ("b".to_string(), score(2.)),
+            ("a".to_string(), score(1.)),
+        ];
+        let out = fuse_scored(&list, fuser::comb_sum);
+        assert_eq!(
+            out,
+            vec![
+                EntryInfo { id: "b".to_string(), score: score(2.) },
+                EntryInfo { id: "a".to_string(), score: score(1.5) },
+            ]
+        );
+
+        let list = scored_tuples(vec![("a", 0.5), ("b", 0.25)]).unwrap();
+        assert_eq!(list, vec![("a", score(0.5)), ("b", score(0.25))]);
+        assert_eq!(scored_tuples(vec![("a", 0.5), ("b", f32::NAN)]), Err(NanError));
+    }
+
+    #[test]
+    fn test_rank_scored_list() {
+        let list: Vec<_> = (0..5)
+            .map(|rank| RankedEntryInfo {
+                id: rank,
+                score: score(1.),
+                rank,
+            })
+            .collect();
+        let scored: Vec<_> = rank_scored_list(&list).collect();
+
+        assert_eq!(scored[0].score(), 1.);
+        assert_eq!(scored[1].score(), 0.5);
+        for w in scored.windows(2) {
+            assert!(w[0].score() > w[1].score());
+            assert!(w[0].rank() < w[1].rank());
+        }
+
+        let fused = fuser::fuse_scored(rank_scored_list(&list), fuser::comb_sum);
+        let ids: Vec<_> = fused.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_by_id_set_difference() {
+        use std::collections::HashSet;
+
+        let run_a: HashSet<_> = vec![
+            ById(EntryInfo { id: "d1", score: score(1.) }),
+            ById(EntryInfo { id: "d2", score: score(0.5) }),
+            ById(EntryInfo { id: "d3", score: score(0.25) }),
+        ]
+        .into_iter()
+        .collect();
+        let run_b: HashSet<_> = vec![
+            ById(EntryInfo { id: "d2", score: score(4.) }),
+            ById(EntryInfo { id: "d4", score: score(2.) }),
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(run_b.contains(&ById(EntryInfo { id: "d2", score: score(0.5) })));
+        assert!(run_b.contains(&"d2"));
+
+        let mut only_a: Vec<_> = run_a.difference(&run_b).map(|e| e.0).collect();
+        only_a.sort_by_key(|e| e.id);
+        assert_eq!(
+            only_a,
+            vec![
+                EntryInfo { id: "d1", score: score(1.) },
+                EntryInfo { id: "d3", score: score(0.25) },
+            ]
+        );
+    }
+}
@@ -1,104 +1,892 @@
 pub extern crate noisy_float;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{read_to_string, File};
-use std::io::BufWriter;
-use std::path::PathBuf;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use structopt::StructOpt;
 
 use vindicator::*;
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "vindicator", about = "Search result list processing tool.")]
-pub enum App {
+pub struct App {
+    /// Seed for all randomized operations, such as ordering documents with
+    /// equal scores. Runs with the same seed produce the same output.
+    #[structopt(long = "seed", default_value = "0", global = true)]
+    seed: u64,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+// only parsed once, so the size of the merge options does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, StructOpt)]
+pub enum Command {
     #[structopt(name = "merge", about = "Perform late fusion of search result lists")]
     Merge {
-        /// The input lists
+        /// The input lists. Directories are expanded into the run files
+        /// that they contain, sorted by file name
         #[structopt(parse(from_os_str))]
         files: Vec<PathBuf>,
-        /// Result fusion algorithm
-        #[structopt(short = "f")]
-        fuser: Fuser,
-        /// The output's query name
-        #[structopt(short = "q", long = "qid", default_value = "fusion")]
-        qid: String,
+        /// File name pattern of the run files to take from input
+        /// directories, where `*` matches any sequence of characters and `?`
+        /// matches any single character (`*.txt` and `*.run` by default)
+        #[structopt(long = "glob")]
+        glob: Option<String>,
+        /// Result fusion algorithm, by name, ignoring case: combMAX, combSUM,
+        /// combMNZ, combAVG, combROBUST or combHUBER (the `comb` prefix can
+        /// be left out), or RRF, which fuses the ranks of the input lists
+        /// rather than their scores
+        #[structopt(short = "f", required_unless = "fuser-script", conflicts_with = "fuser-script")]
+        fuser: Option<Fuser>,
+        /// Fuse the scores of each document with an expression instead of a
+        /// built-in algorithm, such as `sum(scores) * len(scores)` for
+        /// CombMNZ. Expressions can use numbers, `+ - * / ^`, parentheses,
+        /// `n` for the number of scores, `sum`, `len`, `max`, `min` and
+        /// `mean` of `scores`, and the functions `ln`, `log2`, `exp`, `sqrt`
+        /// and `abs`. Fails if the expression is not a number for some
+        /// document, as with `ln` of a negative sum
+        #[structopt(long = "fuser-script")]
+        fuser_script: Option<expr::Expression>,
+        /// Format of the input lists: trec, csv or jsonl (inferred from
+        /// each file's extension by default)
+        #[structopt(long = "input-format")]
+        input_format: Option<InputFormat>,
+        /// Skip malformed lines of TREC input lists, reporting them to
+        /// stderr, instead of failing
+        #[structopt(long = "skip-errors")]
+        skip_errors: bool,
+        /// Fuse all input lists as a single query with this name. By default,
+        /// each query is fused separately, merging the results with the same
+        /// query ID across all input lists. `-q fusion` gives the single
+        /// query output of versions before per-query fusion
+        #[structopt(short = "q", long = "qid")]
+        qid: Option<String>,
+        /// Remove the longest suffix of each query ID which matches this
+        /// glob pattern (not a regular expression: `*` matches any sequence
+        /// of characters, `?` matches any single character, and all other
+        /// characters match themselves) before grouping, so that queries
+        /// fuse under a shared parent: `.*` removes the first dot and all
+        /// that follows, fusing the sub-topics `301.1` and `301.2` as the
+        /// topic `301`. A document retrieved for several sub-topics in the
+        /// same input list is kept once, with its highest score. The
+        /// stripped query IDs are used everywhere else, including in the
+        /// output, `--disjoint-queries` and `--query-weights`
+        #[structopt(long = "qid-strip", conflicts_with = "qid")]
+        qid_strip: Option<String>,
+        /// Fail if the same query ID appears in more than one input file,
+        /// for input files which are expected to cover distinct queries
+        #[structopt(long = "disjoint-queries")]
+        disjoint_queries: bool,
+        /// Treat the scores of the input lists as distances, where lower is
+        /// better, turning each distance `d` into a score of `1 / (1 + d)`
+        /// before fusion. Fails if a distance is negative
+        #[structopt(long = "distance-scores")]
+        distance_scores: bool,
+        /// Take the weight of each run from its run ID, with a pattern in
+        /// which `{}` stands for the weight, such as `*_w{}` for run IDs
+        /// like `bm25_w2.0`. `*` matches any sequence of characters and `?`
+        /// any single character (regular expressions are not supported).
+        /// Each entry's score is multiplied by its run's weight before
+        /// fusion, and runs which do not match have a weight of 1
+        #[structopt(long = "weight-from-runid")]
+        weight_from_runid: Option<String>,
+        /// File of per-query system weights, with one `qid runid weight`
+        /// line per query and run. Each entry's score is multiplied by the
+        /// weight of its run for its query before fusion (1 by default)
+        #[structopt(parse(from_os_str), long = "query-weights")]
+        query_weights: Option<PathBuf>,
+        /// File with the IDs of the only documents to fuse, one per line.
+        /// All other documents are left out of the output
+        #[structopt(parse(from_os_str), long = "candidates")]
+        candidates: Option<PathBuf>,
+        /// File with the IDs of documents which must all be in the output,
+        /// one per line, for evaluation over a fixed pool. Documents which
+        /// were not retrieved are added to each query with a score of 0,
+        /// before the cutoffs are applied. As the output stays sorted by
+        /// score, they are only at the bottom if no fused score is
+        /// negative: they go before any documents with a negative score,
+        /// as with z-score normalized inputs
+        #[structopt(parse(from_os_str), long = "complete")]
+        complete: Option<PathBuf>,
         /// The output's run name
         #[structopt(long = "runid", default_value = "vindicated")]
         runid: String,
+        /// Output at most this many documents per query
+        #[structopt(long = "top-k")]
+        top_k: Option<usize>,
+        /// Leave out documents with a fused score below this value. This is
+        /// applied before the top-k cutoff
+        #[structopt(long = "min-score")]
+        min_score: Option<f32>,
         /// Output file (print to stdout by default)
         #[structopt(parse(from_os_str), short = "o")]
         output: Option<PathBuf>,
+        /// Also write the provenance of the output to this JSON file: the
+        /// input files and run IDs which contributed to each output
+        /// document, along with their ranks and scores
+        #[structopt(parse(from_os_str), long = "provenance")]
+        provenance: Option<PathBuf>,
+        /// Replace the scores in the output with each document's rank,
+        /// hiding the fused scores (the original scores cannot be recovered)
+        #[structopt(long = "scores-as-rank")]
+        scores_as_rank: bool,
+        /// Write the output in the strict run format of ir_measures and
+        /// trec_eval: a literal `Q0` column, ranks starting at 1, and scores
+        /// always written as floating point numbers. Fails if a document ID
+        /// contains whitespace
+        #[structopt(long = "ir-measures-compat", conflicts_with = "scores-as-rank")]
+        ir_measures_compat: bool,
+        /// How to order documents with equal fused scores: by document ID
+        /// (docno), by descending document ID (treceval), by order of first
+        /// appearance in the input lists (input), as left by the fusion,
+        /// which also follows first appearance (score), or randomly,
+        /// according to the seed (random). trec_eval
+        /// ignores the ranks in a run and sorts ties by descending document
+        /// ID, so treceval makes the output's ranks agree with the ranking
+        /// that trec_eval evaluates
+        #[structopt(long = "tie-break", default_value = "docno")]
+        tie_break: TieBreak,
+        /// Print the number of documents retrieved by each input list and
+        /// their overlap, per query, to stderr
+        #[structopt(long = "stats")]
+        stats: bool,
+        /// Evaluate the output against the relevance judgments in this
+        /// qrels file, printing the mean average precision, precision at 10
+        /// and nDCG at 10 over the judged queries to stderr
+        #[structopt(parse(from_os_str), long = "qrels")]
+        qrels: Option<PathBuf>,
+        /// Report progress to stderr: the lines and entries read from each
+        /// input list, and the documents fused across all queries
+        #[structopt(long = "progress")]
+        progress: bool,
+        /// Do not print progress or warnings about queries missing from
+        /// some input lists to stderr
+        #[structopt(long = "quiet")]
+        quiet: bool,
+    },
+    #[structopt(name = "lint", about = "Check TREC run files for problems affecting fusion")]
+    Lint {
+        /// The run files to check
+        #[structopt(parse(from_os_str))]
+        files: Vec<PathBuf>,
+        /// File with the IDs of the topics that each run should have results
+        /// for, one per line. By default, a run is checked against the query
+        /// IDs found in all of the given runs
+        #[structopt(parse(from_os_str), long = "topics")]
+        topics: Option<PathBuf>,
     },
+    #[structopt(name = "split", about = "Split a search result list into one TREC file per query")]
+    Split {
+        /// The input list
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Format of the input list: trec, csv or jsonl (inferred from the
+        /// file's extension by default)
+        #[structopt(long = "from")]
+        from: Option<InputFormat>,
+        /// Directory to write the files to, created if it does not exist.
+        /// Each file is named after its query ID, with characters other
+        /// than ASCII letters, digits, `-`, `_` and `.` replaced by `_`,
+        /// and a `.txt` extension
+        #[structopt(parse(from_os_str), short = "o")]
+        output: PathBuf,
+    },
+    #[structopt(name = "concat", about = "Concatenate partial TREC runs covering distinct queries")]
+    Concat {
+        /// The partial runs, such as those fused by separate processes over
+        /// shards of the queries. Fails if a query is in more than one run,
+        /// or if its results are not ranked one by one from 0 or 1
+        #[structopt(parse(from_os_str))]
+        files: Vec<PathBuf>,
+        /// Output file (print to stdout by default)
+        #[structopt(parse(from_os_str), short = "o")]
+        output: Option<PathBuf>,
+    },
+    #[structopt(name = "convert", about = "Convert a search result list to another format")]
+    Convert {
+        /// The input list
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Format of the input list: trec, csv or jsonl (inferred from the
+        /// file's extension by default)
+        #[structopt(long = "from")]
+        from: Option<InputFormat>,
+        /// Format of the output list: trec, csv or jsonl. All fields are
+        /// kept, except for the reserved column of TREC lists, which is
+        /// always written as 0. IDs which cannot be written in the output
+        /// format, such as those with whitespace in TREC or with commas in
+        /// CSV, are reported as errors, and so are infinite scores in JSONL
+        #[structopt(long = "to")]
+        to: InputFormat,
+        /// Output file (print to stdout by default)
+        #[structopt(parse(from_os_str), short = "o")]
+        output: Option<PathBuf>,
+    },
+}
+
+/// A fusion algorithm picked at run time, either built in or from
+/// `--fuser-script`.
+enum DynFuser {
+    Score(BoxedScoreFuser),
+    Rank(fuser::RankFuserFn),
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, StructOpt)]
+type BoxedScoreFuser = Box<dyn Fn(&[Score]) -> Score>;
+
+/// A built-in fusion algorithm, looked up in the `fuser` module by name.
+#[derive(Debug, Copy, Clone)]
 pub enum Fuser {
-    #[structopt(name = "combMAX", alias = "combmax")]
-    CombMax,
-    #[structopt(name = "combSUM", alias = "combsum")]
-    CombSum,
-    #[structopt(name = "combMNZ", alias = "combmnz")]
-    CombMnz,
+    Score(fuser::ScoreFuserFn),
+    Rank(fuser::RankFuserFn),
 }
 
 impl std::str::FromStr for Fuser {
     type Err = String;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fuser::score_fuser(s)
+            .or_else(|| fuser::score_fuser(&format!("comb{}", s)))
+            .map(Fuser::Score)
+            .or_else(|| fuser::rank_fuser(s).map(Fuser::Rank))
+            .ok_or_else(|| {
+                format!(
+                    "Unknown fusion algorithm `{}` (expected one of {})",
+                    s,
+                    fuser::available_fusers().join(", ")
+                )
+            })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TieBreak {
+    DocNo,
+    TrecEval,
+    Input,
+    Score,
+    Random,
+}
+
+impl std::str::FromStr for TieBreak {
+    type Err = String;
+
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "combMAX" | "combmax" | "max" => Ok(Fuser::CombMax),
-            "combSUM" | "combsum" | "sum" => Ok(Fuser::CombMax),
-            "combMNZ" | "combmnz" | "mnz" => Ok(Fuser::CombMax),
-            _ => Err(format!("Unknown fusion algorithm `{}`", s)),
+            "docno" => Ok(TieBreak::DocNo),
+            "treceval" => Ok(TieBreak::TrecEval),
+            "input" => Ok(TieBreak::Input),
+            "score" => Ok(TieBreak::Score),
+            "random" => Ok(TieBreak::Random),
+            _ => Err(format!("Unknown tie-break policy `{}`", s)),
         }
     }
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InputFormat {
+    Trec,
+    Csv,
+    Jsonl,
+}
+
+impl InputFormat {
+    /// Infers the format of a file from its extension, assuming TREC when
+    /// unknown.
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => InputFormat::Csv,
+            Some("jsonl") | Some("json") => InputFormat::Jsonl,
+            _ => InputFormat::Trec,
+        }
+    }
+
+    fn parse(self, data: &str) -> Result<Vec<trec::TrecEntryOwned>, trec::ParseError> {
+        match self {
+            InputFormat::Trec => Ok(trec::parse_from_trec(data)?
+                .iter()
+                .map(trec::TrecEntry::to_owned)
+                .collect()),
+            InputFormat::Csv => Ok(csv::parse_from_csv(data)?
+                .iter()
+                .map(trec::TrecEntry::to_owned)
+                .collect()),
+            InputFormat::Jsonl => jsonl::parse_from_jsonl(data),
+        }
+    }
+
+    fn write<W: Write>(self, writer: W, entries: &[trec::TrecEntryOwned]) -> std::io::Result<()> {
+        let entries = entries.iter().map(trec::TrecEntryOwned::as_entry);
+        match self {
+            InputFormat::Trec => {
+                let entries: Vec<_> = entries.collect();
+                for e in &entries {
+                    trec::check_field("query ID", e.qid)?;
+                    trec::check_field("document ID", e.docno)?;
+                    trec::check_field("run ID", e.runid)?;
+                }
+                trec::write_all(writer, entries)
+            }
+            InputFormat::Csv => csv::write_all(writer, entries),
+            InputFormat::Jsonl => jsonl::write_all(writer, entries),
+        }
+    }
+}
+
+impl std::str::FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trec" => Ok(InputFormat::Trec),
+            "csv" => Ok(InputFormat::Csv),
+            "jsonl" => Ok(InputFormat::Jsonl),
+            _ => Err(format!("Unknown format `{}`", s)),
+        }
+    }
+}
+
+/// Checks whether a file name matches a pattern with `*` and `?` wildcards.
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+    let name: Vec<char> = name.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    // position of the last `*` in the pattern, and of the name when it was found
+    let mut star = None;
+    let (mut i, mut j) = (0, 0);
+    while i < name.len() {
+        match pattern.get(j) {
+            Some('*') => {
+                star = Some((j, i));
+                j += 1;
+            }
+            Some(&c) if c == '?' || c == name[i] => {
+                i += 1;
+                j += 1;
+            }
+            _ => match star {
+                Some((sj, si)) => {
+                    j = sj + 1;
+                    i = si + 1;
+                    star = Some((sj, si + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[j..].iter().all(|&c| c == '*')
+}
+
+/// Extracts a number from a run ID, with a pattern where `{}` marks the
+/// number and the rest is matched as in `matches_pattern`.
+fn extract_weight(runid: &str, pattern: &str) -> Option<f32> {
+    let marker = pattern.find("{}")?;
+    let (prefix, suffix) = (&pattern[..marker], &pattern[marker + 2..]);
+    let is_numeric = |c: char| c.is_ascii_digit() || c == '.' || c == '-' || c == '+';
+    for (start, _) in runid.char_indices() {
+        if !matches_pattern(&runid[..start], prefix) {
+            continue;
+        }
+        let len = runid[start..].find(|c| !is_numeric(c)).unwrap_or(runid.len() - start);
+        // prefer the longest number
+        for end in (start + 1..=start + len).rev() {
+            if matches_pattern(&runid[end..], suffix) {
+                if let Some(weight) = runid[start..end].parse::<f32>().ok().filter(|w| !w.is_nan()) {
+                    return Some(weight);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Removes the longest suffix of a query ID which matches a pattern, as in
+/// `matches_pattern`, leaving at least one character.
+fn strip_qid<'a>(qid: &'a str, pattern: &str) -> &'a str {
+    match qid.char_indices().skip(1).find(|&(i, _)| matches_pattern(&qid[i..], pattern)) {
+        Some((i, _)) => &qid[..i],
+        None => qid,
+    }
+}
+
+/// Expands the directories in the given list of paths into the run files
+/// that they contain, sorted by file name.
+fn expand_dirs(paths: Vec<PathBuf>, glob: Option<&str>) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        let mut dir_files = Vec::new();
+        for entry in std::fs::read_dir(&path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let matches = match glob {
+                Some(glob) => matches_pattern(&name, glob),
+                None => name.ends_with(".txt") || name.ends_with(".run"),
+            };
+            if matches && entry.file_type()?.is_file() {
+                dir_files.push(entry.path());
+            }
+        }
+        dir_files.sort();
+        files.extend(dir_files);
+    }
+    Ok(files)
+}
+
+/// Reads a file of document IDs, one per line, skipping blank lines.
+fn read_ids(path: &Path) -> std::io::Result<Vec<String>> {
+    Ok(read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Checks that no query ID appears in more than one input file.
+fn check_disjoint_queries(
+    paths: &[PathBuf],
+    files: &[Vec<trec::TrecEntryOwned>],
+) -> Result<(), String> {
+    let mut owners: BTreeMap<&str, usize> = BTreeMap::new();
+    for (i, entries) in files.iter().enumerate() {
+        for e in entries {
+            let owner = *owners.entry(&e.qid).or_insert(i);
+            if owner != i {
+                return Err(format!(
+                    "query `{}` appears in both {} and {}",
+                    e.qid,
+                    paths[owner].display(),
+                    paths[i].display()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Accumulates the contributions of each input file to each query, one
+/// system per file, so that system indices match the file positions. With
+/// `qid`, all entries belong to a single query of that name.
+fn accumulate_queries(
+    files: &[Vec<trec::TrecEntryOwned>],
+    qid: Option<&str>,
+) -> BTreeMap<String, fuser::ScoreAccumulator<String>> {
+    let mut queries: BTreeMap<&str, Vec<Vec<&trec::TrecEntryOwned>>> = BTreeMap::new();
+    for (i, entries) in files.iter().enumerate() {
+        for e in entries {
+            queries
+                .entry(qid.unwrap_or(&e.qid))
+                .or_insert_with(|| vec![Vec::new(); files.len()])[i]
+                .push(e);
+        }
+    }
+    queries
+        .into_iter()
+        .map(|(q, lists)| {
+            let mut acc = fuser::ScoreAccumulator::new();
+            for list in lists {
+                acc.add_run_list(list);
+            }
+            (q.to_string(), acc)
+        })
+        .collect()
+}
+
+/// Opens the output of a command: the given file, or stdout if none. Both
+/// are buffered, since stdout on its own is flushed on every line, which
+/// dominates the run time on large outputs. The writer must be flushed
+/// explicitly at the end, so that write errors are not ignored.
+fn open_output(output: Option<PathBuf>) -> std::io::Result<Box<dyn Write>> {
+    Ok(match output {
+        Some(o) => Box::new(BufWriter::new(File::create(o)?)),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    })
+}
+
+/// Evaluates fused lists against relevance judgments in the TREC qrels
+/// format, printing the mean of each metric over the queries which have
+/// both judgments and a fused list to stderr, in the format of trec_eval.
+fn print_evaluation(qrels: &str, fused: &[(String, Vec<EntryInfo<String>>)]) -> Result<(), trec::ParseError> {
+    let mut judgments: HashMap<&str, HashMap<String, i32>> = HashMap::new();
+    for q in trec::parse_qrels(qrels)? {
+        judgments.entry(q.qid).or_default().insert(q.docno.to_string(), q.relevance);
+    }
+    let mut sums = [0.; 3];
+    let mut num_queries = 0;
+    for (qid, list) in fused {
+        if let Some(judgments) = judgments.get(&qid[..]) {
+            sums[0] += eval::average_precision(list, judgments);
+            sums[1] += eval::precision_at_k(list, judgments, 10);
+            sums[2] += eval::ndcg_at_k(list, judgments, 10);
+            num_queries += 1;
+        }
+    }
+    eprintln!("num_q\tall\t{}", num_queries);
+    for (name, sum) in ["map", "P_10", "ndcg_cut_10"].iter().zip(&sums) {
+        let mean = if num_queries > 0 { sum / num_queries as f32 } else { 0. };
+        eprintln!("{}\tall\t{:.4}", name, mean);
+    }
+    Ok(())
+}
+
+/// Prints a fusion report for each query to stderr, one list per file.
+fn print_stats(files: &[Vec<trec::TrecEntryOwned>]) {
+    let mut queries: BTreeMap<&str, Vec<Vec<&trec::TrecEntryOwned>>> = BTreeMap::new();
+    for (i, entries) in files.iter().enumerate() {
+        for e in entries {
+            queries
+                .entry(&e.qid)
+                .or_insert_with(|| vec![Vec::new(); files.len()])[i]
+                .push(e);
+        }
+    }
+    for (qid, lists) in queries {
+        eprintln!("{}: {}", qid, report::fusion_report(lists));
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let app = App::from_args();
+    let mut rng = rng::Rng::new(app.seed);
 
-    match app {
-        App::Merge {
+    match app.command {
+        Command::Merge {
             files,
+            glob,
             fuser,
+            fuser_script,
+            input_format,
+            skip_errors,
             output,
+            provenance,
             qid,
+            qid_strip,
+            disjoint_queries,
+            distance_scores,
+            weight_from_runid,
+            query_weights,
+            candidates,
+            complete,
+            top_k,
+            min_score,
             runid,
+            stats,
+            qrels,
+            progress,
+            quiet,
+            scores_as_rank,
+            ir_measures_compat,
+            tie_break,
         } => {
-            let files_data = files
+            let files = expand_dirs(files, glob.as_deref())?;
+            let progress = progress && !quiet;
+            let mut entries = files
                 .iter()
-                .map(read_to_string)
+                .enumerate()
+                .map(|(i, path)| -> Result<_, Box<dyn std::error::Error>> {
+                    let data = read_to_string(path)?;
+                    let format = input_format.unwrap_or_else(|| InputFormat::from_path(path));
+                    let list = if skip_errors && format == InputFormat::Trec {
+                        let (list, errors) = trec::parse_from_trec_lenient(&data);
+                        for (line, e) in &errors {
+                            eprintln!("{}:{}: {}", path.display(), line, e);
+                        }
+                        if !errors.is_empty() {
+                            eprintln!("{}: skipped {} malformed lines", path.display(), errors.len());
+                        }
+                        list.iter().map(trec::TrecEntry::to_owned).collect()
+                    } else {
+                        format.parse(&data)?
+                    };
+                    if progress {
+                        eprintln!(
+                            "[{}/{}] {}: {} lines, {} entries",
+                            i + 1,
+                            files.len(),
+                            path.display(),
+                            data.lines().count(),
+                            list.len()
+                        );
+                    }
+                    Ok(list)
+                })
                 .collect::<Result<Vec<_>, _>>()?;
-            let entries = files_data
+            if let Some(pattern) = &qid_strip {
+                for list in &mut entries {
+                    for e in list.iter_mut() {
+                        let len = strip_qid(&e.qid, pattern).len();
+                        e.qid.truncate(len);
+                    }
+                    // a document may now be in the same query more than once
+                    trec::dedup_documents(list);
+                }
+            }
+            if stats {
+                print_stats(&entries);
+            }
+            if disjoint_queries {
+                check_disjoint_queries(&files, &entries)?;
+            } else if qid.is_none() && !quiet {
+                let coverage = report::validate_query_coverage(entries.iter().map(|l| l.iter().map(|e| &e.qid)));
+                for (path, missing) in files.iter().zip(&coverage.missing) {
+                    if !missing.is_empty() {
+                        eprintln!(
+                            "warning: {} has no results for {} of {} queries: {}",
+                            path.display(),
+                            missing.len(),
+                            coverage.queries.len(),
+                            missing.join(", ")
+                        );
+                    }
+                }
+            }
+
+            if distance_scores {
+                for (path, list) in files.iter().zip(&mut entries) {
+                    for e in list.iter_mut() {
+                        e.score = normalize::invert_score(e.score).map_err(|err| {
+                            format!("{}: query {}, document {}: {}", path.display(), e.qid, e.docno, err)
+                        })?;
+                    }
+                }
+            }
+
+            if let Some(pattern) = weight_from_runid {
+                if pattern.matches("{}").count() != 1 {
+                    return Err(format!("weight pattern `{}` must contain `{{}}` exactly once", pattern).into());
+                }
+                let mut weights: HashMap<String, f32> = HashMap::new();
+                for e in entries.iter_mut().flatten() {
+                    if !weights.contains_key(&e.runid) {
+                        let weight = extract_weight(&e.runid, &pattern).unwrap_or(1.);
+                        weights.insert(e.runid.clone(), weight);
+                    }
+                    e.score *= weights[&e.runid];
+                }
+            }
+
+            if let Some(path) = query_weights {
+                let weights = trec::parse_query_weights(&read_to_string(path)?)?;
+                trec::apply_query_weights(entries.iter_mut().flatten(), &weights);
+            }
+
+            let accs = provenance
+                .as_ref()
+                .map(|_| accumulate_queries(&entries, qid.as_deref()));
+
+            // group the entries of all lists by query
+            let mut queries: BTreeMap<String, Vec<trec::TrecEntryOwned>> = BTreeMap::new();
+            for e in entries.into_iter().flatten() {
+                let key = match &qid {
+                    Some(qid) => qid.clone(),
+                    None => e.qid.clone(),
+                };
+                queries.entry(key).or_default().push(e);
+            }
+
+            let candidates: Option<HashSet<String>> = match candidates {
+                Some(path) => Some(read_ids(&path)?.into_iter().collect()),
+                None => None,
+            };
+            let complete = match complete {
+                Some(path) => Some(read_ids(&path)?),
+                None => None,
+            };
+
+            // an evaluation error of --fuser-script, reported after fusing its query
+            let eval_error: Rc<Cell<Option<expr::EvalError>>> = Rc::default();
+            let fuser: DynFuser = match (fuser, fuser_script) {
+                (_, Some(expression)) => {
+                    let eval_error = Rc::clone(&eval_error);
+                    DynFuser::Score(Box::new(move |scores: &[Score]| {
+                        expression.eval(scores).unwrap_or_else(|e| {
+                            eval_error.set(Some(e));
+                            score(0.)
+                        })
+                    }))
+                }
+                (Some(Fuser::Score(f)), None) => DynFuser::Score(Box::new(f)),
+                (Some(Fuser::Rank(f)), None) => DynFuser::Rank(f),
+                (None, None) => unreachable!("either -f or --fuser-script is required"),
+            };
+            let mut fused = Vec::with_capacity(queries.len());
+            for (qid, list) in queries {
+                let mut fused_list = match (&fuser, &candidates) {
+                    (DynFuser::Score(f), Some(allowed)) => fuser::fuse_scored_restricted(&list, &**f, allowed),
+                    (DynFuser::Score(f), None) => fuser::fuse_scored(&list, &**f),
+                    (DynFuser::Rank(f), Some(allowed)) => {
+                        fuser::fuse_ranked(list.iter().filter(|e| allowed.contains(&e.docno)), f)
+                    }
+                    (DynFuser::Rank(f), None) => fuser::fuse_ranked(&list, f),
+                };
+                if let Some(e) = eval_error.take() {
+                    return Err(format!("query {}: {}", qid, e).into());
+                }
+                if let Some(pool) = &complete {
+                    fused_list = postprocess::complete(fused_list, pool.iter().cloned());
+                }
+                match tie_break {
+                    TieBreak::DocNo => {
+                        postprocess::sort_ties_by(&mut fused_list, |a, b| a.id.cmp(&b.id))
+                    }
+                    TieBreak::TrecEval => {
+                        postprocess::sort_ties_by(&mut fused_list, |a, b| b.id.cmp(&a.id))
+                    }
+                    TieBreak::Input => {
+                        let mut first_seen = HashMap::new();
+                        for (i, e) in list.iter().enumerate() {
+                            first_seen.entry(&e.docno).or_insert(i);
+                        }
+                        // documents added by --complete go last, in pool order
+                        let position = |id: &String| first_seen.get(id).cloned().unwrap_or(usize::MAX);
+                        postprocess::sort_ties_by(&mut fused_list, |a, b| position(&a.id).cmp(&position(&b.id)))
+                    }
+                    TieBreak::Score => {}
+                    TieBreak::Random => postprocess::shuffle_ties(&mut fused_list, &mut rng),
+                }
+                let fused_list = postprocess::finalize(fused_list, top_k, min_score);
+                fused.push((qid, fused_list));
+            }
+            if progress {
+                let num_documents: usize = fused.iter().map(|(_, list)| list.len()).sum();
+                eprintln!("fused {} documents over {} queries", num_documents, fused.len());
+            }
+
+            // create output stream
+            let mut writer = open_output(output)?;
+            for (qid, list) in &fused {
+                if ir_measures_compat {
+                    trec::write_all_ir_measures(&mut writer, qid, &runid, list)?;
+                } else if scores_as_rank {
+                    let list = list.iter().enumerate().map(|(i, e)| trec::TrecEntry {
+                        qid,
+                        docno: &e.id,
+                        rank: i as Rank,
+                        score: e.score(),
+                        runid: &runid,
+                    });
+                    trec::write_all_scores_as_rank(&mut writer, list)?;
+                } else {
+                    trec::write_all_ranked(&mut writer, qid, &runid, list)?;
+                }
+            }
+
+            writer.flush()?;
+
+            if let Some(path) = qrels {
+                print_evaluation(&read_to_string(path)?, &fused)?;
+            }
+
+            if let (Some(path), Some(accs)) = (provenance, accs) {
+                let sources: Vec<String> = files.iter().map(|p| p.display().to_string()).collect();
+                let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+                let queries = fused
+                    .iter()
+                    .map(|(qid, list)| (&qid[..], &accs[qid], &list[..]));
+                report::write_provenance(BufWriter::new(File::create(path)?), &sources, queries)?;
+            }
+        }
+        Command::Lint { files, topics } => {
+            let data = files.iter().map(read_to_string).collect::<Result<Vec<_>, _>>()?;
+            let runs = data
                 .iter()
                 .map(|data| trec::parse_from_trec(data))
                 .collect::<Result<Vec<_>, _>>()?;
-            let list: Vec<_> = entries.into_iter().flatten().collect();
-            if let Some(list) = match fuser {
-                Fuser::CombMax => Some(fuser::fuse_scored(list, fuser::comb_max)),
-                Fuser::CombSum => Some(fuser::fuse_scored(list, fuser::comb_sum)),
-                Fuser::CombMnz => Some(fuser::fuse_scored(list, fuser::comb_mnz)),
-            } {
-                // transform results into new list
-                let list = list.into_iter().enumerate().map(|(i, e)| trec::TrecEntry {
-                    qid: &qid,
-                    docno: *e.id(),
-                    rank: i as Rank,
-                    score: e.score(),
-                    runid: &runid,
-                });
-
-                // create output stream
-                match output {
-                    Some(o) => {
-                        let file = BufWriter::new(File::create(o)?);
-                        trec::write_all(file, list)?;
-                    }
-                    None => {
-                        trec::write_all(std::io::stdout(), list)?;
-                    }
+            let topics: Vec<String> = match topics {
+                Some(path) => read_ids(&path)?,
+                None => {
+                    let mut seen = HashSet::new();
+                    runs.iter()
+                        .flatten()
+                        .filter(|e| seen.insert(e.qid))
+                        .map(|e| e.qid.to_string())
+                        .collect()
+                }
+            };
+            let topics: Vec<&str> = topics.iter().map(String::as_str).collect();
+            for (path, run) in files.iter().zip(&runs) {
+                for warning in trec::lint_trec(run, Some(&topics)) {
+                    println!("{}: {}", path.display(), warning);
                 }
             }
         }
+        Command::Split { file, from, output } => {
+            let format = from.unwrap_or_else(|| InputFormat::from_path(&file));
+            let entries = format.parse(&read_to_string(&file)?)?;
+            std::fs::create_dir_all(&output)?;
+            trec::split_by_query(entries.iter().map(trec::TrecEntryOwned::as_entry), &output)?;
+        }
+        Command::Concat { files, output } => {
+            // report which files overlap, rather than the error's fields
+            let entries = trec::merge_partial_runs(&files).map_err(|e| e.to_string())?;
+            let mut writer = open_output(output)?;
+            trec::write_all(&mut writer, entries.iter().map(trec::TrecEntryOwned::as_entry))?;
+            writer.flush()?;
+        }
+        Command::Convert { file, from, to, output } => {
+            let format = from.unwrap_or_else(|| InputFormat::from_path(&file));
+            let entries = format.parse(&read_to_string(&file)?)?;
+            let mut writer = open_output(output)?;
+            to.write(&mut writer, &entries)?;
+            writer.flush()?;
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_weight, matches_pattern, strip_qid, Fuser};
+    use vindicator::fuser::available_fusers;
+
+    #[test]
+    fn test_parse_fuser() {
+        for name in available_fusers() {
+            assert!(name.parse::<Fuser>().is_ok(), "{} is not accepted", name);
+            assert!(name.to_lowercase().parse::<Fuser>().is_ok(), "{} is not accepted", name);
+        }
+        assert!(matches!("sum".parse(), Ok(Fuser::Score(_))));
+        assert!(matches!("rrf".parse(), Ok(Fuser::Rank(_))));
+        assert!("combfoo".parse::<Fuser>().is_err());
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("run1.txt", "*.txt"));
+        assert!(matches_pattern("run1.txt", "run?.*"));
+        assert!(matches_pattern("run1.txt", "*"));
+        assert!(matches_pattern("a.b.txt", "*.*.txt"));
+        assert!(!matches_pattern("run1.txt", "*.run"));
+        assert!(!matches_pattern("run10.txt", "run?.txt"));
+        assert!(!matches_pattern("run1.txt.bak", "*.txt"));
+    }
+
+    #[test]
+    fn test_extract_weight() {
+        assert_eq!(extract_weight("bm25_w2.0", "*_w{}"), Some(2.));
+        assert_eq!(extract_weight("bm25_w0.5", "bm25_w{}"), Some(0.5));
+        assert_eq!(extract_weight("w3-dense", "w{}-*"), Some(3.));
+        assert_eq!(extract_weight("run_w1.5_v2", "*_w{}_v?"), Some(1.5));
+        assert_eq!(extract_weight("bm25_w12", "*_w{}"), Some(12.));
+        assert_eq!(extract_weight("bm25", "*_w{}"), None);
+        assert_eq!(extract_weight("bm25_wx", "*_w{}"), None);
+    }
+
+    #[test]
+    fn test_strip_qid() {
+        assert_eq!(strip_qid("301.1", ".*"), "301");
+        assert_eq!(strip_qid("301.2.1", ".*"), "301");
+        assert_eq!(strip_qid("301", ".*"), "301");
+        assert_eq!(strip_qid("301-a", "-?"), "301");
+        assert_eq!(strip_qid("301-ab", "-?"), "301-ab");
+        assert_eq!(strip_qid(".1", ".*"), ".1");
+        assert_eq!(strip_qid("301", "*"), "3");
+    }
+}
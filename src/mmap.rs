@@ -0,0 +1,113 @@
+//! Memory-mapped run files, for parsing large files without reading them
+//! into memory first.
+//!
+//! This module requires the `mmap` feature, and is only available on Unix
+//! platforms.
+use crate::trec::{self, ParseError, TrecEntry};
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// A read-only memory mapping of a whole file.
+///
+/// The mapped contents are borrowed directly by the entries parsed from
+/// them, so the mapping must outlive them.
+#[derive(Debug)]
+pub struct MappedFile {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl MappedFile {
+    /// Maps the file at the given path into memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the mapping is
+    /// alive, including by other processes. Changes to the file show up in
+    /// the mapped contents, which are assumed to be immutable, and reading
+    /// beyond the end of a truncated file terminates the process with
+    /// `SIGBUS`. This is only safe to use on run files that nothing else
+    /// writes to.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            // empty mappings are not allowed
+            return Ok(MappedFile {
+                ptr: std::ptr::null_mut(),
+                len,
+            });
+        }
+        let ptr = libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(MappedFile { ptr, len })
+    }
+
+    /// Retrieves the mapped contents.
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // the mapping is valid and readable for `len` bytes until dropped
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+
+    /// Retrieves the mapped contents as text.
+    pub fn as_str(&self) -> Result<&str, ParseError> {
+        std::str::from_utf8(self.as_bytes()).map_err(|e| ParseError::Other(e.to_string()))
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+/// Parses a memory-mapped file in the TREC result list format. This is the
+/// same as [`parse_from_trec`] on the file's contents, with the entries
+/// borrowing from the mapping.
+///
+/// [`parse_from_trec`]: ../trec/fn.parse_from_trec.html
+pub fn parse_from_trec_mmap(file: &MappedFile) -> Result<Vec<TrecEntry<'_>>, ParseError> {
+    trec::parse_from_trec(file.as_str()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_from_trec_mmap() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("vindicator-test-mmap-{}.txt", std::process::id()));
+        let data = "301 0 d1 0 1.5 run\n301 0 d2 1 0.5 run\n";
+        std::fs::write(&path, data).unwrap();
+        let file = unsafe { MappedFile::open(&path) }.unwrap();
+        let entries = parse_from_trec_mmap(&file).unwrap();
+        assert_eq!(entries, trec::parse_from_trec(data).unwrap());
+
+        let empty_path = dir.join(format!("vindicator-test-mmap-empty-{}.txt", std::process::id()));
+        std::fs::write(&empty_path, "").unwrap();
+        let empty = unsafe { MappedFile::open(&empty_path) }.unwrap();
+        assert_eq!(parse_from_trec_mmap(&empty).unwrap(), vec![]);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&empty_path).unwrap();
+    }
+}
@@ -0,0 +1,581 @@
+//! Score normalization and calibration.
+use crate::{EntryInfo, Score, SearchEntry};
+use noisy_float::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+/// A score normalization method, which maps the scores of a system's
+/// results onto a common scale, in place, before they are fused.
+///
+/// Normalizers keep the order of the entries, and are object safe, so they
+/// can be chosen at run time and stored as `Box<dyn Normalizer<I>>`.
+pub trait Normalizer<I> {
+    /// Normalizes the scores of one list of results.
+    fn normalize(&self, entries: &mut [EntryInfo<I>]);
+}
+
+impl<I, N: ?Sized> Normalizer<I> for Box<N>
+where
+    N: Normalizer<I>,
+{
+    fn normalize(&self, entries: &mut [EntryInfo<I>]) {
+        (**self).normalize(entries)
+    }
+}
+
+/// Min-max normalization: scales scores linearly into the range `[0, 1]`,
+/// so that the lowest score becomes 0 and the highest score becomes 1.
+/// If all scores are equal, they all become 1.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct MinMax;
+
+impl<I> Normalizer<I> for MinMax {
+    fn normalize(&self, entries: &mut [EntryInfo<I>]) {
+        let min = entries.iter().map(|e| e.score).min();
+        let max = entries.iter().map(|e| e.score).max();
+        if let (Some(min), Some(max)) = (min, max) {
+            for e in entries {
+                e.score = if max > min {
+                    (e.score - min) / (max - min)
+                } else {
+                    n32(1.)
+                };
+            }
+        }
+    }
+}
+
+/// Z-score normalization: subtracts the mean score and divides by the
+/// (population) standard deviation of the scores. If all scores are equal,
+/// they all become 0.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct ZScore;
+
+impl<I> Normalizer<I> for ZScore {
+    fn normalize(&self, entries: &mut [EntryInfo<I>]) {
+        if entries.is_empty() {
+            return;
+        }
+        let n = entries.len() as f32;
+        let mean = entries.iter().map(|e| e.score.raw()).sum::<f32>() / n;
+        let variance = entries
+            .iter()
+            .map(|e| (e.score.raw() - mean).powi(2))
+            .sum::<f32>()
+            / n;
+        let std_dev = variance.sqrt();
+        for e in entries {
+            e.score = if std_dev > 0. {
+                n32((e.score.raw() - mean) / std_dev)
+            } else {
+                n32(0.)
+            };
+        }
+    }
+}
+
+/// Sum normalization: divides each score by the sum of the absolute values
+/// of all scores, so that non-negative scores add up to 1, and negative
+/// scores keep their order. Scores are left unchanged if they are all 0.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Sum;
+
+impl<I> Normalizer<I> for Sum {
+    fn normalize(&self, entries: &mut [EntryInfo<I>]) {
+        let sum: Score = entries.iter().map(|e| e.score.abs()).sum();
+        if sum != 0. {
+            for e in entries {
+                e.score /= sum;
+            }
+        }
+    }
+}
+
+/// Percentile normalization: replaces each score with the fraction of the
+/// other entries which have a strictly lower score, in the range `[0, 1]`.
+/// This only depends on the order of the scores, not on their magnitude.
+/// A single entry receives a score of 1.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Percentile;
+
+impl<I> Normalizer<I> for Percentile {
+    fn normalize(&self, entries: &mut [EntryInfo<I>]) {
+        if entries.is_empty() {
+            return;
+        }
+        if entries.len() == 1 {
+            entries[0].score = n32(1.);
+            return;
+        }
+        let mut sorted: Vec<Score> = entries.iter().map(|e| e.score).collect();
+        sorted.sort();
+        let others = (entries.len() - 1) as f32;
+        for e in entries {
+            let below = sorted.partition_point(|&s| s < e.score);
+            e.score = n32(below as f32 / others);
+        }
+    }
+}
+
+/// Quantile normalization: maps the score distribution of a system onto a
+/// reference distribution, given by its quantiles, such as those of a
+/// baseline system computed with [`reference_quantiles`].
+///
+/// Each entry's score is first turned into its percentile within the list,
+/// as with [`Percentile`], and then replaced with the reference score at
+/// that percentile, interpolating linearly between the given quantiles.
+/// The normalized lists thus follow the shape of the reference, whatever
+/// their own shape, which makes CombSUM fairer than with min-max or z-score
+/// normalization when some systems have heavy-tailed scores.
+///
+/// Like any normalizer, this maps each query's results on their own, or all
+/// of a system's results together, as chosen with [`normalize_system`]. The
+/// reference should be computed over the same scope: from one query of the
+/// baseline, or from all of its results.
+///
+/// [`reference_quantiles`]: ./fn.reference_quantiles.html
+/// [`Percentile`]: ./struct.Percentile.html
+/// [`normalize_system`]: ./fn.normalize_system.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantile {
+    /// the scores of the reference distribution at evenly spaced
+    /// percentiles from 0 to 1, in ascending order
+    pub reference: Vec<Score>,
+}
+
+impl<I> Normalizer<I> for Quantile {
+    fn normalize(&self, entries: &mut [EntryInfo<I>]) {
+        quantile_normalize(entries, &self.reference)
+    }
+}
+
+/// The score at a percentile `p` of ascending scores, interpolating
+/// linearly between the closest two.
+fn quantile_at(sorted: &[Score], p: f32) -> Score {
+    let pos = p * (sorted.len() - 1) as f32;
+    let lo = pos.floor() as usize;
+    let hi = (lo + 1).min(sorted.len() - 1);
+    let frac = pos - lo as f32;
+    n32(sorted[lo].raw() + (sorted[hi].raw() - sorted[lo].raw()) * frac)
+}
+
+/// Computes the quantiles of a baseline system's scores, as a reference
+/// for [`quantile_normalize`]: the scores at the percentiles `j / (n - 1)`
+/// for `j` from 0 to `n - 1`, where `n` is the number of quantiles, with
+/// linear interpolation between the baseline's scores. The lowest and
+/// highest quantiles are the lowest and highest baseline scores.
+///
+/// # Panics
+///
+/// Panics if there are fewer than 2 quantiles, or no baseline entries.
+///
+/// [`quantile_normalize`]: ./fn.quantile_normalize.html
+pub fn reference_quantiles<I>(baseline: &[EntryInfo<I>], num_quantiles: usize) -> Vec<Score> {
+    assert!(num_quantiles >= 2, "at least 2 quantiles are needed");
+    assert!(!baseline.is_empty(), "the baseline has no entries");
+    let mut sorted: Vec<Score> = baseline.iter().map(|e| e.score).collect();
+    sorted.sort_unstable();
+    (0..num_quantiles)
+        .map(|j| quantile_at(&sorted, j as f32 / (num_quantiles - 1) as f32))
+        .collect()
+}
+
+/// Maps the scores of a list onto a reference distribution, as described
+/// for [`Quantile`]. The order of the entries is kept, and entries with
+/// equal scores receive equal scores.
+///
+/// # Panics
+///
+/// Panics if the reference is empty.
+///
+/// [`Quantile`]: ./struct.Quantile.html
+pub fn quantile_normalize<I>(entries: &mut [EntryInfo<I>], reference: &[Score]) {
+    assert!(!reference.is_empty(), "the reference has no quantiles");
+    Percentile.normalize(entries);
+    for e in entries {
+        e.score = quantile_at(reference, e.score.raw());
+    }
+}
+
+/// The set of results over which a normalizer's parameters, such as the
+/// minimum and maximum scores, are computed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NormalizationScope {
+    /// Normalize each query's results on their own. This is the usual
+    /// choice, and the only one when scores are not comparable across
+    /// queries, as with BM25, whose scores depend on the query terms.
+    PerQuery,
+    /// Normalize all of a system's results together, applying the same
+    /// scaling to every query. This keeps the differences in score ranges
+    /// between queries, so that a query where the system is confident is
+    /// not treated the same as one where all its scores are low. It is
+    /// only meaningful when the system's scores are comparable across
+    /// queries, such as with calibrated probabilities or cosine
+    /// similarities.
+    Global,
+}
+
+/// Normalizes the results of one system, given as one list per query, with
+/// the given scope. The order of the lists and their entries is kept.
+pub fn normalize_system<I, N>(normalizer: &N, queries: &mut [Vec<EntryInfo<I>>], scope: NormalizationScope)
+where
+    N: ?Sized + Normalizer<I>,
+{
+    match scope {
+        NormalizationScope::PerQuery => {
+            for list in queries {
+                normalizer.normalize(list);
+            }
+        }
+        NormalizationScope::Global => {
+            let lens: Vec<usize> = queries.iter().map(Vec::len).collect();
+            let mut all: Vec<_> = queries.iter_mut().flat_map(|q| q.drain(..)).collect();
+            normalizer.normalize(&mut all);
+            let mut all = all.into_iter();
+            for (list, len) in queries.iter_mut().zip(lens) {
+                list.extend(all.by_ref().take(len));
+            }
+        }
+    }
+}
+
+/// Advice on normalizing the scores of a system, given by
+/// [`recommend_normalization`].
+///
+/// [`recommend_normalization`]: ./fn.recommend_normalization.html
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NormalizationAdvice {
+    /// The system has no results, so there is nothing to normalize.
+    Empty,
+    /// All scores are equal, so they carry no information about the order
+    /// of the results, and normalizing them is meaningless (min-max and
+    /// z-score normalization would divide by zero). Rank-based fusion is
+    /// better suited to this system.
+    ConstantScores,
+    /// All scores are already within `[0, 1]`, so min-max normalization
+    /// would do little, or nothing at all if the scores span the whole
+    /// range. Normalization is only needed if the other systems are
+    /// normalized differently.
+    UnitRange,
+    /// Some scores are outside `[0, 1]`, so the scores should be normalized
+    /// before fusing them with those of other systems.
+    Normalize,
+}
+
+/// Analyzes the range of the scores of each system, one list per system,
+/// and advises whether they should be normalized before fusion.
+pub fn recommend_normalization<LL, L, R>(lists: LL) -> Vec<NormalizationAdvice>
+where
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry,
+{
+    lists
+        .into_iter()
+        .map(|list| {
+            let mut scores = list.into_iter().map(|r| r.score());
+            let first = match scores.next() {
+                Some(first) => first,
+                None => return NormalizationAdvice::Empty,
+            };
+            let (min, max) = scores.fold((first, first), |(min, max), s| (min.min(s), max.max(s)));
+            if min == max {
+                NormalizationAdvice::ConstantScores
+            } else if min >= 0. && max <= 1. {
+                NormalizationAdvice::UnitRange
+            } else {
+                NormalizationAdvice::Normalize
+            }
+        })
+        .collect()
+}
+
+/// Error type for a negative distance, which cannot be turned into a
+/// similarity score by [`invert_score`].
+///
+/// [`invert_score`]: ./fn.invert_score.html
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct NegativeDistance(pub Score);
+
+impl fmt::Display for NegativeDistance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "negative distance {} (distances must not be negative)", self.0)
+    }
+}
+
+impl std::error::Error for NegativeDistance {}
+
+/// Turns a distance, where lower is better, into a similarity score, where
+/// higher is better, as expected by the fusion algorithms. The distance `d`
+/// becomes `1 / (1 + d)`, so that a distance of 0 becomes 1 and scores
+/// approach 0 as distances grow.
+///
+/// Fails on a negative distance, which would not map to a score between 0
+/// and 1, and which no metric distance can be.
+pub fn invert_score(distance: Score) -> Result<Score, NegativeDistance> {
+    if distance < 0. {
+        return Err(NegativeDistance(distance));
+    }
+    Ok(n32(1.) / (n32(1.) + distance))
+}
+
+/// Turns the distances of a list into similarity scores, as in
+/// [`invert_score`]. The order of the entries is kept, so a list sorted by
+/// ascending distance ends up sorted by descending score. Fails on the
+/// first negative distance, leaving the list partly converted.
+///
+/// [`invert_score`]: ./fn.invert_score.html
+pub fn invert_scores<I>(entries: &mut [EntryInfo<I>]) -> Result<(), NegativeDistance> {
+    for e in entries {
+        e.score = invert_score(e.score)?;
+    }
+    Ok(())
+}
+
+/// Calibrates the scores of a system's results into an estimate of the
+/// probability of relevance, by isotonic regression against relevance
+/// judgments.
+///
+/// The judgments map document IDs to a relevance level, where levels above
+/// zero are relevant. Documents without a judgment are considered
+/// non-relevant. The fitted mapping is the non-decreasing function of the
+/// original score which best matches the observed relevance (by least
+/// squares), obtained with the pool adjacent violators algorithm. Entries
+/// with equal scores always receive the same calibrated score.
+///
+/// This requires labeled data for the query, so it is meant for fitting on
+/// training queries, or for analysis. The output keeps the order of the
+/// input.
+pub fn calibrate_isotonic<I, S>(
+    entries: &[EntryInfo<I>],
+    qrels: &HashMap<I, i32, S>,
+) -> Vec<EntryInfo<I>>
+where
+    I: Eq + Hash + Clone,
+    S: std::hash::BuildHasher,
+{
+    // indices of the entries by ascending score
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by_key(|&i| entries[i].score);
+
+    // blocks of (sum of labels, number of entries, number of input indices)
+    let mut blocks: Vec<(f32, f32, usize)> = Vec::new();
+    let mut last_score = None;
+    for &i in &order {
+        let e = &entries[i];
+        let label = match qrels.get(&e.id) {
+            Some(&r) if r > 0 => 1.,
+            _ => 0.,
+        };
+        match blocks.last_mut() {
+            // tied scores are pooled from the start
+            Some(b) if last_score == Some(e.score) => {
+                b.0 += label;
+                b.1 += 1.;
+                b.2 += 1;
+            }
+            _ => blocks.push((label, 1., 1)),
+        }
+        last_score = Some(e.score);
+
+        // merge adjacent blocks violating monotonicity
+        while blocks.len() > 1 {
+            let (s2, n2, c2) = blocks[blocks.len() - 1];
+            let (s1, n1, c1) = blocks[blocks.len() - 2];
+            if s1 / n1 <= s2 / n2 {
+                break;
+            }
+            blocks.pop();
+            *blocks.last_mut().unwrap() = (s1 + s2, n1 + n2, c1 + c2);
+        }
+    }
+
+    let mut calibrated: Vec<Score> = vec![n32(0.); entries.len()];
+    let mut indices = order.iter();
+    for (sum, n, count) in blocks {
+        let value = n32(sum / n);
+        for &i in indices.by_ref().take(count) {
+            calibrated[i] = value;
+        }
+    }
+
+    entries
+        .iter()
+        .zip(calibrated)
+        .map(|(e, score)| EntryInfo {
+            id: e.id.clone(),
+            score,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_normalizers() {
+        let entries = vec![
+            EntryInfo { id: "a", score: score(4.) },
+            EntryInfo { id: "b", score: score(2.) },
+            EntryInfo { id: "c", score: score(2.) },
+            EntryInfo { id: "d", score: score(0.) },
+        ];
+        type BoxedNormalizer = Box<dyn Normalizer<&'static str>>;
+        let normalizers: Vec<(BoxedNormalizer, [f32; 4])> = vec![
+            (Box::new(MinMax), [1., 0.5, 0.5, 0.]),
+            (Box::new(ZScore), [std::f32::consts::SQRT_2, 0., 0., -std::f32::consts::SQRT_2]),
+            (Box::new(Sum), [0.5, 0.25, 0.25, 0.]),
+            (Box::new(Percentile), [1., 1. / 3., 1. / 3., 0.]),
+        ];
+        for (normalizer, expected) in normalizers {
+            let mut out = entries.clone();
+            normalizer.normalize(&mut out);
+            for (i, (e, x)) in out.iter().zip(&expected).enumerate() {
+                assert_eq!(e.id, entries[i].id);
+                assert_abs_diff_eq!(e.score.raw(), *x, epsilon = 1e-6);
+            }
+        }
+
+        // constant scores
+        let mut out = vec![EntryInfo { id: "a", score: score(3.) }; 2];
+        MinMax.normalize(&mut out);
+        assert_eq!(out[0].score, 1.);
+        ZScore.normalize(&mut out);
+        assert_eq!(out[1].score, 0.);
+
+        // negative scores keep their order
+        let mut out = vec![
+            EntryInfo { id: "a", score: score(-1.) },
+            EntryInfo { id: "b", score: score(-3.) },
+        ];
+        Sum.normalize(&mut out);
+        assert_eq!(out[0].score, -0.25);
+        assert_eq!(out[1].score, -0.75);
+
+        // empty lists
+        let mut out: Vec<EntryInfo<&str>> = vec![];
+        for normalizer in [&MinMax as &dyn Normalizer<&str>, &ZScore, &Sum, &Percentile].iter() {
+            normalizer.normalize(&mut out);
+        }
+    }
+
+    #[test]
+    fn test_quantile_normalize() {
+        // a heavy-tailed baseline, and a system with uniform scores
+        let baseline: Vec<_> = (0..50).map(|i| EntryInfo { id: i, score: score((i as f32 / 8.).exp()) }).collect();
+        let reference = reference_quantiles(&baseline, 101);
+        assert_eq!(reference[0], 1.);
+        assert_eq!(reference[100], baseline[49].score);
+
+        let mut uniform: Vec<_> = (0..11).rev().map(|i| EntryInfo { id: i, score: score(i as f32) }).collect();
+        Quantile { reference }.normalize(&mut uniform);
+        assert_eq!(uniform[0].id, 10);
+        for (x, y) in reference_quantiles(&uniform, 11).iter().zip(reference_quantiles(&baseline, 11)) {
+            assert_abs_diff_eq!(x.raw(), y.raw(), epsilon = 1e-3 * y.raw());
+        }
+
+        let mut ties = vec![EntryInfo { id: 0, score: score(2.) }; 3];
+        quantile_normalize(&mut ties, &[score(0.), score(1.)]);
+        assert!(ties.iter().all(|e| e.score == 0.));
+    }
+
+    #[test]
+    fn test_normalize_system() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let queries = vec![vec![e("a", 10.), e("b", 5.)], vec![e("c", 2.), e("d", 1.)]];
+        let scores = |queries: &[Vec<EntryInfo<&str>>]| -> Vec<Vec<f32>> {
+            queries.iter().map(|q| q.iter().map(|e| e.score.raw()).collect()).collect()
+        };
+
+        let mut per_query = queries.clone();
+        normalize_system(&MinMax, &mut per_query, NormalizationScope::PerQuery);
+        assert_eq!(scores(&per_query), vec![vec![1., 0.], vec![1., 0.]]);
+
+        let mut global = queries.clone();
+        normalize_system(&MinMax, &mut global, NormalizationScope::Global);
+        assert_eq!(scores(&global), vec![vec![1., 4. / 9.], vec![1. / 9., 0.]]);
+        assert_eq!(global[1][0].id, "c");
+    }
+
+    #[test]
+    fn test_recommend_normalization() {
+        let e = |s| EntryInfo { id: 0, score: score(s) };
+        let lists = vec![
+            vec![e(2.), e(2.), e(2.)],
+            vec![e(0.9), e(0.4), e(0.)],
+            vec![e(12.5), e(7.), e(0.5)],
+            vec![],
+            vec![e(0.5), e(-0.5)],
+        ];
+        assert_eq!(
+            recommend_normalization(&lists),
+            vec![
+                NormalizationAdvice::ConstantScores,
+                NormalizationAdvice::UnitRange,
+                NormalizationAdvice::Normalize,
+                NormalizationAdvice::Empty,
+                NormalizationAdvice::Normalize,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invert_scores() {
+        let mut entries = vec![
+            EntryInfo { id: "a", score: score(0.) },
+            EntryInfo { id: "b", score: score(1.) },
+            EntryInfo { id: "c", score: score(3.) },
+        ];
+        invert_scores(&mut entries).unwrap();
+        let scores: Vec<_> = entries.iter().map(|e| e.score.raw()).collect();
+        assert_eq!(scores, vec![1., 0.5, 0.25]);
+
+        assert_eq!(invert_score(n32(-0.5)), Err(NegativeDistance(n32(-0.5))));
+    }
+
+    #[test]
+    fn test_calibrate_isotonic() {
+        let entries: Vec<_> = (0..10)
+            .map(|i| EntryInfo {
+                id: i,
+                score: score(i as f32 * 10.),
+            })
+            .collect();
+        // relevance mostly increases with the score
+        let qrels: HashMap<_, _> = vec![(2, 1), (5, 1), (6, 0), (7, 1), (8, 1), (9, 2)]
+            .into_iter()
+            .collect();
+
+        let out = calibrate_isotonic(&entries, &qrels);
+        assert_eq!(out.len(), entries.len());
+        for (e, c) in entries.iter().zip(&out) {
+            assert_eq!(e.id, c.id);
+            assert!(c.score >= 0. && c.score <= 1.);
+        }
+        for w in out.windows(2) {
+            assert!(w[0].score <= w[1].score);
+        }
+        assert_eq!(out[0].score, 0.);
+        assert_eq!(out[9].score, 1.);
+        // 2 was pooled with its non-relevant neighbors
+        assert_eq!(out[2].score, out[4].score);
+    }
+
+    #[test]
+    fn test_calibrate_isotonic_ties() {
+        let entries = vec![
+            EntryInfo { id: "a", score: score(1.) },
+            EntryInfo { id: "b", score: score(1.) },
+            EntryInfo { id: "c", score: score(0.) },
+        ];
+        let qrels: HashMap<_, _> = vec![("a", 1)].into_iter().collect();
+        let out = calibrate_isotonic(&entries, &qrels);
+        assert_eq!(out[0].score, 0.5);
+        assert_eq!(out[1].score, 0.5);
+        assert_eq!(out[2].score, 0.);
+    }
+}
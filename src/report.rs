@@ -0,0 +1,252 @@
+//! Diagnostics about search result lists and their fusion.
+use crate::fuser::ScoreAccumulator;
+use crate::json;
+use crate::{EntryInfo, SearchEntry};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display};
+use std::hash::Hash;
+use std::io::{self, Write};
+
+/// Counts describing the documents retrieved by each system taking part in
+/// a fusion.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct FusionReport {
+    /// the number of unique documents across all systems
+    pub num_documents: usize,
+    /// the number of unique documents retrieved by each system
+    pub per_system: Vec<usize>,
+    /// the number of documents retrieved by exactly `k + 1` systems, at
+    /// each index `k`
+    pub overlap: Vec<usize>,
+}
+
+impl FusionReport {
+    /// Retrieves the number of documents retrieved by more than one system.
+    pub fn num_shared(&self) -> usize {
+        self.overlap.iter().skip(1).sum()
+    }
+}
+
+impl fmt::Display for FusionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} documents, per system {:?}, by number of systems {:?}",
+            self.num_documents, self.per_system, self.overlap
+        )
+    }
+}
+
+/// Builds a report of the documents retrieved by each system, one list per
+/// system. A document appearing more than once in the same list is counted
+/// once.
+pub fn fusion_report<LL, L, R>(lists: LL) -> FusionReport
+where
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry,
+    R::Id: Hash + Clone,
+{
+    // the systems retrieving each document, in increasing order
+    let mut systems: HashMap<R::Id, Vec<usize>> = HashMap::new();
+    let mut per_system = Vec::new();
+    for (system, list) in lists.into_iter().enumerate() {
+        let mut count = 0;
+        for r in list {
+            let v = systems.entry(r.id().clone()).or_default();
+            if v.last() != Some(&system) {
+                v.push(system);
+                count += 1;
+            }
+        }
+        per_system.push(count);
+    }
+
+    let mut overlap = vec![0; per_system.len()];
+    for v in systems.values() {
+        overlap[v.len() - 1] += 1;
+    }
+
+    FusionReport {
+        num_documents: systems.len(),
+        per_system,
+        overlap,
+    }
+}
+
+/// The queries covered by each system taking part in a per-query fusion.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CoverageReport {
+    /// the IDs of all queries, across all systems, in sorted order
+    pub queries: Vec<String>,
+    /// the IDs of the queries which each system has no results for, in
+    /// sorted order
+    pub missing: Vec<Vec<String>>,
+}
+
+impl CoverageReport {
+    /// Checks whether every system has results for every query.
+    pub fn is_complete(&self) -> bool {
+        self.missing.iter().all(Vec::is_empty)
+    }
+}
+
+/// Builds a report of the queries which each system has results for, one
+/// list of query IDs per system, such as the query ID of each entry of a
+/// run. A query which a system is missing is still fused from the other
+/// systems, but with less evidence than the other queries, which skews
+/// comparisons across queries.
+pub fn validate_query_coverage<LL, L, Q>(lists: LL) -> CoverageReport
+where
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = Q>,
+    Q: AsRef<str>,
+{
+    let per_system: Vec<BTreeSet<String>> = lists
+        .into_iter()
+        .map(|list| list.into_iter().map(|q| q.as_ref().to_string()).collect())
+        .collect();
+    let queries: BTreeSet<&String> = per_system.iter().flatten().collect();
+    let missing = per_system
+        .iter()
+        .map(|qids| {
+            queries
+                .iter()
+                .filter(|q| !qids.contains(**q))
+                .map(|q| q.to_string())
+                .collect()
+        })
+        .collect();
+    CoverageReport {
+        queries: queries.into_iter().cloned().collect(),
+        missing,
+    }
+}
+
+/// Writes the provenance of fused lists as a JSON object, mapping each
+/// query ID to the documents of its fused list, in order. Each document
+/// lists the score and rank given to it by every contributing system, along
+/// with the system's name in `sources` and the entry's run ID, when known:
+///
+/// ```text
+/// {"301": [{"docno": "d1", "score": 1.5, "contributions": [
+///     {"source": "a.txt", "runid": "a", "rank": 0, "score": 1.5}]}]}
+/// ```
+///
+/// The contributions are taken from each query's accumulator, whose system
+/// indices are expected to match the positions in `sources`. Systems without
+/// a name have a `null` source.
+pub fn write_provenance<'a, W, I, Q>(mut writer: W, sources: &[&str], queries: Q) -> io::Result<()>
+where
+    W: Write,
+    I: 'a + Eq + Clone + Hash + Display,
+    Q: IntoIterator<Item = (&'a str, &'a ScoreAccumulator<I>, &'a [EntryInfo<I>])>,
+{
+    let mut out = String::from("{");
+    for (i, (qid, acc, fused)) in queries.into_iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        json::write_string(&mut out, qid);
+        out.push_str(": [");
+        for (j, e) in fused.iter().enumerate() {
+            if j > 0 {
+                out.push_str(", ");
+            }
+            out.push_str("{\"docno\": ");
+            json::write_string(&mut out, &e.id.to_string());
+            out.push_str(", \"score\": ");
+            json::write_number(&mut out, e.score.raw());
+            out.push_str(", \"contributions\": [");
+            for (k, c) in acc.contributions(&e.id).iter().enumerate() {
+                if k > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str("{\"source\": ");
+                match sources.get(c.system) {
+                    Some(source) => json::write_string(&mut out, source),
+                    None => out.push_str("null"),
+                }
+                if let Some(runid) = acc.runid(c) {
+                    out.push_str(", \"runid\": ");
+                    json::write_string(&mut out, runid);
+                }
+                out.push_str(&format!(", \"rank\": {}, \"score\": ", c.rank));
+                json::write_number(&mut out, c.score.raw());
+                out.push('}');
+            }
+            out.push_str("]}");
+        }
+        out.push(']');
+    }
+    out.push_str("}\n");
+    writer.write_all(out.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{score, EntryInfo};
+
+    #[test]
+    fn test_fusion_report() {
+        let e = |id| EntryInfo { id, score: score(1.) };
+        let lists = vec![
+            vec![e("a"), e("b"), e("c")],
+            vec![e("b"), e("d"), e("b")],
+            vec![e("b"), e("a")],
+        ];
+
+        let report = fusion_report(&lists);
+        assert_eq!(
+            report,
+            FusionReport {
+                num_documents: 4,
+                per_system: vec![3, 2, 2],
+                overlap: vec![2, 1, 1],
+            }
+        );
+        assert_eq!(report.num_shared(), 2);
+        assert_eq!(
+            report.to_string(),
+            "4 documents, per system [3, 2, 2], by number of systems [2, 1, 1]"
+        );
+    }
+
+    #[test]
+    fn test_validate_query_coverage() {
+        let systems = vec![
+            vec!["301", "301", "302", "303"],
+            vec!["303", "301", "302"],
+            vec!["301", "303"],
+        ];
+        let report = validate_query_coverage(&systems);
+        assert_eq!(report.queries, vec!["301", "302", "303"]);
+        assert_eq!(report.missing, vec![vec![], vec![], vec!["302".to_string()]]);
+        assert!(!report.is_complete());
+
+        assert!(validate_query_coverage(&systems[..2]).is_complete());
+        assert_eq!(validate_query_coverage(Vec::<Vec<&str>>::new()), CoverageReport::default());
+    }
+
+    #[test]
+    fn test_write_provenance() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let mut acc = ScoreAccumulator::new();
+        acc.add_list(vec![e("a", 2.), e("b", 1.)]);
+        acc.add_list(vec![e("b", 0.5)]);
+        let fused = [e("a", 2.), e("b", 1.5)];
+
+        let mut out = Vec::new();
+        write_provenance(&mut out, &["x"], vec![("q1", &acc, &fused[..])]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"q1\": [\
+             {\"docno\": \"a\", \"score\": 2, \"contributions\": [\
+             {\"source\": \"x\", \"rank\": 0, \"score\": 2}]}, \
+             {\"docno\": \"b\", \"score\": 1.5, \"contributions\": [\
+             {\"source\": \"x\", \"rank\": 1, \"score\": 1}, \
+             {\"source\": null, \"rank\": 0, \"score\": 0.5}]}]}\n"
+        );
+    }
+}
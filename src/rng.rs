@@ -0,0 +1,85 @@
+//! Deterministic pseudo-random number generation, for reproducible
+//! randomized operations.
+
+/// A small, seedable pseudo-random number generator (SplitMix64).
+///
+/// This is not suitable for cryptographic purposes. Its purpose is to make
+/// randomized operations reproducible: the same seed always yields the same
+/// sequence of numbers, on every platform.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a new generator from the given seed.
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// Produces the next pseudo-random 64-bit integer.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Produces a pseudo-random integer in the range `0..n`.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `n` is zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0, "empty range");
+        // multiply-shift range reduction
+        ((u128::from(self.next_u64()) * n as u128) >> 64) as usize
+    }
+
+    /// Produces a pseudo-random number in the range `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Shuffles a slice in place (Fisher-Yates).
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng_reproducible() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+        let xs: Vec<_> = (0..8).map(|_| a.next_u64()).collect();
+        let ys: Vec<_> = (0..8).map(|_| b.next_u64()).collect();
+        let zs: Vec<_> = (0..8).map(|_| c.next_u64()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(xs, zs);
+
+        for _ in 0..100 {
+            assert!(a.below(3) < 3);
+            let x = a.next_f32();
+            assert!((0. ..1.).contains(&x));
+        }
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let mut items: Vec<_> = (0..20).collect();
+        Rng::new(7).shuffle(&mut items);
+        assert_ne!(items, (0..20).collect::<Vec<_>>());
+        let mut sorted = items.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+    }
+}
@@ -1,7 +1,10 @@
 //! TREC File parsing and printing module
-use crate::{Rank, RankedSearchEntry, Score, SearchEntry};
+use crate::{parsed_score, rank_score, EntryInfo, Rank, RankedSearchEntry, RunSearchEntry, Score, SearchEntry};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::BuildHasher;
 use std::fmt;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct TrecEntry<'a> {
@@ -46,6 +49,12 @@ impl<'a> RankedSearchEntry for TrecEntry<'a> {
     }
 }
 
+impl<'a> RunSearchEntry for TrecEntry<'a> {
+    fn runid(&self) -> &str {
+        self.runid
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct TrecEntryOwned {
     pub qid: String,
@@ -55,6 +64,19 @@ pub struct TrecEntryOwned {
     pub runid: String,
 }
 
+impl TrecEntryOwned {
+    /// Borrows this entry as a `TrecEntry`.
+    pub fn as_entry(&self) -> TrecEntry<'_> {
+        TrecEntry {
+            qid: &self.qid,
+            docno: &self.docno,
+            rank: self.rank,
+            score: self.score,
+            runid: &self.runid,
+        }
+    }
+}
+
 impl SearchEntry for TrecEntryOwned {
     type Id = String;
     fn id(&self) -> &Self::Id {
@@ -72,7 +94,20 @@ impl RankedSearchEntry for TrecEntryOwned {
     }
 }
 
+impl RunSearchEntry for TrecEntryOwned {
+    fn runid(&self) -> &str {
+        &self.runid
+    }
+}
+
+/// Error type for parsing search results and related data.
+///
+/// New variants may be added in the future, so code outside this crate
+/// which needs to tell errors apart should match on [`kind`] instead.
+///
+/// [`kind`]: #method.kind
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ParseError {
     /// Unexpected end of line before reading a specific attribute
     Eol(&'static str),
@@ -80,10 +115,67 @@ pub enum ParseError {
     InvalidRank(String),
     /// Invalid score value (must be a non-NaN number)
     InvalidScore(String),
+    /// The line appears to lack the reserved column, with all remaining
+    /// fields shifted to the left
+    MissingReservedColumn(String),
+    /// A line is longer than the maximum line length, given with its line
+    /// number (starting at 1)
+    LineTooLong { line: usize, max: usize },
+    /// The data could not be read
+    Io(std::io::Error),
     /// Something else happened
     Other(String),
 }
 
+/// The category of a [`ParseError`], as returned by [`ParseError::kind`].
+/// Unlike `ParseError`, this enumeration is stable: any new kind of parse
+/// error falls into one of these categories.
+///
+/// [`ParseError`]: ./enum.ParseError.html
+/// [`ParseError::kind`]: ./enum.ParseError.html#method.kind
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ParseErrorKind {
+    /// A line ended before all expected fields were read
+    UnexpectedEol,
+    /// A rank is not a non-negative integer
+    InvalidRank,
+    /// A score is not a number
+    InvalidScore,
+    /// A line is missing a column, rather than only being cut short
+    MissingColumn,
+    /// Any other error
+    Other,
+}
+
+impl ParseError {
+    /// Retrieves the category of this error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use vindicator::trec::{parse_from_trec, ParseErrorKind};
+    ///
+    /// let err = parse_from_trec("301 0 d1 first 2.5 run").unwrap_err();
+    /// let message = match err.kind() {
+    ///     ParseErrorKind::UnexpectedEol => "truncated line",
+    ///     ParseErrorKind::InvalidRank => "bad rank",
+    ///     ParseErrorKind::InvalidScore => "bad score",
+    ///     ParseErrorKind::MissingColumn => "missing column",
+    ///     ParseErrorKind::Other => "other",
+    /// };
+    /// assert_eq!(message, "bad rank");
+    /// ```
+    pub fn kind(&self) -> ParseErrorKind {
+        match self {
+            ParseError::Eol(_) => ParseErrorKind::UnexpectedEol,
+            ParseError::InvalidRank(_) => ParseErrorKind::InvalidRank,
+            ParseError::InvalidScore(_) => ParseErrorKind::InvalidScore,
+            ParseError::MissingReservedColumn(_) => ParseErrorKind::MissingColumn,
+            ParseError::LineTooLong { .. } | ParseError::Io(_) | ParseError::Other(_) => ParseErrorKind::Other,
+        }
+    }
+}
+
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use ParseError::*;
@@ -91,18 +183,45 @@ impl fmt::Display for ParseError {
             Eol(att) => write!(f, "failed to parse TREC data: unexpected end of line ({})", att),
             InvalidRank(rank) => write!(f, "failed to parse TREC data: invalid rank `{}`", rank),
             InvalidScore(score) => write!(f, "failed to parse TREC data: invalid score `{}`", score),
+            MissingReservedColumn(line) => write!(
+                f,
+                "failed to parse TREC data: possible missing reserved column in line `{}`",
+                line
+            ),
+            LineTooLong { line, max } => write!(
+                f,
+                "failed to parse TREC data: line {} is longer than {} bytes",
+                line, max
+            ),
+            Io(e) => write!(f, "failed to read TREC data: {}", e),
             Other(s) => write!(f, "failed to parse TREC data: {}", s),
         }
     }
 }
 
+impl From<std::io::Error> for ParseError {
+    fn from(e: std::io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
+
 impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+
     fn description(&self) -> &str {
         use ParseError::*;
         match *self {
             Eol(_) => "unexpected end of line",
             InvalidRank(_) => "invalid rank",
             InvalidScore(_) => "invalid score",
+            MissingReservedColumn(_) => "possible missing reserved column",
+            LineTooLong { .. } => "line too long",
+            Io(_) => "failed to read data",
             Other(ref s) => s,
         }
     }
@@ -112,47 +231,510 @@ impl std::error::Error for ParseError {
 ///
 /// `qid 0 docno rank score run_id`
 pub fn parse_from_trec<'a>(file_data: &'a str) -> Result<Vec<TrecEntry<'a>>, ParseError> {
+    file_data.lines().map(parse_trec_line).collect()
+}
+
+/// The maximum length in bytes of a line read by [`parse_from_trec_reader`],
+/// not counting the line break. Lines of TREC runs are usually well under
+/// a hundred bytes, so a longer line is a sign of a malformed file, such as
+/// a binary file or one without line breaks.
+///
+/// [`parse_from_trec_reader`]: ./fn.parse_from_trec_reader.html
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Parses TREC result entries from a reader, one line at a time, so that
+/// the whole text does not need to be in memory.
+///
+/// Lines longer than [`DEFAULT_MAX_LINE_LENGTH`] are rejected with
+/// `ParseError::LineTooLong` as soon as the limit is reached, so a
+/// malformed file cannot exhaust memory with a single line. See
+/// [`parse_from_trec_reader_with_limit`] for another limit.
+///
+/// Expected format:
+///
+/// `qid 0 docno rank score run_id`
+///
+/// [`DEFAULT_MAX_LINE_LENGTH`]: ./constant.DEFAULT_MAX_LINE_LENGTH.html
+/// [`parse_from_trec_reader_with_limit`]: ./fn.parse_from_trec_reader_with_limit.html
+pub fn parse_from_trec_reader<R: std::io::BufRead>(reader: R) -> Result<Vec<TrecEntryOwned>, ParseError> {
+    parse_from_trec_reader_with_limit(reader, DEFAULT_MAX_LINE_LENGTH)
+}
+
+/// Parses TREC result entries from a reader, like
+/// [`parse_from_trec_reader`], with the given maximum line length in bytes.
+///
+/// [`parse_from_trec_reader`]: ./fn.parse_from_trec_reader.html
+pub fn parse_from_trec_reader_with_limit<R: std::io::BufRead>(
+    mut reader: R,
+    max_line_length: usize,
+) -> Result<Vec<TrecEntryOwned>, ParseError> {
+    use std::io::{BufRead, Read};
+
+    let mut entries = Vec::new();
+    let mut buf = Vec::new();
+    for line in 1.. {
+        buf.clear();
+        // two more bytes than the limit, for a `\r\n` line break
+        let limit = max_line_length as u64 + 2;
+        if reader.by_ref().take(limit).read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let mut l = &buf[..];
+        if let Some(rest) = l.strip_suffix(b"\n") {
+            l = rest.strip_suffix(b"\r").unwrap_or(rest);
+        }
+        if l.len() > max_line_length {
+            return Err(ParseError::LineTooLong { line, max: max_line_length });
+        }
+        let l = std::str::from_utf8(l).map_err(|_| ParseError::Other(format!("line {} is not valid UTF-8", line)))?;
+        entries.push(parse_trec_line(l)?.to_owned());
+    }
+    Ok(entries)
+}
+
+/// Parses TREC result entries, skipping malformed lines instead of failing.
+/// Returns the entries of all valid lines, and the line number (starting at
+/// 1) and error of each malformed line.
+///
+/// This salvages large, noisy files, but a skipped line may have been a
+/// document which the system retrieved, so the rest of the list is not
+/// necessarily faithful to the run. Callers should report the errors, or at
+/// least their count, rather than ignoring them.
+///
+/// Expected format:
+///
+/// `qid 0 docno rank score run_id`
+pub fn parse_from_trec_lenient<'a>(file_data: &'a str) -> (Vec<TrecEntry<'a>>, Vec<(usize, ParseError)>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+    for (i, l) in file_data.lines().enumerate() {
+        match parse_trec_line(l) {
+            Ok(e) => entries.push(e),
+            Err(e) => errors.push((i + 1, e)),
+        }
+    }
+    (entries, errors)
+}
+
+fn parse_trec_line<'a>(l: &'a str) -> Result<TrecEntry<'a>, ParseError> {
+    parse_trec_fields(l).map_err(|e| {
+        if is_column_shifted(l) {
+            ParseError::MissingReservedColumn(l.to_string())
+        } else {
+            e
+        }
+    })
+}
+
+fn parse_trec_fields<'a>(l: &'a str) -> Result<TrecEntry<'a>, ParseError> {
+    let mut words = l.split_whitespace();
+    let qid = words
+        .next()
+        .ok_or(ParseError::Eol("qid"))?;
+    let _reserved = words
+        .next()
+        .ok_or(ParseError::Eol("reserved"))?;
+    let docno = words
+        .next()
+        .ok_or(ParseError::Eol("docno"))?;
+    let rank = words
+        .next()
+        .ok_or(ParseError::Eol("rank"))?;
+    let rank: u32 = rank
+        .parse()
+        .map_err(|_| ParseError::InvalidRank(rank.to_string()))?;
+    let score = words
+        .next()
+        .ok_or(ParseError::Eol("score"))?;
+    let score: Score = score
+        .parse()
+        .map_err(|_| ())
+        .and_then(|s| parsed_score(s).ok_or(()))
+        .map_err(|_| ParseError::InvalidScore(score.to_string()))?;
+    let runid = words
+        .next()
+        .ok_or(ParseError::Eol("runid"))?;
+    Ok(TrecEntry {
+        qid,
+        docno,
+        rank,
+        score,
+        runid,
+    })
+}
+
+/// Checks whether a line that failed to parse would be valid if it had a
+/// reserved column: exactly five fields, in the form
+/// `qid docno rank score run_id`.
+fn is_column_shifted(l: &str) -> bool {
+    let words: Vec<&str> = l.split_whitespace().collect();
+    words.len() == 5
+        && words[2].parse::<u32>().is_ok()
+        && words[3].parse::<f32>().map(|s| !s.is_nan()).unwrap_or(false)
+}
+
+/// A TREC result entry with multiple score columns, such as separate
+/// lexical and neural scores of the same document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrecMultiScoreEntry<'a> {
+    /// Query ID
+    pub qid: &'a str,
+    /// Document number (unique identifier for a document)
+    pub docno: &'a str,
+    /// Rank (position of the document in the list)
+    pub rank: Rank,
+    /// Similarity scores, one per score column (higher is more similar)
+    pub scores: Vec<Score>,
+    /// Unique run ID
+    pub runid: &'a str,
+}
+
+/// Parses TREC result entries with one or more score columns, all of them
+/// between the rank and the run ID. Every line is expected to have the same
+/// number of score columns, in the same order, but this is not checked.
+///
+/// Expected format:
+///
+/// `qid 0 docno rank score_1 score_2 ... score_n run_id`
+pub fn parse_from_trec_multiscore<'a>(file_data: &'a str) -> Result<Vec<TrecMultiScoreEntry<'a>>, ParseError> {
+    const FIELDS: [&str; 6] = ["qid", "reserved", "docno", "rank", "score", "runid"];
     file_data
         .lines()
         .map(|l| {
-            let mut words = l.split_whitespace();
-            let qid = words
-                .next()
-                .ok_or_else(|| ParseError::Eol("qid"))?;
-            let _0 = words
-                .next()
-                .ok_or_else(|| ParseError::Eol("reserved"))?;
-            let docno = words
-                .next()
-                .ok_or_else(|| ParseError::Eol("docno"))?;
-            let rank = words
-                .next()
-                .ok_or_else(|| ParseError::Eol("rank"))?;
+            let words: Vec<&str> = l.split_whitespace().collect();
+            if words.len() < FIELDS.len() {
+                return Err(ParseError::Eol(FIELDS[words.len()]));
+            }
+            let rank = words[3];
             let rank: u32 = rank
                 .parse()
                 .map_err(|_| ParseError::InvalidRank(rank.to_string()))?;
-            let score = words
-                .next()
-                .ok_or_else(|| ParseError::Eol("score"))?;
-            let score: Score = score
+            let scores = words[4..words.len() - 1]
+                .iter()
+                .map(|score| {
+                    score
+                        .parse()
+                        .ok()
+                        .and_then(parsed_score)
+                        .ok_or_else(|| ParseError::InvalidScore(score.to_string()))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(TrecMultiScoreEntry {
+                qid: words[0],
+                docno: words[2],
+                rank,
+                scores,
+                runid: words[words.len() - 1],
+            })
+        })
+        .collect()
+}
+
+/// Parses TREC result entries of rank-only runs, which have no score
+/// column. Each entry is given the pseudo-score `1 / (rank + 1)` of its
+/// rank, as with [`rank_score`], so that the entries can take part in
+/// score-based fusion as well; rank-based fusion, such as RRF, only sees
+/// the ranks.
+///
+/// Expected format:
+///
+/// `qid 0 docno rank run_id`
+///
+/// [`rank_score`]: ../fn.rank_score.html
+pub fn parse_from_trec_rankonly<'a>(file_data: &'a str) -> Result<Vec<TrecEntry<'a>>, ParseError> {
+    const FIELDS: [&str; 5] = ["qid", "reserved", "docno", "rank", "runid"];
+    file_data
+        .lines()
+        .map(|l| {
+            let words: Vec<&str> = l.split_whitespace().collect();
+            if words.len() < FIELDS.len() {
+                return Err(ParseError::Eol(FIELDS[words.len()]));
+            }
+            if words.len() > FIELDS.len() {
+                return Err(ParseError::Other(format!(
+                    "expected {} columns in a rank-only run, found {}",
+                    FIELDS.len(),
+                    words.len()
+                )));
+            }
+            let rank = words[3];
+            let rank: u32 = rank
                 .parse()
-                .map_err(|_| ())
-                .and_then(|s| Score::try_new(s).ok_or(()))
-                .map_err(|_| ParseError::InvalidScore(score.to_string()))?;
-            let runid = words
-                .next()
-                .ok_or_else(|| ParseError::Eol("runid"))?;
+                .map_err(|_| ParseError::InvalidRank(rank.to_string()))?;
             Ok(TrecEntry {
+                qid: words[0],
+                docno: words[2],
+                rank,
+                score: rank_score(rank),
+                runid: words[4],
+            })
+        })
+        .collect()
+}
+
+/// A relevance judgment of a document for a query.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct QrelEntry<'a> {
+    /// Query ID
+    pub qid: &'a str,
+    /// Document number (unique identifier for a document)
+    pub docno: &'a str,
+    /// Relevance level (documents with a level above zero are relevant)
+    pub relevance: i32,
+}
+
+/// Parses relevance judgments in the TREC qrels format.
+///
+/// Expected format:
+///
+/// `qid 0 docno relevance`
+pub fn parse_qrels<'a>(file_data: &'a str) -> Result<Vec<QrelEntry<'a>>, ParseError> {
+    file_data
+        .lines()
+        .map(|l| {
+            let mut words = l.split_whitespace();
+            let qid = words.next().ok_or(ParseError::Eol("qid"))?;
+            let _reserved = words.next().ok_or(ParseError::Eol("reserved"))?;
+            let docno = words.next().ok_or(ParseError::Eol("docno"))?;
+            let relevance = words.next().ok_or(ParseError::Eol("relevance"))?;
+            let relevance = relevance
+                .parse()
+                .map_err(|_| ParseError::Other(format!("invalid relevance `{}`", relevance)))?;
+            Ok(QrelEntry {
                 qid,
                 docno,
-                rank,
-                score,
-                runid,
+                relevance,
             })
         })
         .collect()
 }
 
+/// Parses per-query system weights, mapping each query ID to the weight of
+/// each run ID for that query.
+///
+/// Expected format:
+///
+/// `qid runid weight`
+pub fn parse_query_weights(file_data: &str) -> Result<HashMap<String, HashMap<String, f32>>, ParseError> {
+    let mut weights: HashMap<String, HashMap<String, f32>> = HashMap::new();
+    for l in file_data.lines() {
+        let mut words = l.split_whitespace();
+        let qid = words.next().ok_or(ParseError::Eol("qid"))?;
+        let runid = words.next().ok_or(ParseError::Eol("runid"))?;
+        let weight = words.next().ok_or(ParseError::Eol("weight"))?;
+        let weight: f32 = weight
+            .parse()
+            .ok()
+            .filter(|w: &f32| !w.is_nan())
+            .ok_or_else(|| ParseError::Other(format!("invalid weight `{}`", weight)))?;
+        weights
+            .entry(qid.to_string())
+            .or_default()
+            .insert(runid.to_string(), weight);
+    }
+    Ok(weights)
+}
+
+/// The weight of a run for a query in per-query system weights, or 1 if the
+/// run has no weight for the query.
+fn query_weight<S1, S2>(weights: &HashMap<String, HashMap<String, f32, S2>, S1>, qid: &str, runid: &str) -> f32
+where
+    S1: BuildHasher,
+    S2: BuildHasher,
+{
+    weights.get(qid).and_then(|w| w.get(runid)).cloned().unwrap_or(1.)
+}
+
+/// Multiplies the score of each entry by the weight of its run for its
+/// query, as produced by [`parse_query_weights`]. Runs without a weight for
+/// a query keep their scores.
+///
+/// [`parse_query_weights`]: ./fn.parse_query_weights.html
+pub fn apply_query_weights<'a, I, S1, S2>(entries: I, weights: &HashMap<String, HashMap<String, f32, S2>, S1>)
+where
+    I: IntoIterator<Item = &'a mut TrecEntryOwned>,
+    S1: BuildHasher,
+    S2: BuildHasher,
+{
+    for e in entries {
+        e.score *= query_weight(weights, &e.qid, &e.runid);
+    }
+}
+
+/// Removes repeated documents of the same query from a list, keeping the
+/// entry with the highest score for each query and document, at the
+/// position of the first one. Lists in which several queries were renamed
+/// to one, such as sub-topics under their parent topic, can retrieve a
+/// document more than once per query, which would otherwise count as
+/// separate votes in the fusion.
+pub fn dedup_documents(entries: &mut Vec<TrecEntryOwned>) {
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+    let mut kept: Vec<TrecEntryOwned> = Vec::with_capacity(entries.len());
+    for e in entries.drain(..) {
+        let key = (e.qid.clone(), e.docno.clone());
+        match index.get(&key) {
+            Some(&i) => {
+                if e.score > kept[i].score {
+                    kept[i] = e;
+                }
+            }
+            None => {
+                index.insert(key, kept.len());
+                kept.push(e);
+            }
+        }
+    }
+    *entries = kept;
+}
+
+/// Combines the scored results of multiple systems, one list per system,
+/// separately for each query, after multiplying each entry's score by the
+/// weight of its run for its query, as with [`apply_query_weights`]. Each
+/// query is fused with [`fuse_weighted`], keeping one list per system.
+///
+/// The output contains each query ID and its fused list, sorted by query
+/// ID.
+///
+/// [`apply_query_weights`]: ./fn.apply_query_weights.html
+/// [`fuse_weighted`]: ../fuser/fn.fuse_weighted.html
+pub fn fuse_by_query_weighted<'a, LL, L, F, S1, S2>(
+    lists: LL,
+    weights: &HashMap<String, HashMap<String, f32, S2>, S1>,
+    fuser: F,
+) -> Vec<(String, Vec<EntryInfo<String>>)>
+where
+    LL: IntoIterator<Item = L>,
+    L: IntoIterator<Item = &'a TrecEntryOwned>,
+    F: Fn(&[Score]) -> Score,
+    S1: BuildHasher,
+    S2: BuildHasher,
+{
+    let mut queries: BTreeMap<&str, Vec<Vec<EntryInfo<String>>>> = BTreeMap::new();
+    for (system, list) in lists.into_iter().enumerate() {
+        for e in list {
+            let systems = queries.entry(&e.qid).or_default();
+            if systems.len() <= system {
+                systems.resize_with(system + 1, Vec::new);
+            }
+            systems[system].push(EntryInfo {
+                id: e.docno.clone(),
+                score: e.score * query_weight(weights, &e.qid, &e.runid),
+            });
+        }
+    }
+    queries
+        .into_iter()
+        .map(|(qid, systems)| (qid.to_string(), crate::fuser::fuse_weighted(&systems, &[], &fuser)))
+        .collect()
+}
+
+/// A structural problem in a TREC run, found by [`lint_trec`].
+///
+/// [`lint_trec`]: ./fn.lint_trec.html
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LintWarning {
+    /// A document appears more than once in the results of a query, so it
+    /// would contribute more than once to its fused score.
+    DuplicateDocument { qid: String, docno: String },
+    /// The ranks of a query's results, in ascending order, do not form a
+    /// sequence starting at 0 or 1 without gaps or repetitions.
+    NonContiguousRanks { qid: String, expected: Rank, found: Rank },
+    /// A document has a higher score than a document ranked before it, so
+    /// score-based and rank-based fusion see different rankings.
+    ScoreRankMismatch { qid: String, docno: String },
+    /// The run has no results for a query of the topic set that it was
+    /// checked against.
+    MissingQuery { qid: String },
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use LintWarning::*;
+        match self {
+            DuplicateDocument { qid, docno } => {
+                write!(f, "query {}: document `{}` appears more than once", qid, docno)
+            }
+            NonContiguousRanks { qid, expected, found } => {
+                write!(f, "query {}: expected rank {}, found {}", qid, expected, found)
+            }
+            ScoreRankMismatch { qid, docno } => write!(
+                f,
+                "query {}: document `{}` scores higher than a document ranked before it",
+                qid, docno
+            ),
+            MissingQuery { qid } => write!(f, "query {}: no results", qid),
+        }
+    }
+}
+
+/// Checks a TREC run for structural problems which would silently affect
+/// fusion, returning a warning for each problem found.
+///
+/// The results of each query are checked in order of first appearance, for
+/// duplicate documents, for gaps or repetitions in the ranks, and for
+/// scores which do not decrease with the rank, of which only the first is
+/// reported. If a topic set is given, such as the query IDs of the relevance
+/// judgments, each topic without results is reported missing, in the order
+/// they are given.
+pub fn lint_trec(entries: &[TrecEntry], topics: Option<&[&str]>) -> Vec<LintWarning> {
+    let queries = group_by_query(entries);
+    let mut warnings = Vec::new();
+    for (qid, mut list) in queries.iter().cloned() {
+        lint_ranks(qid, &mut list, &mut warnings);
+        if let Some(w) = list.windows(2).find(|w| w[1].score > w[0].score) {
+            warnings.push(LintWarning::ScoreRankMismatch {
+                qid: qid.to_string(),
+                docno: w[1].docno.to_string(),
+            });
+        }
+    }
+
+    if let Some(topics) = topics {
+        let found: HashSet<&str> = queries.iter().map(|&(qid, _)| qid).collect();
+        for qid in topics.iter().filter(|qid| !found.contains(*qid)) {
+            warnings.push(LintWarning::MissingQuery { qid: qid.to_string() });
+        }
+    }
+    warnings
+}
+
+/// Groups the entries of a run by query, in order of first appearance.
+fn group_by_query<'e, 'a>(entries: &'e [TrecEntry<'a>]) -> Vec<(&'a str, Vec<&'e TrecEntry<'a>>)> {
+    let mut queries: Vec<(&str, Vec<&TrecEntry>)> = Vec::new();
+    let mut query_index: HashMap<&str, usize> = HashMap::new();
+    for e in entries {
+        let i = *query_index.entry(e.qid).or_insert_with(|| {
+            queries.push((e.qid, Vec::new()));
+            queries.len() - 1
+        });
+        queries[i].1.push(e);
+    }
+    queries
+}
+
+/// Checks the results of a query for duplicate documents and for gaps or
+/// repetitions in the ranks, leaving them sorted by rank.
+fn lint_ranks(qid: &str, list: &mut Vec<&TrecEntry>, warnings: &mut Vec<LintWarning>) {
+    let mut seen = HashSet::new();
+    for e in list.iter() {
+        if !seen.insert(e.docno) {
+            warnings.push(LintWarning::DuplicateDocument {
+                qid: qid.to_string(),
+                docno: e.docno.to_string(),
+            });
+        }
+    }
+
+    list.sort_by_key(|e| e.rank);
+    let first: Rank = if list[0].rank == 0 { 0 } else { 1 };
+    if let Some((expected, e)) = (first..).zip(list.iter()).find(|(r, e)| e.rank != *r) {
+        warnings.push(LintWarning::NonContiguousRanks {
+            qid: qid.to_string(),
+            expected,
+            found: e.rank,
+        });
+    }
+}
 
 /// Write a single text line of this TREC result entry.
 /// 
@@ -177,3 +759,678 @@ where
     }
     Ok(())
 }
+
+/// Write a list of TREC result entries, formatting each score with the given
+/// number of decimal places, or as with [`write_all`] if `None`.
+///
+/// A fixed precision makes run files comparable line by line, regardless of
+/// how each score would otherwise be formatted.
+///
+/// Format: `qid 0 docno rank score run_id` (separated by spaces)
+///
+/// [`write_all`]: ./fn.write_all.html
+pub fn write_all_with_precision<'a, I, W>(
+    mut writer: W,
+    list: I,
+    decimals: Option<usize>,
+) -> Result<(), std::io::Error>
+where
+    I: IntoIterator<Item = TrecEntry<'a>>,
+    W: Write,
+{
+    let decimals = match decimals {
+        Some(decimals) => decimals,
+        None => return write_all(writer, list),
+    };
+    for e in list {
+        writeln!(
+            writer,
+            "{} 0 {} {} {:.*} {}",
+            e.qid, e.docno, e.rank, decimals, e.score.raw(), e.runid
+        )?;
+    }
+    Ok(())
+}
+
+/// Write a list of search results of a single query, in order, as TREC
+/// result entries, ranking them on the fly. Ranks start at 0.
+///
+/// Format: `qid 0 docno rank score run_id` (separated by spaces)
+pub fn write_all_ranked<W, L, R>(mut writer: W, qid: &str, runid: &str, list: L) -> Result<(), std::io::Error>
+where
+    W: Write,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry,
+    R::Id: fmt::Display,
+{
+    for (rank, e) in list.into_iter().enumerate() {
+        writeln!(writer, "{} 0 {} {} {} {}", qid, e.id(), rank, e.score(), runid)?;
+    }
+    Ok(())
+}
+
+/// Checks that a field of an entry, such as its document ID, can be written
+/// in the TREC format and read back the same, which is not the case if it
+/// is empty or contains whitespace. Fails with an error of kind
+/// `InvalidData` naming the field otherwise.
+pub fn check_field(name: &str, value: &str) -> Result<(), std::io::Error> {
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        return Err(invalid_field(name, value, "TREC"));
+    }
+    Ok(())
+}
+
+/// The error for a field which cannot be written in the given format.
+pub(crate) fn invalid_field(name: &str, value: &str, format: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{} `{}` cannot be written to {}", name, value, format),
+    )
+}
+
+/// Write a list of search results of a single query, in order, in the
+/// strict form of the TREC run format accepted by tools such as
+/// `ir_measures` and `trec_eval`. Compared to [`write_all_ranked`]:
+///
+/// - the second column is the literal `Q0`;
+/// - ranks start at 1, as is the TREC convention, and are contiguous;
+/// - scores are always written as floating point numbers, such as `1.0`
+///   rather than `1`, in the shortest form which reads back as the same
+///   value;
+/// - fields are separated by a single space, and fail to be written with an
+///   error of kind `InvalidData` if the query ID, a document ID or the run
+///   ID is empty or contains whitespace, as they could not be read back.
+///
+/// Format: `qid Q0 docno rank score run_id` (separated by spaces)
+///
+/// [`write_all_ranked`]: ./fn.write_all_ranked.html
+pub fn write_all_ir_measures<W, L, R>(mut writer: W, qid: &str, runid: &str, list: L) -> Result<(), std::io::Error>
+where
+    W: Write,
+    L: IntoIterator<Item = R>,
+    R: SearchEntry,
+    R::Id: fmt::Display,
+{
+    check_field("query ID", qid)?;
+    check_field("run ID", runid)?;
+    for (i, e) in list.into_iter().enumerate() {
+        let docno = e.id().to_string();
+        check_field("document ID", &docno)?;
+        writeln!(writer, "{} Q0 {} {} {:?} {}", qid, docno, i + 1, e.score().raw(), runid)?;
+    }
+    Ok(())
+}
+
+/// Write a list of TREC result entries, replacing each entry's score with
+/// its rank, so that the original scores are not disclosed.
+///
+/// This is lossy: the ordering of the list is kept, but the scores cannot
+/// be recovered. Note that ranks grow as entries become less relevant, so
+/// tools which order entries by descending score should be told to use the
+/// rank column instead.
+///
+/// Format: `qid 0 docno rank rank run_id` (separated by spaces)
+pub fn write_all_scores_as_rank<'a, I, W>(mut writer: W, list: I) -> Result<(), std::io::Error>
+where
+    I: IntoIterator<Item = TrecEntry<'a>>,
+    W: Write,
+{
+    for e in list {
+        writeln!(writer, "{} 0 {} {} {} {}", e.qid, e.docno, e.rank, e.rank, e.runid)?;
+    }
+    Ok(())
+}
+
+/// Turns a query ID into a file name which is safe on all platforms, by
+/// replacing every character other than ASCII letters, digits, `-`, `_`
+/// and `.` with `_`. Names made only of dots, such as `..`, are replaced
+/// as well, and an empty ID becomes `_`. Names which Windows reserves for
+/// devices, such as `CON` or `com1.a` (in any case, and with any
+/// extension), are prefixed with `_`.
+///
+/// Distinct query IDs can still map to the same file name, or to names
+/// which only differ in case, which is the same file on case-insensitive
+/// file systems; [`split_by_query`] checks for both.
+///
+/// [`split_by_query`]: ./fn.split_by_query.html
+pub fn sanitize_file_name(qid: &str) -> String {
+    const RESERVED: &[&str] = &["CON", "PRN", "AUX", "NUL"];
+    const NUMBERED: &[&str] = &["COM", "LPT"];
+
+    let name: String = qid
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+    if name.chars().all(|c| c == '.') {
+        return "_".repeat(name.len().max(1));
+    }
+    let stem = name.split('.').next().unwrap_or("").to_ascii_uppercase();
+    let reserved = RESERVED.contains(&&*stem)
+        || (stem.len() == 4
+            && NUMBERED.contains(&&stem[..3])
+            && stem.as_bytes()[3].is_ascii_digit()
+            && stem.as_bytes()[3] != b'0');
+    if reserved {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// Writes the entries of each query to a separate TREC file in the given
+/// directory, named after the query ID (see [`sanitize_file_name`]) with a
+/// `.txt` extension, so that the directory can be given back as input to
+/// the fusion tools. Existing files are replaced. The entries of each query
+/// keep their order.
+///
+/// Returns the paths of the written files, in order of first appearance of
+/// their queries.
+///
+/// # Errors
+///
+/// Besides I/O errors, returns an error of kind `InvalidData` if two
+/// query IDs map to file names which are the same when ignoring case, as
+/// they would overwrite each other on case-insensitive file systems. No
+/// file is written in that case.
+///
+/// [`sanitize_file_name`]: ./fn.sanitize_file_name.html
+pub fn split_by_query<'a, I, P>(entries: I, dir: P) -> Result<Vec<PathBuf>, std::io::Error>
+where
+    I: IntoIterator<Item = TrecEntry<'a>>,
+    P: AsRef<Path>,
+{
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut queries: Vec<(&str, Vec<TrecEntry>)> = Vec::new();
+    for e in entries {
+        match index.get(e.qid) {
+            Some(&q) => queries[q].1.push(e),
+            None => {
+                index.insert(e.qid, queries.len());
+                queries.push((e.qid, vec![e]));
+            }
+        }
+    }
+
+    let mut names: HashMap<String, &str> = HashMap::new();
+    for (qid, _) in &queries {
+        let name = sanitize_file_name(qid) + ".txt";
+        if let Some(other) = names.insert(name.to_ascii_lowercase(), qid) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("queries `{}` and `{}` would both be written to {}", other, qid, name),
+            ));
+        }
+    }
+
+    let dir = dir.as_ref();
+    let mut paths = Vec::with_capacity(queries.len());
+    for (qid, list) in queries {
+        let path = dir.join(sanitize_file_name(qid) + ".txt");
+        write_all(std::io::BufWriter::new(std::fs::File::create(&path)?), list)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// An error concatenating partial runs with [`merge_partial_runs`].
+///
+/// [`merge_partial_runs`]: ./fn.merge_partial_runs.html
+#[derive(Debug)]
+pub enum MergeError {
+    /// a partial run could not be read or parsed
+    Parse { path: PathBuf, error: ParseError },
+    /// the same query appears in two partial runs
+    QueryOverlap { qid: String, first: PathBuf, second: PathBuf },
+    /// the results of a query in a partial run are not ranked one by one
+    /// from 0 or 1, or have a document more than once
+    InvalidRanks { path: PathBuf, warning: LintWarning },
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::Parse { path, error } => write!(f, "{}: {}", path.display(), error),
+            MergeError::QueryOverlap { qid, first, second } => write!(
+                f,
+                "query {} appears in both {} and {}",
+                qid,
+                first.display(),
+                second.display()
+            ),
+            MergeError::InvalidRanks { path, warning } => write!(f, "{}: {}", path.display(), warning),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MergeError::Parse { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Concatenates partial TREC runs, such as those fused by separate
+/// processes over shards of the queries, in the order given.
+///
+/// Each query must be in only one of the runs, and its results must be
+/// well-formed: with contiguous ranks from 0 or 1, and no document more
+/// than once, as checked by [`lint_trec`]. Scores are not checked.
+///
+/// [`lint_trec`]: ./fn.lint_trec.html
+pub fn merge_partial_runs<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<TrecEntryOwned>, MergeError> {
+    let mut owner: HashMap<String, usize> = HashMap::new();
+    let mut merged = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        let path = path.as_ref();
+        let parse_error = |error| MergeError::Parse {
+            path: path.to_path_buf(),
+            error,
+        };
+        let file = std::fs::File::open(path).map_err(|e| parse_error(e.into()))?;
+        let entries = parse_from_trec_reader(std::io::BufReader::new(file)).map_err(parse_error)?;
+
+        let borrowed: Vec<_> = entries.iter().map(TrecEntryOwned::as_entry).collect();
+        let mut warnings = Vec::new();
+        for (qid, mut list) in group_by_query(&borrowed) {
+            lint_ranks(qid, &mut list, &mut warnings);
+        }
+        if let Some(warning) = warnings.into_iter().next() {
+            return Err(MergeError::InvalidRanks {
+                path: path.to_path_buf(),
+                warning,
+            });
+        }
+
+        for e in &entries {
+            let first = *owner.entry(e.qid.clone()).or_insert(i);
+            if first != i {
+                return Err(MergeError::QueryOverlap {
+                    qid: e.qid.clone(),
+                    first: paths[first].as_ref().to_path_buf(),
+                    second: path.to_path_buf(),
+                });
+            }
+        }
+        merged.extend(entries);
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score;
+
+    #[test]
+    fn test_lint_trec() {
+        let data = include_str!("../tests/resources/broken.txt");
+        let entries = parse_from_trec(data).unwrap();
+        let s = str::to_string;
+        let structural = vec![
+            LintWarning::DuplicateDocument { qid: s("301"), docno: s("d1") },
+            LintWarning::NonContiguousRanks { qid: s("301"), expected: 3, found: 4 },
+            LintWarning::ScoreRankMismatch { qid: s("301"), docno: s("d3") },
+        ];
+        assert_eq!(lint_trec(&entries, None), structural);
+        let mut with_topics = structural;
+        with_topics.push(LintWarning::MissingQuery { qid: s("302") });
+        assert_eq!(lint_trec(&entries, Some(&["301", "302", "303"])), with_topics);
+
+        let clean = parse_from_trec("301 0 d1 1 2 r\n301 0 d2 2 1 r\n302 0 d1 1 1 r\n").unwrap();
+        assert_eq!(lint_trec(&clean, Some(&["302", "301"])), vec![]);
+
+        // sparse numeric query IDs are not gaps
+        let sparse = parse_from_trec("1 0 d1 0 1 r\n4000000000 0 d1 0 1 r\n").unwrap();
+        assert_eq!(lint_trec(&sparse, None), vec![]);
+    }
+
+    #[test]
+    fn test_dedup_documents() {
+        let data = "301 0 d1 0 2 r\n301 0 d2 1 1 r\n301 0 d1 0 3 r\n302 0 d1 0 1 r\n301 0 d2 1 0.5 r\n";
+        let mut entries: Vec<_> = parse_from_trec(data).unwrap().iter().map(TrecEntry::to_owned).collect();
+        dedup_documents(&mut entries);
+        let kept: Vec<_> = entries.iter().map(|e| (&*e.qid, &*e.docno, e.score.raw())).collect();
+        assert_eq!(kept, vec![("301", "d1", 3.), ("301", "d2", 1.), ("302", "d1", 1.)]);
+    }
+
+    #[test]
+    fn test_write_all_ir_measures() {
+        let e = |id, s| EntryInfo { id, score: score(s) };
+        let list = vec![e("d1", 3.), e("d2", 0.25), e("d3", -1e-7), e("d4", 2e20)];
+        let mut out = Vec::new();
+        write_all_ir_measures(&mut out, "301", "fused", &list).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        // the expectations of a strict TREC run reader
+        for (i, line) in out.lines().enumerate() {
+            let fields: Vec<_> = line.split(' ').collect();
+            assert_eq!(fields.len(), 6, "bad line `{}`", line);
+            assert!(fields.iter().all(|f| !f.is_empty() && !f.contains('\t')));
+            assert_eq!(fields[0], "301");
+            assert_eq!(fields[1], "Q0");
+            assert_eq!(fields[2], list[i].id);
+            assert_eq!(fields[3].parse::<usize>().unwrap(), i + 1);
+            assert!(fields[4].contains(['.', 'e']), "score `{}` is not a float", fields[4]);
+            assert_eq!(fields[4].parse::<f32>().unwrap(), list[i].score.raw());
+            assert_eq!(fields[5], "fused");
+        }
+        assert_eq!(out.lines().next(), Some("301 Q0 d1 1 3.0 fused"));
+
+        let invalid = vec![e("d 1", 1.)];
+        let err = write_all_ir_measures(Vec::new(), "301", "fused", &invalid).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(write_all_ir_measures(Vec::new(), "301", "", &list).is_err());
+    }
+
+    #[test]
+    fn test_parse_error_kind() {
+        let kind = |data| parse_from_trec(data).unwrap_err().kind();
+        assert_eq!(kind("301 0 d1 0 2.5"), ParseErrorKind::UnexpectedEol);
+        assert_eq!(kind("301 0 d1 -1 2.5 run"), ParseErrorKind::InvalidRank);
+        assert_eq!(kind("301 0 d1 0 high run"), ParseErrorKind::InvalidScore);
+        assert_eq!(kind("301 d1 0 2.5 run"), ParseErrorKind::MissingColumn);
+        assert_eq!(parse_qrels("301 0 d1 yes").unwrap_err().kind(), ParseErrorKind::Other);
+    }
+
+    #[test]
+    fn test_parse_tiny_scores() {
+        use crate::fuser::{comb_mnz, fuse_scored};
+
+        let data = "301 0 d1 0 1e-45 run\n301 0 d2 1 -0.0 run\n301 0 d3 2 1e-50 run\n301 0 d4 3 -1e-50 run\n";
+        let list = parse_from_trec(data).unwrap();
+        let scores: Vec<f32> = list.iter().map(|e| e.score.raw()).collect();
+        // the subnormal score is kept, and all forms of zero are the same
+        assert_eq!(scores[0], f32::from_bits(1));
+        assert!(scores[1..].iter().all(|s| s.to_bits() == 0), "unexpected scores {:?}", scores);
+
+        let mut out = Vec::new();
+        write_all(&mut out, parse_from_trec(data).unwrap()).unwrap();
+        let written = String::from_utf8(out).unwrap();
+        assert!(written.lines().skip(1).all(|l| l.split_whitespace().nth(4) == Some("0")), "{}", written);
+
+        // zero scores still count as retrieved for CombMNZ, and only the
+        // subnormal score ranks above them
+        let mut fused = fuse_scored(list.iter().chain(&list), comb_mnz);
+        assert_eq!(fused[0], EntryInfo { id: "d1", score: score(4. * f32::from_bits(1)) });
+        fused[1..].sort_by_key(|e| e.id);
+        assert_eq!(
+            fused[1..].iter().map(|e| (e.id, e.score.raw().to_bits())).collect::<Vec<_>>(),
+            vec![("d2", 0), ("d3", 0), ("d4", 0)]
+        );
+    }
+
+    #[test]
+    fn test_parse_from_trec_reader() {
+        let data = "301 0 d1 0 12.5 run\r\n301 0 d2 1 3.25 run\n";
+        let list = parse_from_trec_reader(data.as_bytes()).unwrap();
+        let expected: Vec<_> = parse_from_trec(data).unwrap().iter().map(TrecEntry::to_owned).collect();
+        assert_eq!(list, expected);
+        assert_eq!(parse_from_trec_reader_with_limit(data.as_bytes(), 19).unwrap(), expected);
+
+        match parse_from_trec_reader_with_limit(data.as_bytes(), 18) {
+            Err(ParseError::LineTooLong { line: 1, max: 18 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        // a gigabyte without line breaks is rejected after reading the limit
+        let endless = std::io::BufReader::new(std::io::Read::take(std::io::repeat(b'x'), 1 << 30));
+        match parse_from_trec_reader(endless) {
+            Err(e @ ParseError::LineTooLong { .. }) => assert_eq!(e.kind(), ParseErrorKind::Other),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_from_trec_lenient() {
+        let data = "301 0 d1 0 12.5 run\n301 0 d2 one 3.25 run\n301 0 d3 2 -1 run\n";
+        let (list, errors) = parse_from_trec_lenient(data);
+        assert_eq!(list, vec![
+            TrecEntry { qid: "301", docno: "d1", rank: 0, score: score(12.5), runid: "run" },
+            TrecEntry { qid: "301", docno: "d3", rank: 2, score: score(-1.), runid: "run" },
+        ]);
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            (2, ParseError::InvalidRank(rank)) => assert_eq!(rank, "one"),
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        let (list, errors) = parse_from_trec_lenient("301 0 d1 0 12.5 run\n");
+        assert_eq!(list.len(), 1);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_parse_missing_reserved_column() {
+        let data = "301 0 d1 0 12.5 run\n301 d2 1 3.25 run\n";
+        let err = parse_from_trec(data).unwrap_err();
+        match &err {
+            ParseError::MissingReservedColumn(line) => assert_eq!(line, "301 d2 1 3.25 run"),
+            e => panic!("unexpected error {:?}", e),
+        }
+        assert!(err.to_string().contains("possible missing reserved column"));
+
+        // other malformed lines keep their specific error
+        match parse_from_trec("301 0 d1 first 12.5 run") {
+            Err(ParseError::InvalidRank(rank)) => assert_eq!(rank, "first"),
+            r => panic!("unexpected result {:?}", r),
+        }
+        match parse_from_trec("301 0 d1 0 12.5") {
+            Err(ParseError::Eol("runid")) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_parse_query_weights() {
+        let weights = parse_query_weights("301 bm25 2\n301 dense 0.5\n302 bm25 0\n").unwrap();
+        assert_eq!(weights.len(), 2);
+        assert_eq!(weights["301"]["bm25"], 2.);
+        assert_eq!(weights["301"]["dense"], 0.5);
+        assert_eq!(weights["302"]["bm25"], 0.);
+
+        assert!(parse_query_weights("301 bm25 heavy").is_err());
+        assert!(parse_query_weights("301 bm25").is_err());
+    }
+
+    #[test]
+    fn test_parse_from_trec_multiscore() {
+        let data = "301 0 d1 0 12.5 0.75 run\n301 0 d2 1 3.25 0.5 run\n";
+        let list = parse_from_trec_multiscore(data).unwrap();
+        assert_eq!(
+            list,
+            vec![
+                TrecMultiScoreEntry {
+                    qid: "301",
+                    docno: "d1",
+                    rank: 0,
+                    scores: vec![score(12.5), score(0.75)],
+                    runid: "run",
+                },
+                TrecMultiScoreEntry {
+                    qid: "301",
+                    docno: "d2",
+                    rank: 1,
+                    scores: vec![score(3.25), score(0.5)],
+                    runid: "run",
+                },
+            ]
+        );
+
+        match parse_from_trec_multiscore("301 0 d1 0 run") {
+            Err(ParseError::Eol("runid")) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        match parse_from_trec_multiscore("301 0 d1 0 1 x run") {
+            Err(ParseError::InvalidScore(s)) => assert_eq!(s, "x"),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_parse_from_trec_rankonly() {
+        use crate::fuser::{fuse_ranked, rrf};
+
+        let data = "301 0 d2 0 bm25\n301 0 d1 1 bm25\n301 0 d3 2 bm25\n";
+        let list = parse_from_trec_rankonly(data).unwrap();
+        assert_eq!(list[1], TrecEntry { qid: "301", docno: "d1", rank: 1, score: score(0.5), runid: "bm25" });
+        assert_eq!(list.iter().map(|e| e.score.raw()).collect::<Vec<_>>(), vec![1., 0.5, 1. / 3.]);
+
+        let other = parse_from_trec("301 0 d1 0 12.5 dense\n301 0 d3 1 10 dense\n").unwrap();
+        let fused = fuse_ranked(list.iter().chain(&other), rrf);
+        assert_eq!(fused.iter().map(|e| e.id).collect::<Vec<_>>(), vec!["d1", "d2", "d3"]);
+
+        assert_eq!(parse_from_trec_rankonly("301 0 d1 0").unwrap_err().kind(), ParseErrorKind::UnexpectedEol);
+        assert_eq!(parse_from_trec_rankonly("301 0 d1 x bm25").unwrap_err().kind(), ParseErrorKind::InvalidRank);
+        assert_eq!(parse_from_trec_rankonly("301 0 d1 0 1 bm25").unwrap_err().kind(), ParseErrorKind::Other);
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("301"), "301");
+        assert_eq!(sanitize_file_name("q-1_a.b"), "q-1_a.b");
+        assert_eq!(sanitize_file_name("../etc/passwd"), ".._etc_passwd");
+        assert_eq!(sanitize_file_name("what is: rust?"), "what_is__rust_");
+        assert_eq!(sanitize_file_name(".."), "__");
+        assert_eq!(sanitize_file_name(""), "_");
+        assert_eq!(sanitize_file_name("CON"), "_CON");
+        assert_eq!(sanitize_file_name("nul.1"), "_nul.1");
+        assert_eq!(sanitize_file_name("Com7"), "_Com7");
+        assert_eq!(sanitize_file_name("com0"), "com0");
+        assert_eq!(sanitize_file_name("console"), "console");
+
+        // both queries would go to q_1.txt
+        let data = "q/1 0 d1 0 1 a\nq:1 0 d1 0 1 a\n";
+        let dir = std::env::temp_dir();
+        let err = split_by_query(parse_from_trec(data).unwrap(), &dir).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // the same file on case-insensitive file systems
+        let data = "Q1 0 d1 0 1 a\nq1 0 d1 0 1 a\n";
+        let err = split_by_query(parse_from_trec(data).unwrap(), &dir).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_parse_qrels() {
+        let qrels = parse_qrels("301 0 d1 1\n301 0 d2 0\n302 0 d1 2\n").unwrap();
+        assert_eq!(
+            qrels,
+            vec![
+                QrelEntry { qid: "301", docno: "d1", relevance: 1 },
+                QrelEntry { qid: "301", docno: "d2", relevance: 0 },
+                QrelEntry { qid: "302", docno: "d1", relevance: 2 },
+            ]
+        );
+
+        assert!(parse_qrels("301 0 d1 yes").is_err());
+    }
+
+    #[test]
+    fn test_write_all_with_precision() {
+        let data = "301 0 d1 0 0.5 run\n301 0 d2 1 0.1 run\n";
+        let write = |decimals| {
+            let mut out = Vec::new();
+            write_all_with_precision(&mut out, parse_from_trec(data).unwrap(), decimals).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(write(Some(6)), "301 0 d1 0 0.500000 run\n301 0 d2 1 0.100000 run\n");
+        assert_eq!(write(Some(0)), "301 0 d1 0 0 run\n301 0 d2 1 0 run\n");
+        assert_eq!(write(None), data);
+    }
+
+    #[test]
+    fn test_write_all_ranked() {
+        let list = vec![
+            EntryInfo { id: "d3", score: score(2.) },
+            EntryInfo { id: "d1", score: score(1.5) },
+            EntryInfo { id: "d2", score: score(0.25) },
+        ];
+        let mut out = Vec::new();
+        write_all_ranked(&mut out, "301", "run", &list).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "301 0 d3 0 2 run\n301 0 d1 1 1.5 run\n301 0 d2 2 0.25 run\n"
+        );
+    }
+
+    #[test]
+    fn test_write_all_scores_as_rank() {
+        let data = "301 0 d1 0 12.5 run\n301 0 d2 1 3.25 run\n301 0 d3 2 -1 run\n";
+        let list = parse_from_trec(data).unwrap();
+        let mut out = Vec::new();
+        write_all_scores_as_rank(&mut out, list).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let written = parse_from_trec(&out).unwrap();
+        assert_eq!(written.len(), 3);
+        for e in written {
+            assert_eq!(e.score, e.rank as f32);
+        }
+        assert_eq!(out, "301 0 d1 0 0 run\n301 0 d2 1 1 run\n301 0 d3 2 2 run\n");
+    }
+
+    #[test]
+    fn test_merge_partial_runs() {
+        let dir = std::env::temp_dir().join(format!("vindicator-test-shards-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let shard = |name: &str, data: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, data).unwrap();
+            path
+        };
+        let a = shard("a.txt", "301 0 d1 1 2 fused\n301 0 d2 2 1 fused\n303 0 d1 1 1 fused\n");
+        let b = shard("b.txt", "302 0 d3 1 4 fused\n");
+        let c = shard("c.txt", "304 0 d1 1 2 fused\n303 0 d2 1 3 fused\n");
+        let gap = shard("gap.txt", "305 0 d1 1 2 fused\n305 0 d2 3 1 fused\n");
+
+        let merged = merge_partial_runs(&[&a, &b]);
+        let overlap = merge_partial_runs(&[&a, &b, &c]);
+        let invalid = merge_partial_runs(&[&b, &gap]);
+        let missing = merge_partial_runs(&[dir.join("none.txt")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let qids: Vec<_> = merged.unwrap().into_iter().map(|e| (e.qid, e.docno)).collect();
+        let s = str::to_string;
+        assert_eq!(
+            qids,
+            vec![(s("301"), s("d1")), (s("301"), s("d2")), (s("303"), s("d1")), (s("302"), s("d3"))]
+        );
+        match overlap {
+            Err(MergeError::QueryOverlap { qid, first, second }) => {
+                assert_eq!((qid, first, second), (s("303"), a, c));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(matches!(
+            invalid,
+            Err(MergeError::InvalidRanks { warning: LintWarning::NonContiguousRanks { .. }, .. })
+        ));
+        assert!(matches!(missing, Err(MergeError::Parse { error: ParseError::Io(_), .. })));
+    }
+
+    #[test]
+    fn test_fuse_by_query_weighted() {
+        let parse = |data| {
+            parse_from_trec(data)
+                .unwrap()
+                .iter()
+                .map(TrecEntry::to_owned)
+                .collect::<Vec<_>>()
+        };
+        let run1 = parse("301 0 d1 0 1 bm25\n302 0 d1 0 1 bm25\n");
+        let run2 = parse("301 0 d2 0 0.75 dense\n302 0 d2 0 0.75 dense\n");
+        // query 302 prefers the dense run
+        let weights = parse_query_weights("302 dense 2\n302 bm25 0.5\n").unwrap();
+
+        let out = fuse_by_query_weighted(vec![&run1, &run2], &weights, crate::fuser::comb_sum);
+        let e = |id: &str, s| EntryInfo { id: id.to_string(), score: score(s) };
+        assert_eq!(
+            out,
+            vec![
+                ("301".to_string(), vec![e("d1", 1.), e("d2", 0.75)]),
+                ("302".to_string(), vec![e("d2", 1.5), e("d1", 0.5)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,630 @@
+use std::process::Command;
+
+/// Runs the command line tool with the given arguments, returning its
+/// standard output.
+fn run(args: &[&str]) -> String {
+    run_full(args).0
+}
+
+/// Runs the command line tool with the given arguments, returning its
+/// standard output and standard error.
+fn run_full(args: &[&str]) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_vindicator"))
+        .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/resources"))
+        .args(args)
+        .output()
+        .expect("could not run vindicator");
+    assert!(
+        output.status.success(),
+        "vindicator failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (
+        String::from_utf8(output.stdout).expect("output is not UTF-8"),
+        String::from_utf8(output.stderr).expect("output is not UTF-8"),
+    )
+}
+
+/// Runs the command line tool with the given arguments, expecting it to
+/// fail, and returns its standard error.
+fn run_err(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_vindicator"))
+        .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/resources"))
+        .args(args)
+        .output()
+        .expect("could not run vindicator");
+    assert!(!output.status.success(), "vindicator did not fail");
+    String::from_utf8(output.stderr).expect("output is not UTF-8")
+}
+
+#[test]
+fn test_merge_registered_fusers() {
+    // RRF fuses the ranks, where d2 is first in one list and second in the other
+    assert_eq!(
+        run(&["merge", "-f", "RRF", "mixed.a.txt", "mixed.b.csv"]),
+        "301 0 d2 0 1.5 vindicated\n\
+         301 0 d1 1 1 vindicated\n\
+         301 0 d3 2 0.5 vindicated\n"
+    );
+    // with fewer than three scores, combROBUST is the same as combSUM
+    assert_eq!(
+        run(&["merge", "-f", "combROBUST", "mixed.a.txt", "mixed.b.csv"]),
+        run(&["merge", "-f", "combSUM", "mixed.a.txt", "mixed.b.csv"])
+    );
+    assert!(run_err(&["merge", "-f", "combFOO", "mixed.a.txt"]).contains("expected one of combMAX"));
+}
+
+#[test]
+fn test_merge_mixed_formats() {
+    let out = run(&["merge", "-f", "combSUM", "mixed.a.txt", "mixed.b.csv"]);
+    assert_eq!(
+        out,
+        "301 0 d2 0 1.25 vindicated\n\
+         301 0 d1 1 1 vindicated\n\
+         301 0 d3 2 0.25 vindicated\n"
+    );
+
+    // combAVG does not reward d2 for being in both lists
+    let out = run(&["merge", "-f", "combAVG", "mixed.a.txt", "mixed.b.csv"]);
+    assert_eq!(
+        out,
+        "301 0 d1 0 1 vindicated\n\
+         301 0 d2 1 0.625 vindicated\n\
+         301 0 d3 2 0.25 vindicated\n"
+    );
+
+    let out = run(&[
+        "merge",
+        "-f",
+        "combSUM",
+        "mixed.a.txt",
+        "mixed.b.csv",
+        "mixed.c.jsonl",
+    ]);
+    assert_eq!(
+        out,
+        "301 0 d2 0 1.25 vindicated\n\
+         301 0 d1 1 1 vindicated\n\
+         301 0 d3 2 0.75 vindicated\n"
+    );
+}
+
+#[test]
+fn test_merge_explicit_input_format() {
+    let out = run(&["merge", "-f", "combMAX", "--input-format", "csv", "mixed.b.csv"]);
+    assert_eq!(
+        out,
+        "301 0 d2 0 0.75 vindicated\n\
+         301 0 d3 1 0.25 vindicated\n"
+    );
+}
+
+#[test]
+fn test_merge_seed_reproducible() {
+    let args = |seed| ["--seed", seed, "merge", "-f", "combSUM", "--tie-break", "random", "ties.txt"];
+    for seed in &["0", "1", "12345"] {
+        let out = run(&args(seed));
+        assert!(out.starts_with("301 0 d7 0 2 vindicated\n"));
+        for _ in 0..4 {
+            assert_eq!(out, run(&args(seed)));
+        }
+    }
+
+    // the seed has a default value
+    let args = ["merge", "-f", "combSUM", "--tie-break", "random", "ties.txt"];
+    let out = run(&args);
+    assert_eq!(out, run(&args));
+    assert_eq!(out, run(&["--seed", "0", "merge", "-f", "combSUM", "--tie-break", "random", "ties.txt"]));
+}
+
+#[test]
+fn test_merge_tie_break() {
+    let docno = run(&["merge", "-f", "combSUM", "ties.unsorted.txt"]);
+    assert_eq!(docno, run(&["merge", "-f", "combSUM", "--tie-break", "docno", "ties.unsorted.txt"]));
+    assert_eq!(
+        docno,
+        "301 0 d4 0 2 vindicated\n\
+         301 0 d1 1 1 vindicated\n\
+         301 0 d2 2 1 vindicated\n\
+         301 0 d3 3 1 vindicated\n"
+    );
+
+    let treceval = run(&["merge", "-f", "combSUM", "--tie-break", "treceval", "ties.unsorted.txt"]);
+    assert_eq!(
+        treceval,
+        "301 0 d4 0 2 vindicated\n\
+         301 0 d3 1 1 vindicated\n\
+         301 0 d2 2 1 vindicated\n\
+         301 0 d1 3 1 vindicated\n"
+    );
+
+    let input = run(&["merge", "-f", "combSUM", "--tie-break", "input", "ties.unsorted.txt"]);
+    assert_eq!(
+        input,
+        "301 0 d4 0 2 vindicated\n\
+         301 0 d3 1 1 vindicated\n\
+         301 0 d1 2 1 vindicated\n\
+         301 0 d2 3 1 vindicated\n"
+    );
+
+    // no secondary order, only the scores are guaranteed
+    let score = run(&["merge", "-f", "combSUM", "--tie-break", "score", "ties.unsorted.txt"]);
+    let mut lines: Vec<_> = score.lines().collect();
+    assert!(lines[0].starts_with("301 0 d4 0 2 "));
+    lines.sort_by_key(|l| l.split(' ').nth(2));
+    assert_eq!(lines.len(), 4);
+    for (line, docno) in lines.iter().zip(&["d1", "d2", "d3"]) {
+        assert_eq!(line.split(' ').nth(2), Some(*docno));
+        assert_eq!(line.split(' ').nth(4), Some("1"));
+    }
+
+    let random = run(&["merge", "-f", "combSUM", "--tie-break", "random", "ties.unsorted.txt"]);
+    assert!(random.starts_with("301 0 d4 0 2 vindicated\n"));
+}
+
+#[test]
+fn test_merge_directory() {
+    let dir = std::env::temp_dir().join(format!("vindicator-test-dir-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.txt"), "301 0 d1 0 1 a\n301 0 d2 1 0.5 a\n").unwrap();
+    std::fs::write(dir.join("b.run"), "301 0 d2 0 1 b\n").unwrap();
+    std::fs::write(dir.join("c.txt"), "301 0 d3 0 0.25 c\n301 0 d1 1 0.125 c\n").unwrap();
+    std::fs::write(dir.join("notes.md"), "not a run file").unwrap();
+
+    let dir_str = dir.to_str().unwrap();
+    let out = run(&["merge", "-f", "combSUM", dir_str]);
+    let out_glob = run(&["merge", "-f", "combSUM", "--glob", "*.txt", dir_str]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        out,
+        "301 0 d2 0 1.5 vindicated\n\
+         301 0 d1 1 1.125 vindicated\n\
+         301 0 d3 2 0.25 vindicated\n"
+    );
+    assert_eq!(
+        out_glob,
+        "301 0 d1 0 1.125 vindicated\n\
+         301 0 d2 1 0.5 vindicated\n\
+         301 0 d3 2 0.25 vindicated\n"
+    );
+}
+
+#[test]
+fn test_merge_stats() {
+    let (out, err) = run_full(&["merge", "-f", "combSUM", "--stats", "mixed.a.txt", "mixed.b.csv"]);
+    assert_eq!(out, run(&["merge", "-f", "combSUM", "mixed.a.txt", "mixed.b.csv"]));
+    assert_eq!(
+        err,
+        "301: 3 documents, per system [2, 2], by number of systems [2, 1]\n"
+    );
+}
+
+#[test]
+fn test_merge_scores_as_rank() {
+    let out = run(&["merge", "-f", "combSUM", "--scores-as-rank", "mixed.a.txt", "mixed.b.csv"]);
+    assert_eq!(
+        out,
+        "301 0 d2 0 0 vindicated\n\
+         301 0 d1 1 1 vindicated\n\
+         301 0 d3 2 2 vindicated\n"
+    );
+}
+
+#[test]
+fn test_merge_ir_measures_compat() {
+    let out = run(&["merge", "-f", "combSUM", "--ir-measures-compat", "mixed.a.txt", "mixed.b.csv"]);
+    assert_eq!(
+        out,
+        "301 Q0 d2 1 1.25 vindicated\n\
+         301 Q0 d1 2 1.0 vindicated\n\
+         301 Q0 d3 3 0.25 vindicated\n"
+    );
+    run_err(&["merge", "-f", "combSUM", "--ir-measures-compat", "--scores-as-rank", "mixed.a.txt"]);
+}
+
+#[test]
+fn test_merge_by_query() {
+    let dir = std::env::temp_dir().join(format!("vindicator-test-qid-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let a = dir.join("a.txt");
+    let b = dir.join("b.txt");
+    let c = dir.join("c.txt");
+    std::fs::write(&a, "302 0 d1 0 1 a\n301 0 d2 0 0.5 a\n").unwrap();
+    std::fs::write(&b, "301 0 d2 0 1 b\n301 0 d3 1 0.25 b\n").unwrap();
+    std::fs::write(&c, "303 0 d4 0 1 c\n").unwrap();
+    let (a, b, c) = (a.to_str().unwrap(), b.to_str().unwrap(), c.to_str().unwrap());
+
+    let out = run(&["merge", "-f", "combSUM", a, b]);
+    let out_single = run(&["merge", "-f", "combSUM", "-q", "all", a, b]);
+    let out_disjoint = run(&["merge", "-f", "combSUM", "--disjoint-queries", a, c]);
+    let err = run_err(&["merge", "-f", "combSUM", "--disjoint-queries", a, b]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        out,
+        "301 0 d2 0 1.5 vindicated\n\
+         301 0 d3 1 0.25 vindicated\n\
+         302 0 d1 0 1 vindicated\n"
+    );
+    assert_eq!(
+        out_single,
+        "all 0 d2 0 1.5 vindicated\n\
+         all 0 d1 1 1 vindicated\n\
+         all 0 d3 2 0.25 vindicated\n"
+    );
+    assert_eq!(
+        out_disjoint,
+        "301 0 d2 0 0.5 vindicated\n\
+         302 0 d1 0 1 vindicated\n\
+         303 0 d4 0 1 vindicated\n"
+    );
+    assert!(err.contains("query `301` appears in both"), "unexpected error: {}", err);
+}
+
+#[test]
+fn test_merge_query_weights() {
+    let dir = std::env::temp_dir().join(format!("vindicator-test-weights-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let a = dir.join("a.txt");
+    let b = dir.join("b.txt");
+    let weights = dir.join("weights.txt");
+    std::fs::write(&a, "301 0 d1 0 1 bm25\n302 0 d1 0 1 bm25\n").unwrap();
+    std::fs::write(&b, "301 0 d2 0 0.75 dense\n302 0 d2 0 0.75 dense\n").unwrap();
+    std::fs::write(&weights, "302 dense 2\n").unwrap();
+    let (a, b, weights) = (a.to_str().unwrap(), b.to_str().unwrap(), weights.to_str().unwrap());
+
+    let out = run(&["merge", "-f", "combSUM", "--query-weights", weights, a, b]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // only query 302 has its weights changed
+    assert_eq!(
+        out,
+        "301 0 d1 0 1 vindicated\n\
+         301 0 d2 1 0.75 vindicated\n\
+         302 0 d2 0 1.5 vindicated\n\
+         302 0 d1 1 1 vindicated\n"
+    );
+}
+
+#[test]
+fn test_merge_weight_from_runid() {
+    let dir = std::env::temp_dir().join(format!("vindicator-test-runid-weights-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let a = dir.join("a.txt");
+    let b = dir.join("b.txt");
+    std::fs::write(&a, "301 0 d1 0 1 bm25_w0.5\n301 0 d2 1 0.5 bm25_w0.5\n").unwrap();
+    std::fs::write(&b, "301 0 d2 0 1 dense_w2\n301 0 d3 1 0.5 dense_w2\n").unwrap();
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    let out = run(&["merge", "-f", "combSUM", "--weight-from-runid", "*_w{}", a, b]);
+    let err = run_err(&["merge", "-f", "combSUM", "--weight-from-runid", "*_w", a, b]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // without the weights, d1 would rank above d3
+    assert_eq!(
+        out,
+        "301 0 d2 0 2.25 vindicated\n\
+         301 0 d3 1 1 vindicated\n\
+         301 0 d1 2 0.5 vindicated\n"
+    );
+    assert!(err.contains("exactly once"), "unexpected error: {}", err);
+}
+
+#[test]
+fn test_merge_missing_query_warning() {
+    let dir = std::env::temp_dir().join(format!("vindicator-test-coverage-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, data) in &[
+        ("a.txt", "301 0 d1 0 1 a\n302 0 d1 0 1 a\n"),
+        ("b.txt", "301 0 d2 0 1 b\n"),
+        ("c.txt", "302 0 d2 0 1 c\n301 0 d1 0 1 c\n"),
+    ] {
+        std::fs::write(dir.join(name), data).unwrap();
+    }
+    let (out, err) = run_full(&["merge", "-f", "combSUM", dir.to_str().unwrap()]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(out.lines().count(), 4);
+    assert_eq!(err.lines().count(), 1, "unexpected warnings: {}", err);
+    assert!(err.starts_with("warning: "), "{}", err);
+    assert!(err.contains("b.txt has no results for 1 of 2 queries: 302"), "{}", err);
+}
+
+#[test]
+fn test_merge_progress() {
+    let args = ["merge", "-f", "combSUM", "mixed.a.txt", "mixed.b.csv"];
+    let (out, err) = run_full(&[&args[..], &["--progress"]].concat());
+    assert_eq!(out, run(&args));
+    assert_eq!(
+        err,
+        "[1/2] mixed.a.txt: 2 lines, 2 entries\n\
+         [2/2] mixed.b.csv: 3 lines, 2 entries\n\
+         fused 3 documents over 1 queries\n"
+    );
+
+    let (_, err) = run_full(&[&args[..], &["--progress", "--quiet"]].concat());
+    assert_eq!(err, "");
+}
+
+#[test]
+fn test_merge_qrels() {
+    let path = std::env::temp_dir().join(format!("vindicator-test-qrels-{}.txt", std::process::id()));
+    // d2 is the only relevant document, and ranks first after fusion
+    std::fs::write(&path, "301 0 d1 0\n301 0 d2 1\n302 0 d1 1\n").unwrap();
+    let qrels = path.to_str().unwrap();
+
+    let args = ["merge", "-f", "combSUM", "mixed.a.txt", "mixed.b.csv"];
+    let (out, err) = run_full(&[&args[..], &["--qrels", qrels]].concat());
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(out, run(&args));
+    assert!(out.starts_with("301 0 d2 0 "), "unexpected run: {}", out);
+    assert_eq!(err, "num_q\tall\t1\nmap\tall\t1.0000\nP_10\tall\t0.1000\nndcg_cut_10\tall\t1.0000\n");
+}
+
+#[test]
+fn test_merge_fuser_script() {
+    let files = ["mixed.a.txt", "mixed.b.csv", "mixed.c.jsonl"];
+    let mnz = run(&[&["merge", "-f", "combMNZ"][..], &files].concat());
+    let out = run(&[&["merge", "--fuser-script", "sum(scores) * len(scores)"][..], &files].concat());
+    assert_eq!(out, mnz);
+
+    let err = run_err(&["merge", "--fuser-script", "sum(scores) *", "mixed.a.txt"]);
+    assert!(err.contains("position 13"), "{}", err);
+    run_err(&["merge", "mixed.a.txt"]);
+    run_err(&["merge", "-f", "combSUM", "--fuser-script", "max(scores)", "mixed.a.txt"]);
+    let err = run_err(&["merge", "--fuser-script", "ln(sum(scores) - 10)", "mixed.a.txt"]);
+    assert!(err.contains("not a number"), "{}", err);
+}
+
+#[test]
+fn test_merge_qid_strip() {
+    let dir = std::env::temp_dir().join(format!("vindicator-test-qid-strip-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let a = dir.join("a.txt");
+    let b = dir.join("b.txt");
+    std::fs::write(&a, "301.1 0 d1 0 2 a\n301.2 0 d2 0 1 a\n301.2 0 d1 1 0.5 a\n302.1 0 d3 0 1 a\n").unwrap();
+    std::fs::write(&b, "301.2 0 d1 0 0.5 b\n302 0 d4 0 3 b\n").unwrap();
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    let out = run(&["merge", "-f", "combSUM", "--qid-strip", ".*", a, b]);
+    // d1 is under both sub-topics of 301 in a, but only counts once
+    let mnz = run(&["merge", "-f", "combMNZ", "--qid-strip", ".*", a, b]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        out,
+        "301 0 d1 0 2.5 vindicated\n\
+         301 0 d2 1 1 vindicated\n\
+         302 0 d4 0 3 vindicated\n\
+         302 0 d3 1 1 vindicated\n"
+    );
+    assert!(mnz.starts_with("301 0 d1 0 5 vindicated\n"), "{}", mnz);
+}
+
+#[test]
+fn test_merge_candidates() {
+    let path = std::env::temp_dir().join(format!("vindicator-test-candidates-{}.txt", std::process::id()));
+    std::fs::write(&path, "d3\n\nd1\n").unwrap();
+    let candidates = path.to_str().unwrap();
+
+    let out = run(&["merge", "-f", "combSUM", "--candidates", candidates, "mixed.a.txt", "mixed.b.csv"]);
+    std::fs::remove_file(&path).unwrap();
+
+    // d2 is not a candidate
+    assert_eq!(
+        out,
+        "301 0 d1 0 1 vindicated\n\
+         301 0 d3 1 0.25 vindicated\n"
+    );
+}
+
+#[test]
+fn test_merge_complete() {
+    let path = std::env::temp_dir().join(format!("vindicator-test-complete-{}.txt", std::process::id()));
+    std::fs::write(&path, "d9\nd1\nd8\n").unwrap();
+    let pool = path.to_str().unwrap();
+
+    let out = run(&["merge", "-f", "combSUM", "--complete", pool, "mixed.a.txt", "mixed.b.csv"]);
+    let out_input = run(&[
+        "merge", "-f", "combSUM", "--complete", pool, "--tie-break", "input", "mixed.a.txt", "mixed.b.csv",
+    ]);
+    std::fs::remove_file(&path).unwrap();
+
+    // d8 and d9 were not retrieved by any system
+    assert_eq!(
+        out,
+        "301 0 d2 0 1.25 vindicated\n\
+         301 0 d1 1 1 vindicated\n\
+         301 0 d3 2 0.25 vindicated\n\
+         301 0 d8 3 0 vindicated\n\
+         301 0 d9 4 0 vindicated\n"
+    );
+    assert!(out_input.ends_with("301 0 d9 3 0 vindicated\n301 0 d8 4 0 vindicated\n"));
+}
+
+#[test]
+fn test_merge_cutoffs() {
+    let args = |extra: &[&'static str]| {
+        let mut args = vec!["merge", "-f", "combSUM", "mixed.a.txt", "mixed.b.csv"];
+        args.extend(extra);
+        run(&args)
+    };
+    assert_eq!(
+        args(&["--top-k", "2"]),
+        "301 0 d2 0 1.25 vindicated\n\
+         301 0 d1 1 1 vindicated\n"
+    );
+    assert_eq!(args(&["--top-k", "2", "--min-score", "1.1"]), "301 0 d2 0 1.25 vindicated\n");
+    assert_eq!(args(&["--top-k", "1", "--min-score", "0.5"]), "301 0 d2 0 1.25 vindicated\n");
+    assert_eq!(args(&["--min-score", "0.5"]), args(&["--top-k", "2"]));
+}
+
+#[test]
+fn test_merge_skip_errors() {
+    let path = std::env::temp_dir().join(format!("vindicator-test-noisy-{}.txt", std::process::id()));
+    std::fs::write(&path, "301 0 d1 0 1 a\n301 0 d2 x 0.5 a\n301 0 d3 2 0.25 a\n").unwrap();
+    let noisy = path.to_str().unwrap();
+
+    let err = run_err(&["merge", "-f", "combSUM", noisy]);
+    let (out, warnings) = run_full(&["merge", "-f", "combSUM", "--skip-errors", noisy]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(err.contains("InvalidRank(\"x\")"), "unexpected error: {}", err);
+    assert_eq!(
+        out,
+        "301 0 d1 0 1 vindicated\n\
+         301 0 d3 1 0.25 vindicated\n"
+    );
+    assert!(warnings.contains(":2: failed to parse TREC data: invalid rank `x`"), "{}", warnings);
+    assert!(warnings.contains("skipped 1 malformed lines"), "{}", warnings);
+}
+
+#[test]
+fn test_merge_provenance() {
+    let path = std::env::temp_dir().join(format!("vindicator-test-provenance-{}.json", std::process::id()));
+    let provenance = path.to_str().unwrap();
+
+    let out = run(&["merge", "-f", "combSUM", "--provenance", provenance, "mixed.a.txt", "mixed.b.csv"]);
+    let sidecar = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // the run file itself is unchanged
+    assert_eq!(out, run(&["merge", "-f", "combSUM", "mixed.a.txt", "mixed.b.csv"]));
+    // d2 is retrieved by both systems
+    assert!(
+        sidecar.contains(
+            "{\"docno\": \"d2\", \"score\": 1.25, \"contributions\": [\
+             {\"source\": \"mixed.a.txt\", \"runid\": \"a\", \"rank\": 1, \"score\": 0.5}, \
+             {\"source\": \"mixed.b.csv\", \"runid\": \"b\", \"rank\": 0, \"score\": 0.75}]}"
+        ),
+        "unexpected provenance: {}",
+        sidecar
+    );
+    assert!(sidecar.starts_with("{\"301\": [{\"docno\": \"d2\""));
+}
+
+#[test]
+fn test_merge_distance_scores() {
+    // the closest document comes first
+    assert_eq!(
+        run(&["merge", "-f", "combSUM", "--distance-scores", "distances.txt"]),
+        "301 0 d1 0 1 vindicated\n\
+         301 0 d2 1 0.5 vindicated\n\
+         301 0 d3 2 0.25 vindicated\n"
+    );
+    // as opposed to taking distances as scores
+    assert!(run(&["merge", "-f", "combSUM", "distances.txt"]).starts_with("301 0 d3 0 3 "));
+    // negative scores are not distances
+    let err = run_err(&["merge", "-f", "combSUM", "--distance-scores", "negative.top.txt"]);
+    assert!(err.contains("negative distance"), "{}", err);
+}
+
+#[test]
+fn test_lint() {
+    let broken = "broken.txt: query 301: document `d1` appears more than once\n\
+                  broken.txt: query 301: expected rank 3, found 4\n\
+                  broken.txt: query 301: document `d3` scores higher than a document ranked before it\n";
+    // mixed.a.txt has no results for 303, which broken.txt has
+    assert_eq!(
+        run(&["lint", "broken.txt", "mixed.a.txt"]),
+        format!("{}mixed.a.txt: query 303: no results\n", broken)
+    );
+
+    let path = std::env::temp_dir().join(format!("vindicator-test-topics-{}.txt", std::process::id()));
+    std::fs::write(&path, "301\n302\n").unwrap();
+    let out = run(&["lint", "--topics", path.to_str().unwrap(), "broken.txt", "mixed.a.txt"]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        out,
+        format!("{}broken.txt: query 302: no results\nmixed.a.txt: query 302: no results\n", broken)
+    );
+}
+
+#[test]
+fn test_convert() {
+    let dir = std::env::temp_dir().join(format!("vindicator-test-convert-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let formats = [
+        ("trec", "run.txt", "301 0 d1 0 2.5 bm25\n301 0 d\"2 1 -0.125 bm25\n302 0 d3 0 1 bm25\n"),
+        (
+            "csv",
+            "run.csv",
+            "qid,docno,rank,score,runid\n301,d1,0,2.5,bm25\n301,d\"2,1,-0.125,bm25\n302,d3,0,1,bm25\n",
+        ),
+        (
+            "jsonl",
+            "run.jsonl",
+            r#"{"qid": "301", "docno": "d1", "rank": 0, "score": 2.5, "runid": "bm25"}
+{"qid": "301", "docno": "d\"2", "rank": 1, "score": -0.125, "runid": "bm25"}
+{"qid": "302", "docno": "d3", "rank": 0, "score": 1, "runid": "bm25"}
+"#,
+        ),
+    ];
+    for &(_, name, data) in &formats {
+        std::fs::write(dir.join(name), data).unwrap();
+    }
+
+    for &(from, name, _) in &formats {
+        let path = dir.join(name);
+        let path = path.to_str().unwrap();
+        for &(to, _, expected) in &formats {
+            let out = run(&["convert", "--to", to, path]);
+            assert_eq!(out, expected, "converting from {} to {}", from, to);
+        }
+    }
+
+    // with an explicit input format and an output file
+    let (input, output) = (dir.join("run.dat"), dir.join("run.out"));
+    std::fs::copy(dir.join("run.csv"), &input).unwrap();
+    run(&["convert", "--from", "csv", "--to", "jsonl", "-o", output.to_str().unwrap(), input.to_str().unwrap()]);
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), formats[2].2);
+
+    let spaced = dir.join("spaced.csv");
+    std::fs::write(&spaced, "301,d 1,0,1,bm25\n").unwrap();
+    let err = run_err(&["convert", "--to", "trec", spaced.to_str().unwrap()]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(err.contains("cannot be written to TREC"), "unexpected error: {}", err);
+}
+
+#[test]
+fn test_split() {
+    let dir = std::env::temp_dir().join(format!("vindicator-test-split-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("run.txt");
+    std::fs::write(&input, "302 0 d1 0 1 a\n301 0 d2 0 2 a\n302 0 d3 1 0.5 a\nq/3 0 d4 0 1 a\n").unwrap();
+    let output = dir.join("split");
+
+    run(&["split", input.to_str().unwrap(), "-o", output.to_str().unwrap()]);
+    let read = |name: &str| std::fs::read_to_string(output.join(name)).unwrap();
+    let mut names: Vec<_> = std::fs::read_dir(&output)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    let (a, b, c) = (read("301.txt"), read("302.txt"), read("q_3.txt"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(names, vec!["301.txt", "302.txt", "q_3.txt"]);
+    assert_eq!(a, "301 0 d2 0 2 a\n");
+    assert_eq!(b, "302 0 d1 0 1 a\n302 0 d3 1 0.5 a\n");
+    assert_eq!(c, "q/3 0 d4 0 1 a\n");
+}
+
+#[test]
+fn test_concat() {
+    let dir = std::env::temp_dir().join(format!("vindicator-test-concat-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let a = dir.join("a.txt");
+    let b = dir.join("b.txt");
+    std::fs::write(&a, "301 0 d1 0 2 fused\n301 0 d2 1 1 fused\n").unwrap();
+    std::fs::write(&b, "302 0 d3 0 4 fused\n").unwrap();
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    let out = run(&["concat", a, b]);
+    let err = run_err(&["concat", a, b, a]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(out, "301 0 d1 0 2 fused\n301 0 d2 1 1 fused\n302 0 d3 0 4 fused\n");
+    assert!(err.contains(&format!("query 301 appears in both {} and {}", a, a)), "{}", err);
+}
@@ -1,53 +1,187 @@
+//! Fusion tests against ground truth files in `tests/resources`.
+//!
+//! Each scenario `<name>` has an input file `<name>.top.txt` and one ground
+//! truth file `<name>.out.<method>.txt` per fusion method. Fused lists are
+//! compared regardless of the order of documents with equal scores.
+//!
+//! Run the tests with the environment variable `VINDICATOR_REGENERATE` set
+//! to write the ground truth files from the current implementation instead
+//! of checking against them. Regenerated files must be reviewed by hand.
+
+use approx::assert_abs_diff_eq;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use vindicator::{
+    trec::{self, parse_from_trec, TrecEntry},
+    EntryInfo, Rank, SearchEntry,
+};
+
+/// Environment variable for regenerating the ground truth files.
+const REGENERATE_VAR: &str = "VINDICATOR_REGENERATE";
+
+/// Query ID of the ground truth lists when fusing the whole input at once.
+const WHOLE_QID: &str = "multi";
+
+fn resource(file_name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/resources")
+        .join(file_name)
+}
+
+/// Sorts a fused list by descending score, then by document ID.
+fn canonical_order<I: Ord>(list: &mut [EntryInfo<I>]) {
+    list.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+}
+
+/// Checks the fusion of scenario `name` with a method against its ground
+/// truth file. If `by_query` is true, entries are fused separately for each
+/// query ID; otherwise, all entries are fused together.
+fn check_fusion<F>(name: &str, method: &str, by_query: bool, fuse: F)
+where
+    F: for<'a> Fn(&[TrecEntry<'a>]) -> Vec<EntryInfo<&'a str>>,
+{
+    let raw_data = std::fs::read_to_string(resource(&format!("{}.top.txt", name)))
+        .expect("could not read test input file");
+    let data = parse_from_trec(&raw_data).expect("could not parse test input file");
+
+    let mut groups: BTreeMap<&str, Vec<TrecEntry>> = BTreeMap::new();
+    for e in data {
+        let qid = if by_query { e.qid } else { WHOLE_QID };
+        groups.entry(qid).or_default().push(e);
+    }
+    let out: BTreeMap<&str, Vec<EntryInfo<&str>>> = groups
+        .iter()
+        .map(|(qid, entries)| {
+            let mut fused = fuse(entries);
+            canonical_order(&mut fused);
+            (*qid, fused)
+        })
+        .collect();
+
+    let gt_path = resource(&format!("{}.out.{}.txt", name, method));
+    if std::env::var_os(REGENERATE_VAR).is_some() {
+        let mut file = std::fs::File::create(&gt_path).expect("could not create ground truth file");
+        for (qid, list) in &out {
+            let list = list.iter().enumerate().map(|(i, e)| TrecEntry {
+                qid,
+                docno: e.id,
+                rank: i as Rank + 1,
+                score: e.score,
+                runid: name,
+            });
+            trec::write_all(&mut file, list).expect("could not write ground truth file");
+        }
+        return;
+    }
+
+    let raw_gt = std::fs::read_to_string(&gt_path).expect("could not read ground truth file");
+    let gt = parse_from_trec(&raw_gt).expect("could not parse ground truth file");
+    let mut gt_groups: BTreeMap<&str, Vec<EntryInfo<&str>>> = BTreeMap::new();
+    for e in gt {
+        gt_groups.entry(e.qid).or_default().push(e.to_entry());
+    }
+    for list in gt_groups.values_mut() {
+        canonical_order(list);
+    }
+
+    assert_eq!(
+        out.keys().collect::<Vec<_>>(),
+        gt_groups.keys().collect::<Vec<_>>(),
+        "unexpected query IDs for method {}",
+        method
+    );
+    for (qid, list) in &out {
+        assert_abs_diff_eq!(&**list, &*gt_groups[qid]);
+    }
+}
+
+/// Splits entries into one list per run ID, in order of first appearance.
+fn by_run<'e, 'a>(data: &'e [TrecEntry<'a>]) -> Vec<Vec<&'e TrecEntry<'a>>> {
+    let mut runs: Vec<Vec<&TrecEntry>> = Vec::new();
+    for e in data {
+        match runs.iter_mut().find(|run| run[0].runid == e.runid) {
+            Some(run) => run.push(e),
+            None => runs.push(vec![e]),
+        }
+    }
+    runs
+}
+
+/// The number of distinct documents in the entries.
+fn num_candidates(data: &[TrecEntry]) -> Rank {
+    data.iter().map(|e| e.docno).collect::<BTreeSet<_>>().len() as Rank
+}
 
 macro_rules! test_fusion {
-    ($name: ident) => {
+    ($name: ident, $by_query: expr) => {
         mod $name {
-            use approx::assert_abs_diff_eq;
-            use vindicator::{
-                SearchEntry,
-                fuser::{comb_max, comb_mnz, comb_sum, fuse_scored},
-                trec::parse_from_trec,
+            use super::{by_run, check_fusion, num_candidates};
+            use vindicator::fuser::{
+                borda, comb_avg, comb_huber, comb_max, comb_mnz, comb_robust, comb_sum, fuse_ranked,
+                fuse_ranked_multi, fuse_scored, rrf, Unretrieved,
             };
 
             #[test]
             fn test_comb_max() {
-                let raw_data = include_str!(concat!("resources/", stringify!($name), ".top.txt"));
-                let data = parse_from_trec(raw_data).expect("could not parse test input file");
-
-                let out = fuse_scored(&data, comb_max);
-                let raw_gt = include_str!(concat!("resources/", stringify!($name), ".out.max.txt"));
-                let gt =
-                    parse_from_trec(raw_gt).expect("could not parse comMAX test ground truth file");
-                let gt: Vec<_> = gt.into_iter().map(|e| e.to_entry()).collect();
-                assert_abs_diff_eq!(&*out, &*gt);
+                check_fusion(stringify!($name), "max", $by_query, |data| {
+                    fuse_scored(data, comb_max)
+                });
             }
 
             #[test]
             fn test_comb_sum() {
-                let raw_data = include_str!(concat!("resources/", stringify!($name), ".top.txt"));
-                let data = parse_from_trec(raw_data).expect("could not parse test input file");
-
-                let out = fuse_scored(&data, comb_sum);
-                let raw_gt = include_str!(concat!("resources/", stringify!($name), ".out.sum.txt"));
-                let gt =
-                    parse_from_trec(raw_gt).expect("could not parse combSUM test ground truth file");
-                let gt: Vec<_> = gt.into_iter().map(|e| e.to_entry()).collect();
-                assert_abs_diff_eq!(&*out, &*gt);
+                check_fusion(stringify!($name), "sum", $by_query, |data| {
+                    fuse_scored(data, comb_sum)
+                });
             }
 
             #[test]
             fn test_comb_mnz() {
-                let raw_data = include_str!(concat!("resources/", stringify!($name), ".top.txt"));
-                let data = parse_from_trec(raw_data).expect("could not parse test input file");
+                check_fusion(stringify!($name), "mnz", $by_query, |data| {
+                    fuse_scored(data, comb_mnz)
+                });
+            }
 
-                let out = fuse_scored(&data, comb_mnz);
-                let raw_gt = include_str!(concat!("resources/", stringify!($name), ".out.mnz.txt"));
-                let gt = parse_from_trec(raw_gt).expect("could not parse test ground truth file");
-                let gt: Vec<_> = gt.into_iter().map(|e| e.to_entry()).collect();
-                assert_abs_diff_eq!(&*out, &*gt);
+            #[test]
+            fn test_comb_avg() {
+                check_fusion(stringify!($name), "avg", $by_query, |data| {
+                    fuse_scored(data, comb_avg)
+                });
+            }
+
+            #[test]
+            fn test_comb_robust() {
+                check_fusion(stringify!($name), "robust", $by_query, |data| {
+                    fuse_scored(data, comb_robust)
+                });
+            }
+
+            #[test]
+            fn test_comb_huber() {
+                check_fusion(stringify!($name), "huber", $by_query, |data| {
+                    fuse_scored(data, comb_huber)
+                });
+            }
+
+            #[test]
+            fn test_rrf() {
+                check_fusion(stringify!($name), "rrf", $by_query, |data| {
+                    fuse_ranked(data, rrf)
+                });
+            }
+
+            #[test]
+            fn test_borda() {
+                check_fusion(stringify!($name), "borda", $by_query, |data| {
+                    let fuser = borda(num_candidates(data));
+                    fuse_ranked_multi(by_run(data), fuser, Unretrieved::AfterLast)
+                });
             }
         }
     };
 }
 
-test_fusion!(test1);
+test_fusion!(test1, false);
+test_fusion!(ties, false);
+test_fusion!(negative, false);
+test_fusion!(multiquery, true);
@@ -0,0 +1,89 @@
+//! Randomized property tests: fusion is a function of each document's
+//! scores (or ranks), regardless of the order of the entries in the input.
+use approx::assert_abs_diff_eq;
+use std::collections::HashMap;
+use vindicator::fuser::{available_fusers, fuse_ranked, rank_fuser, score_fuser};
+use vindicator::rng::Rng;
+use vindicator::{fuse_scored, score, EntryInfo, Rank, RankedEntryInfo};
+
+/// Number of random inputs tested per fusion algorithm.
+const CASES: u64 = 200;
+
+/// Generates a random combination of result lists. Document IDs are drawn
+/// from a small pool, so that documents are often retrieved more than once,
+/// and scores from a small set of values, so that ties are common.
+fn random_entries(rng: &mut Rng) -> Vec<RankedEntryInfo<u32>> {
+    let num_lists = 1 + rng.below(4);
+    let mut entries = Vec::new();
+    for _ in 0..num_lists {
+        let len = rng.below(20);
+        for rank in 0..len {
+            entries.push(RankedEntryInfo {
+                id: rng.below(30) as u32,
+                score: score(rng.below(16) as f32 / 4. - 1.),
+                rank: rank as Rank,
+            });
+        }
+    }
+    entries
+}
+
+/// Maps each document ID of a fused list to its score, checking that each
+/// document appears only once and that the list is sorted by score.
+fn scores_by_id(fused: &[EntryInfo<u32>]) -> HashMap<u32, f32> {
+    for w in fused.windows(2) {
+        assert!(w[0].score >= w[1].score, "fused list is not sorted by score");
+    }
+    let scores: HashMap<_, _> = fused.iter().map(|e| (e.id, e.score.raw())).collect();
+    assert_eq!(scores.len(), fused.len(), "fused list has duplicate documents");
+    scores
+}
+
+fn assert_same_fusion(a: &[EntryInfo<u32>], b: &[EntryInfo<u32>]) {
+    let (a, b) = (scores_by_id(a), scores_by_id(b));
+    assert_eq!(a.len(), b.len());
+    for (id, score) in &a {
+        let other = b.get(id).unwrap_or_else(|| panic!("document {} is missing", id));
+        assert_abs_diff_eq!(score, other, epsilon = 1e-4);
+    }
+}
+
+#[test]
+fn test_score_fusion_order_independent() {
+    for name in available_fusers() {
+        let fuser = match score_fuser(name) {
+            Some(fuser) => fuser,
+            None => continue,
+        };
+        let mut rng = Rng::new(1);
+        for _ in 0..CASES {
+            let entries = random_entries(&mut rng);
+            let mut shuffled = entries.clone();
+            rng.shuffle(&mut shuffled);
+
+            let fused = fuse_scored(&entries, fuser);
+            let fused_shuffled = fuse_scored(&shuffled, fuser);
+            assert_same_fusion(&fused, &fused_shuffled);
+        }
+    }
+}
+
+#[test]
+fn test_rank_fusion_order_independent() {
+    for name in available_fusers() {
+        let fuser = match rank_fuser(name) {
+            Some(fuser) => fuser,
+            None => continue,
+        };
+        let mut rng = Rng::new(2);
+        for _ in 0..CASES {
+            let entries = random_entries(&mut rng);
+            let mut shuffled = entries.clone();
+            rng.shuffle(&mut shuffled);
+
+            let fused = fuse_ranked(&entries, fuser);
+            let fused_shuffled = fuse_ranked(&shuffled, fuser);
+            assert_same_fusion(&fused, &fused_shuffled);
+        }
+    }
+}